@@ -225,7 +225,7 @@ async fn run_demo() -> hsipc::Result<()> {
 
     // 2. Test sync method
     println!("✅ Testing sync method...");
-    let multiply_result = client.multiply(6, 7)?;
+    let multiply_result = client.multiply(6, 7).await?;
     println!("   Multiply result: {multiply_result}");
     assert_eq!(multiply_result, 42);
 
@@ -308,7 +308,7 @@ async fn run_client() -> hsipc::Result<()> {
         .await?;
     println!("Remote add: {}", result.result);
 
-    let multiply_result = client.multiply(12, 13)?;
+    let multiply_result = client.multiply(12, 13).await?;
     println!("Remote multiply: {multiply_result}");
 
     let status = client.get_status().await?;
@@ -0,0 +1,363 @@
+//! Attribute argument parsing shared by the hsipc macros
+//!
+//! Every attribute is parsed with `syn`, so unknown keys, values of the wrong
+//! type and repeated keys are reported as compile errors at their span.
+
+use proc_macro2::TokenStream;
+use syn::meta::ParseNestedMeta;
+use syn::parse::{ParseStream, Parser};
+use syn::spanned::Spanned;
+use syn::{Attribute, Expr, Ident, LitInt, LitStr, Token, Type};
+
+/// Store a parsed value, rejecting a key that was already given
+fn set_once<T>(slot: &mut Option<T>, meta: &ParseNestedMeta, value: T) -> syn::Result<()> {
+    if slot.is_some() {
+        return Err(meta.error(format!("duplicate `{}`", path_name(meta))));
+    }
+    *slot = Some(value);
+    Ok(())
+}
+
+/// Set a flag, rejecting a flag that was already given
+fn set_flag(flag: &mut bool, meta: &ParseNestedMeta) -> syn::Result<()> {
+    if *flag {
+        return Err(meta.error(format!("duplicate `{}`", path_name(meta))));
+    }
+    if !meta.input.is_empty() && !meta.input.peek(Token![,]) {
+        return Err(meta.error(format!("`{}` takes no value", path_name(meta))));
+    }
+    *flag = true;
+    Ok(())
+}
+
+fn path_name(meta: &ParseNestedMeta) -> String {
+    meta.path
+        .get_ident()
+        .map(Ident::to_string)
+        .unwrap_or_else(|| "argument".to_string())
+}
+
+fn unknown(meta: &ParseNestedMeta, expected: &str) -> syn::Error {
+    meta.error(format!(
+        "unknown argument `{}`, expected {expected}",
+        path_name(meta)
+    ))
+}
+
+/// Arguments of `#[rpc(server, client, client_trait, mock, namespace = "...", sync)]`
+#[derive(Default)]
+pub struct RpcArgs {
+    pub server: bool,
+    pub client: bool,
+    /// The generated client implements the trait itself
+    pub client_trait: bool,
+    /// Generate a `MockXxx` implementing the trait from expectations
+    pub mock: bool,
+    /// Every trait method is synchronous
+    pub sync: bool,
+    pub namespace: Option<LitStr>,
+}
+
+impl RpcArgs {
+    pub fn parse(args: TokenStream) -> syn::Result<Self> {
+        let mut parsed = Self::default();
+        let parser = syn::meta::parser(|meta| {
+            if meta.path.is_ident("server") {
+                set_flag(&mut parsed.server, &meta)
+            } else if meta.path.is_ident("client") {
+                set_flag(&mut parsed.client, &meta)
+            } else if meta.path.is_ident("client_trait") {
+                set_flag(&mut parsed.client_trait, &meta)
+            } else if meta.path.is_ident("mock") {
+                set_flag(&mut parsed.mock, &meta)
+            } else if meta.path.is_ident("sync") {
+                set_flag(&mut parsed.sync, &meta)
+            } else if meta.path.is_ident("namespace") {
+                let namespace: LitStr = meta.value()?.parse()?;
+                set_once(&mut parsed.namespace, &meta, namespace)
+            } else {
+                Err(unknown(
+                    &meta,
+                    "`server`, `client`, `client_trait`, `mock`, `sync` or `namespace`",
+                ))
+            }
+        });
+        parser.parse2(args)?;
+        Ok(parsed)
+    }
+}
+
+/// Whether a trait method is a plain RPC method or a subscription
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MethodKind {
+    Method,
+    Subscription,
+}
+
+/// Arguments of `#[method(...)]` or `#[subscription(...)]` on a trait method
+pub struct MethodArgs {
+    pub kind: MethodKind,
+    /// RPC name, defaulting to the method name
+    pub name: String,
+    /// `#[method(sync)]`, checked against the method's signature
+    pub sync: bool,
+    /// `#[method(timeout = ms)]`, the generated clients' call timeout
+    pub timeout_ms: Option<u64>,
+    /// `#[subscription(item = Type)]`, checked to be sendable as a subscription item
+    pub item: Option<Type>,
+}
+
+impl MethodArgs {
+    /// Parse the method's `#[method]` or `#[subscription]` attribute
+    ///
+    /// Methods without either attribute are plain RPC methods.
+    pub fn parse(attrs: &[Attribute], default_name: &str) -> syn::Result<Self> {
+        let mut parsed = Self {
+            kind: MethodKind::Method,
+            name: default_name.to_string(),
+            sync: false,
+            timeout_ms: None,
+            item: None,
+        };
+
+        let mut seen: Option<&Attribute> = None;
+        for attr in attrs {
+            let kind = if attr.path().is_ident("method") {
+                MethodKind::Method
+            } else if attr.path().is_ident("subscription") {
+                MethodKind::Subscription
+            } else {
+                continue;
+            };
+            if let Some(previous) = seen {
+                let message = if previous.path() == attr.path() {
+                    "duplicate attribute"
+                } else {
+                    "a method cannot be both `#[method]` and `#[subscription]`"
+                };
+                return Err(syn::Error::new(attr.span(), message));
+            }
+            seen = Some(attr);
+            parsed.kind = kind;
+
+            if matches!(attr.meta, syn::Meta::Path(_)) {
+                continue;
+            }
+            let mut name: Option<LitStr> = None;
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("name") {
+                    let value = meta.value()?;
+                    let lit: LitStr = value.parse()?;
+                    if kind == MethodKind::Subscription && value.peek(Token![=>]) {
+                        let arrow = value.parse::<Token![=>]>()?;
+                        return Err(syn::Error::new(
+                            arrow.span(),
+                            "subscription modes are not supported, remove `=> \"...\"`",
+                        ));
+                    }
+                    set_once(&mut name, &meta, lit)
+                } else if kind == MethodKind::Method && meta.path.is_ident("sync") {
+                    set_flag(&mut parsed.sync, &meta)
+                } else if kind == MethodKind::Method && meta.path.is_ident("timeout") {
+                    let timeout: LitInt = meta.value()?.parse()?;
+                    let millis = timeout.base10_parse::<u64>()?;
+                    set_once(&mut parsed.timeout_ms, &meta, millis)
+                } else if kind == MethodKind::Subscription && meta.path.is_ident("item") {
+                    let item: Type = meta.value()?.parse()?;
+                    set_once(&mut parsed.item, &meta, item)
+                } else if kind == MethodKind::Method {
+                    Err(unknown(&meta, "`name`, `sync` or `timeout`"))
+                } else {
+                    Err(unknown(&meta, "`name` or `item`"))
+                }
+            })?;
+            if let Some(name) = name {
+                parsed.name = name.value();
+            }
+        }
+
+        Ok(parsed)
+    }
+}
+
+/// Arguments of `#[event(topic = "...", version = N)]`
+#[derive(Default)]
+pub struct EventArgs {
+    pub topic: Option<LitStr>,
+    pub version: Option<u32>,
+}
+
+impl EventArgs {
+    /// Parse the `#[event]` attributes of a type, or of an enum variant when
+    /// `allow_version` is false
+    pub fn parse(attrs: &[Attribute], allow_version: bool) -> syn::Result<Self> {
+        let mut parsed = Self::default();
+        for attr in attrs.iter().filter(|attr| attr.path().is_ident("event")) {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("topic") {
+                    let topic: LitStr = meta.value()?.parse()?;
+                    set_once(&mut parsed.topic, &meta, topic)
+                } else if allow_version && meta.path.is_ident("version") {
+                    let version = meta
+                        .value()?
+                        .parse::<LitInt>()
+                        .map_err(|err| {
+                            syn::Error::new(err.span(), "expected an integer schema version")
+                        })?
+                        .base10_parse::<u32>()?;
+                    set_once(&mut parsed.version, &meta, version)
+                } else if meta.path.is_ident("version") {
+                    Err(meta.error("`version` is set on the event type, not on a variant"))
+                } else if allow_version {
+                    Err(unknown(&meta, "`topic` or `version`"))
+                } else {
+                    Err(unknown(&meta, "`topic`"))
+                }
+            })?;
+        }
+        Ok(parsed)
+    }
+}
+
+/// Arguments of `#[subscribe("pattern")]` or `#[subscribe(topic = pattern)]`
+///
+/// The pattern is any `&str` expression, such as `DeviceStatus::PATTERN`.
+pub struct SubscribeArgs {
+    pub topic: Option<Expr>,
+}
+
+impl SubscribeArgs {
+    pub fn parse(args: TokenStream) -> syn::Result<Self> {
+        let parser = |input: ParseStream| {
+            if input.is_empty() {
+                return Ok(Self { topic: None });
+            }
+
+            let topic = if input.peek(Ident) && input.peek2(Token![=]) && !input.peek2(Token![==]) {
+                let key: Ident = input.parse()?;
+                if key != "topic" {
+                    return Err(syn::Error::new(
+                        key.span(),
+                        format!("unknown argument `{key}`, expected `topic`"),
+                    ));
+                }
+                input.parse::<Token![=]>()?;
+                input.parse::<Expr>()?
+            } else {
+                input.parse::<Expr>()?
+            };
+
+            if !input.is_empty() {
+                let rest: TokenStream = input.parse()?;
+                return Err(syn::Error::new(
+                    rest.span(),
+                    "expected a single topic pattern",
+                ));
+            }
+            Ok(Self { topic: Some(topic) })
+        };
+        parser.parse2(args)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quote::quote;
+
+    fn error(result: syn::Result<impl Sized>) -> String {
+        match result {
+            Ok(_) => panic!("expected an error"),
+            Err(err) => err.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_rpc_args() {
+        let args = RpcArgs::parse(quote! { server, client, namespace = "server_admin" }).unwrap();
+        assert!(args.server && args.client && !args.sync);
+        assert_eq!(args.namespace.unwrap().value(), "server_admin");
+
+        // A namespace mentioning "server" no longer turns on the flag
+        let args = RpcArgs::parse(quote! { client, client_trait, namespace = "server" }).unwrap();
+        assert!(!args.server && args.client_trait && !args.mock);
+
+        let args = RpcArgs::parse(quote! { server, mock }).unwrap();
+        assert!(args.server && args.mock);
+
+        assert!(error(RpcArgs::parse(quote! { servr })).contains("unknown argument `servr`"));
+        assert!(error(RpcArgs::parse(quote! { server, server })).contains("duplicate `server`"));
+        assert!(error(RpcArgs::parse(quote! { namespace = 5 })).contains("expected string literal"));
+    }
+
+    #[test]
+    fn test_method_args() {
+        let attrs: Vec<Attribute> = vec![syn::parse_quote!(#[method(name = "add", timeout = 500)])];
+        let args = MethodArgs::parse(&attrs, "add_numbers").unwrap();
+        assert_eq!(args.kind, MethodKind::Method);
+        assert_eq!(args.name, "add");
+        assert_eq!(args.timeout_ms, Some(500));
+
+        let attrs: Vec<Attribute> =
+            vec![syn::parse_quote!(#[subscription(name = "events", item = Event<T>)])];
+        let args = MethodArgs::parse(&attrs, "subscribe_events").unwrap();
+        assert_eq!(args.kind, MethodKind::Subscription);
+        assert_eq!(args.name, "events");
+        assert!(args.item.is_some());
+
+        let attrs: Vec<Attribute> =
+            vec![syn::parse_quote!(#[subscription(name = "events" => "once")])];
+        assert!(error(MethodArgs::parse(&attrs, "events")).contains("modes are not supported"));
+
+        let args = MethodArgs::parse(&[], "status").unwrap();
+        assert_eq!(
+            (args.kind, args.name.as_str()),
+            (MethodKind::Method, "status")
+        );
+
+        let attrs: Vec<Attribute> = vec![syn::parse_quote!(#[method(nmae = "add")])];
+        assert!(error(MethodArgs::parse(&attrs, "add")).contains("unknown argument `nmae`"));
+
+        let attrs: Vec<Attribute> = vec![syn::parse_quote!(#[method(timeout = "5s")])];
+        assert!(error(MethodArgs::parse(&attrs, "add")).contains("expected integer literal"));
+
+        let attrs: Vec<Attribute> = vec![
+            syn::parse_quote!(#[method(name = "add")]),
+            syn::parse_quote!(#[subscription(name = "add")]),
+        ];
+        assert!(error(MethodArgs::parse(&attrs, "add")).contains("cannot be both"));
+    }
+
+    #[test]
+    fn test_event_args() {
+        let attrs: Vec<Attribute> = vec![syn::parse_quote!(#[event(topic = "a/b", version = 2)])];
+        let args = EventArgs::parse(&attrs, true).unwrap();
+        assert_eq!(args.topic.unwrap().value(), "a/b");
+        assert_eq!(args.version, Some(2));
+
+        let attrs: Vec<Attribute> = vec![syn::parse_quote!(#[event(topic = "a", topic = "b")])];
+        assert!(error(EventArgs::parse(&attrs, true)).contains("duplicate `topic`"));
+
+        let attrs: Vec<Attribute> = vec![syn::parse_quote!(#[event(version = 2)])];
+        assert!(error(EventArgs::parse(&attrs, false)).contains("not on a variant"));
+
+        let attrs: Vec<Attribute> = vec![syn::parse_quote!(#[event(topc = "a")])];
+        assert!(error(EventArgs::parse(&attrs, true)).contains("unknown argument `topc`"));
+    }
+
+    #[test]
+    fn test_subscribe_args() {
+        assert!(SubscribeArgs::parse(quote! {}).unwrap().topic.is_none());
+        assert!(SubscribeArgs::parse(quote! { "a/+" })
+            .unwrap()
+            .topic
+            .is_some());
+        assert!(SubscribeArgs::parse(quote! { topic = Status::PATTERN })
+            .unwrap()
+            .topic
+            .is_some());
+
+        assert!(error(SubscribeArgs::parse(quote! { pattern = "a" })).contains("unknown argument"));
+        assert!(error(SubscribeArgs::parse(quote! { "a", "b" })).contains("single topic"));
+    }
+}
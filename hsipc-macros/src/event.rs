@@ -1,132 +1,555 @@
 //! Event derive macro implementation
 
+use crate::attrs::EventArgs;
 use proc_macro::TokenStream;
-use quote::quote;
-use syn::{Attribute, Data, DeriveInput, Fields, Meta};
+use quote::{format_ident, quote};
+use syn::{Data, DeriveInput, Fields, Ident, LitStr};
 
 /// Implementation of the Event derive macro
 pub fn derive_event_impl(input: DeriveInput) -> TokenStream {
     let name = &input.ident;
     let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
 
-    // Parse the #[event] attribute to get the topic
-    let topic = parse_event_topic(&input.attrs);
-
-    let topic_impl = match topic {
-        Some(topic) => {
-            if topic.contains('{') {
-                // Dynamic topic with field interpolation
-                generate_dynamic_topic(name, &input.data, &topic)
-            } else {
-                // Static topic
-                quote! {
-                    fn topic(&self) -> String {
-                        #topic.to_string()
-                    }
-                }
-            }
-        }
-        None => {
-            // Default topic based on type name
-            let default_topic = format!("events/{}", name.to_string().to_lowercase());
-            quote! {
-                fn topic(&self) -> String {
-                    #default_topic.to_string()
-                }
+    let args = match EventArgs::parse(&input.attrs, true) {
+        Ok(args) => args,
+        Err(err) => return err.to_compile_error().into(),
+    };
+
+    // Use the #[event] topic, defaulting to one based on the type name
+    let topic = args.topic.unwrap_or_else(|| {
+        let default_topic = format!("events/{}", name.to_string().to_lowercase());
+        LitStr::new(&default_topic, name.span())
+    });
+
+    let topic_impl = match generate_topic(&input.data, &topic) {
+        Ok(body) => quote! {
+            #[allow(unused_variables)]
+            fn topic(&self) -> String {
+                #body
             }
-        }
+        },
+        Err(err) => return err.to_compile_error().into(),
+    };
+
+    let version_impl = match args.version {
+        Some(version) => quote! {
+            const VERSION: u32 = #version;
+        },
+        None => quote! {},
+    };
+
+    // Struct topics with placeholders also get a subscription pattern, a builder and a parser
+    let helpers = match &input.data {
+        Data::Struct(_) => match generate_topic_helpers(&input, &topic) {
+            Ok(helpers) => helpers,
+            Err(err) => return err.to_compile_error().into(),
+        },
+        _ => quote! {},
     };
 
     let expanded = quote! {
         impl #impl_generics ::hsipc::Event for #name #ty_generics #where_clause {
+            #version_impl
             #topic_impl
         }
+
+        #helpers
     };
 
     TokenStream::from(expanded)
 }
 
-/// Parse the topic from #[event(topic = "...")] attribute
-fn parse_event_topic(attrs: &[Attribute]) -> Option<String> {
-    for attr in attrs {
-        if attr.path().is_ident("event") {
-            // Try to parse meta
-            let meta = &attr.meta;
-            if let Meta::List(list) = meta {
-                // Simple string parsing from tokens
-                let tokens = list.tokens.to_string();
-                if let Some(start) = tokens.find("topic") {
-                    if let Some(quote_start) = tokens[start..].find('"') {
-                        if let Some(quote_end) = tokens[start + quote_start + 1..].find('"') {
-                            let topic = &tokens
-                                [start + quote_start + 1..start + quote_start + 1 + quote_end];
-                            return Some(topic.to_string());
+/// A piece of a topic pattern
+enum TopicPiece {
+    /// Text copied into the topic as-is
+    Literal(String),
+    /// A `{field}`, `{0}` or `{device.id}` placeholder, split on `.`
+    Field(Vec<String>),
+}
+
+/// Split a topic pattern like "device/{device_id}/status" into pieces
+fn parse_topic_pattern(topic: &LitStr) -> syn::Result<Vec<TopicPiece>> {
+    let pattern = topic.value();
+    let mut pieces = Vec::new();
+    let mut literal = String::new();
+    let mut chars = pattern.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '{' => {
+                let mut placeholder = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => placeholder.push(c),
+                        None => {
+                            return Err(syn::Error::new(
+                                topic.span(),
+                                format!("unclosed placeholder `{{{placeholder}` in topic"),
+                            ))
                         }
                     }
                 }
+
+                let path: Vec<String> = placeholder.split('.').map(str::to_string).collect();
+                let valid = path.iter().all(|segment| {
+                    segment.parse::<usize>().is_ok() || syn::parse_str::<Ident>(segment).is_ok()
+                });
+                if !valid {
+                    return Err(syn::Error::new(
+                        topic.span(),
+                        format!(
+                            "invalid placeholder `{{{placeholder}}}` in topic, expected a field name, tuple index or path like `{{device.id}}`"
+                        ),
+                    ));
+                }
+
+                if !literal.is_empty() {
+                    pieces.push(TopicPiece::Literal(std::mem::take(&mut literal)));
+                }
+                pieces.push(TopicPiece::Field(path));
             }
+            '}' => return Err(syn::Error::new(topic.span(), "unmatched `}` in topic")),
+            c => literal.push(c),
         }
     }
-    None
+
+    if !literal.is_empty() {
+        pieces.push(TopicPiece::Literal(literal));
+    }
+    Ok(pieces)
 }
 
-/// Generate dynamic topic implementation for patterns like "device/{device_id}/status"
-fn generate_dynamic_topic(
-    _name: &syn::Ident,
-    data: &Data,
-    topic_pattern: &str,
-) -> proc_macro2::TokenStream {
-    match data {
-        Data::Struct(data_struct) => {
-            match &data_struct.fields {
-                Fields::Named(fields) => {
-                    let topic_code = topic_pattern.to_string();
-                    let mut replacements = Vec::new();
-
-                    // Find all {field_name} patterns in the topic
-                    for field in &fields.named {
-                        if let Some(field_name) = &field.ident {
-                            let field_name_str = field_name.to_string();
-                            let pattern = format!("{{{field_name_str}}}");
-
-                            if topic_code.contains(&pattern) {
-                                replacements.push((pattern, field_name));
-                            }
-                        }
-                    }
+/// Name of the `topic_for` parameter and `parse_topic` field for a placeholder
+fn placeholder_name(path: &[String]) -> Ident {
+    let name = path
+        .iter()
+        .map(|segment| match segment.parse::<usize>() {
+            Ok(index) => format!("field{index}"),
+            Err(_) => segment.clone(),
+        })
+        .collect::<Vec<_>>()
+        .join("_");
+    format_ident!("{}", name)
+}
+
+/// Generate `PATTERN`, `topic_for` and `parse_topic` for a struct event
+///
+/// `PATTERN` replaces every topic level holding a placeholder with `+`.
+/// `parse_topic` returns the placeholder values of a matching topic as strings
+/// in a `{Name}Topic` struct. Topics without placeholders get no helpers, as
+/// the topic itself already is the pattern.
+fn generate_topic_helpers(
+    input: &DeriveInput,
+    topic: &LitStr,
+) -> syn::Result<proc_macro2::TokenStream> {
+    let name = &input.ident;
+    let vis = &input.vis;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let fields_name = format_ident!("{}Topic", name);
 
-                    // Generate replacement code
-                    let mut format_string = topic_code.clone();
-                    let mut format_args = Vec::new();
+    // Split the pattern into levels, keeping each level's pieces
+    let mut levels: Vec<Vec<&TopicPiece>> = vec![Vec::new()];
+    let pieces = parse_topic_pattern(topic)?;
+    if !pieces
+        .iter()
+        .any(|piece| matches!(piece, TopicPiece::Field(_)))
+    {
+        return Ok(quote! {});
+    }
+    let split_literals: Vec<TopicPiece> = pieces
+        .iter()
+        .flat_map(|piece| match piece {
+            TopicPiece::Literal(text) => text
+                .split('/')
+                .enumerate()
+                .flat_map(|(i, part)| {
+                    let separator = (i > 0).then(|| TopicPiece::Literal("/".to_string()));
+                    let part = (!part.is_empty()).then(|| TopicPiece::Literal(part.to_string()));
+                    separator.into_iter().chain(part)
+                })
+                .collect::<Vec<_>>(),
+            TopicPiece::Field(path) => vec![TopicPiece::Field(path.clone())],
+        })
+        .collect();
+    for piece in &split_literals {
+        match piece {
+            TopicPiece::Literal(text) if text == "/" => levels.push(Vec::new()),
+            piece => levels.last_mut().unwrap().push(piece),
+        }
+    }
 
-                    for (pattern, field_name) in replacements {
-                        format_string = format_string.replace(&pattern, "{}");
-                        format_args.push(quote! { &self.#field_name });
-                    }
+    let mut pattern_levels = Vec::new();
+    let mut params: Vec<Ident> = Vec::new();
+    let mut param_paths: Vec<String> = Vec::new();
+    let mut level_matchers = Vec::new();
+    for (i, level) in levels.iter().enumerate() {
+        let level_var = format_ident!("__level{}", i);
+        let has_field = level
+            .iter()
+            .any(|piece| matches!(piece, TopicPiece::Field(_)));
+        if !has_field {
+            let text: String = level
+                .iter()
+                .map(|piece| match piece {
+                    TopicPiece::Literal(text) => text.as_str(),
+                    TopicPiece::Field(_) => unreachable!(),
+                })
+                .collect();
+            level_matchers.push(quote! {
+                if #level_var != #text {
+                    return None;
+                }
+            });
+            pattern_levels.push(text);
+            continue;
+        }
+        pattern_levels.push("+".to_string());
 
-                    quote! {
-                        fn topic(&self) -> String {
-                            format!(#format_string, #(#format_args),*)
+        // Match the level piece by piece, each placeholder ending at the next literal
+        let mut steps = vec![quote! { let __rest = #level_var; }];
+        for (j, piece) in level.iter().enumerate() {
+            match piece {
+                TopicPiece::Literal(text) if j == 0 => steps.push(quote! {
+                    let __rest = __rest.strip_prefix(#text)?;
+                }),
+                TopicPiece::Literal(_) => {}
+                TopicPiece::Field(path) => {
+                    let param = placeholder_name(path);
+                    let value = format_ident!("__{}", param);
+                    match level.get(j + 1) {
+                        Some(TopicPiece::Literal(next)) if j + 2 == level.len() => {
+                            steps.push(quote! {
+                                let #value = __rest.strip_suffix(#next)?;
+                            })
                         }
+                        Some(TopicPiece::Literal(next)) => steps.push(quote! {
+                            let (#value, __rest) = __rest.split_once(#next)?;
+                        }),
+                        Some(TopicPiece::Field(_)) => return Err(syn::Error::new(
+                            topic.span(),
+                            "adjacent topic placeholders cannot be parsed, separate them with text",
+                        )),
+                        None => steps.push(quote! {
+                            let #value = __rest;
+                        }),
                     }
-                }
-                _ => {
-                    // For other field types, just return the pattern as-is
-                    quote! {
-                        fn topic(&self) -> String {
-                            #topic_pattern.to_string()
+
+                    // A repeated placeholder must hold the same value each time, but
+                    // two different placeholders must not share a name
+                    let path = path.join(".");
+                    if let Some(k) = params.iter().position(|known| *known == param) {
+                        if param_paths[k] != path {
+                            return Err(syn::Error::new(
+                                topic.span(),
+                                format!(
+                                    "topic placeholders `{{{}}}` and `{{{path}}}` both become `{param}` in `topic_for` and `parse_topic`, rename one of the fields",
+                                    param_paths[k]
+                                ),
+                            ));
                         }
+                        steps.push(quote! {
+                            if #value != #param {
+                                return None;
+                            }
+                        });
+                    } else {
+                        steps.push(quote! {
+                            let #param = #value;
+                        });
+                        params.push(param);
+                        param_paths.push(path);
                     }
                 }
             }
         }
-        _ => {
-            // For enums and other types, just return the pattern as-is
-            quote! {
-                fn topic(&self) -> String {
-                    #topic_pattern.to_string()
+        level_matchers.push(quote! { #(#steps)* });
+    }
+
+    let pattern = pattern_levels.join("/");
+    let level_count = levels.len();
+    let level_vars: Vec<Ident> = (0..level_count)
+        .map(|i| format_ident!("__level{}", i))
+        .collect();
+    let param_docs = params.iter().map(|param| {
+        let doc = format!(" Value of `{param}` in the topic");
+        quote! { #[doc = #doc] }
+    });
+    let bindings: Vec<(String, proc_macro2::TokenStream)> = params
+        .iter()
+        .map(|param| (param.to_string(), quote! { #param }))
+        .collect();
+    let renamed: Vec<TopicPiece> = pieces
+        .iter()
+        .map(|piece| match piece {
+            TopicPiece::Literal(text) => TopicPiece::Literal(text.clone()),
+            TopicPiece::Field(path) => TopicPiece::Field(vec![placeholder_name(path).to_string()]),
+        })
+        .collect();
+    let topic_body = format_topic(topic, &renamed, &bindings)?;
+    let fields_doc = format!(" Placeholder values parsed from a [`{name}`] topic");
+    let pattern_doc = format!(
+        " Subscription pattern matching every `{}` topic",
+        topic.value()
+    );
+
+    Ok(quote! {
+        #[doc = #fields_doc]
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        #vis struct #fields_name {
+            #(#param_docs pub #params: String,)*
+        }
+
+        impl #impl_generics #name #ty_generics #where_clause {
+            #[doc = #pattern_doc]
+            pub const PATTERN: &'static str = #pattern;
+
+            /// Build the topic for the given placeholder values
+            #[allow(clippy::too_many_arguments)]
+            pub fn topic_for(#(#params: impl ::std::fmt::Display),*) -> String {
+                #topic_body
+            }
+
+            /// Parse the placeholder values out of a topic, if it matches this event's pattern
+            pub fn parse_topic(topic: &str) -> Option<#fields_name> {
+                let mut __levels = topic.split('/');
+                #(let #level_vars = __levels.next()?;)*
+                if __levels.next().is_some() {
+                    return None;
                 }
+                #(#level_matchers)*
+                Some(#fields_name {
+                    #(#params: #params.to_string(),)*
+                })
             }
         }
+    })
+}
+
+/// Generate the body of `Event::topic`
+///
+/// Structs interpolate their own fields. Enums match on the variant, and each
+/// variant uses its own `#[event(topic = "...")]` or else the type's topic.
+fn generate_topic(data: &Data, topic: &LitStr) -> syn::Result<proc_macro2::TokenStream> {
+    match data {
+        Data::Struct(data_struct) => {
+            let pieces = parse_topic_pattern(topic)?;
+            let bindings = field_bindings(&data_struct.fields, |member| quote! { self.#member });
+            format_topic(topic, &pieces, &bindings)
+        }
+        Data::Enum(data_enum) => {
+            let mut arms = Vec::new();
+            for variant in &data_enum.variants {
+                let topic = EventArgs::parse(&variant.attrs, false)?
+                    .topic
+                    .unwrap_or_else(|| topic.clone());
+                let pieces = parse_topic_pattern(&topic)?;
+                let bindings = field_bindings(&variant.fields, |member| match member {
+                    syn::Member::Named(ident) => quote! { #ident },
+                    syn::Member::Unnamed(index) => {
+                        let binding = format_ident!("__field{}", index.index);
+                        quote! { #binding }
+                    }
+                });
+                let body = format_topic(&topic, &pieces, &bindings)?;
+
+                let variant_name = &variant.ident;
+                let pattern = match &variant.fields {
+                    Fields::Named(fields) => {
+                        let names = fields.named.iter().map(|field| &field.ident);
+                        quote! { Self::#variant_name { #(#names),* } }
+                    }
+                    Fields::Unnamed(fields) => {
+                        let names =
+                            (0..fields.unnamed.len()).map(|i| format_ident!("__field{}", i));
+                        quote! { Self::#variant_name(#(#names),*) }
+                    }
+                    Fields::Unit => quote! { Self::#variant_name },
+                };
+                arms.push(quote! { #pattern => #body });
+            }
+
+            if arms.is_empty() {
+                return Ok(quote! { match *self {} });
+            }
+            Ok(quote! {
+                match self {
+                    #(#arms,)*
+                }
+            })
+        }
+        Data::Union(_) => {
+            let pieces = parse_topic_pattern(topic)?;
+            format_topic(topic, &pieces, &[])
+        }
+    }
+}
+
+/// Map each field name, or tuple index as a string, to the expression reading it
+fn field_bindings(
+    fields: &Fields,
+    access: impl Fn(syn::Member) -> proc_macro2::TokenStream,
+) -> Vec<(String, proc_macro2::TokenStream)> {
+    fields
+        .iter()
+        .enumerate()
+        .map(|(i, field)| match &field.ident {
+            Some(ident) => (ident.to_string(), access(syn::Member::Named(ident.clone()))),
+            None => (i.to_string(), access(syn::Member::Unnamed(i.into()))),
+        })
+        .collect()
+}
+
+/// Build the expression formatting a topic from its pieces
+fn format_topic(
+    topic: &LitStr,
+    pieces: &[TopicPiece],
+    bindings: &[(String, proc_macro2::TokenStream)],
+) -> syn::Result<proc_macro2::TokenStream> {
+    let mut format_string = String::new();
+    let mut format_args = Vec::new();
+
+    for piece in pieces {
+        match piece {
+            TopicPiece::Literal(text) => format_string.push_str(text),
+            TopicPiece::Field(path) => {
+                let Some((_, base)) = bindings.iter().find(|(name, _)| *name == path[0]) else {
+                    let known: Vec<_> = bindings.iter().map(|(name, _)| name.as_str()).collect();
+                    let message = if known.is_empty() {
+                        format!(
+                            "topic placeholder `{{{}}}` used on a type without fields",
+                            path.join(".")
+                        )
+                    } else {
+                        format!(
+                            "unknown field `{}` in topic placeholder `{{{}}}`, expected one of: {}",
+                            path[0],
+                            path.join("."),
+                            known.join(", ")
+                        )
+                    };
+                    return Err(syn::Error::new(topic.span(), message));
+                };
+
+                let rest = path[1..]
+                    .iter()
+                    .map(|segment| match segment.parse::<usize>() {
+                        Ok(index) => {
+                            let index = syn::Index::from(index);
+                            quote! { .#index }
+                        }
+                        Err(_) => {
+                            let ident = format_ident!("{}", segment);
+                            quote! { .#ident }
+                        }
+                    });
+                format_string.push_str("{}");
+                format_args.push(quote! { #base #(#rest)* });
+            }
+        }
+    }
+
+    if format_args.is_empty() {
+        return Ok(quote! { #format_string.to_string() });
+    }
+    Ok(quote! { format!(#format_string, #(#format_args),*) })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn helpers(input: DeriveInput) -> syn::Result<String> {
+        let topic = EventArgs::parse(&input.attrs, true)?.topic.unwrap();
+        generate_topic_helpers(&input, &topic).map(|tokens| tokens.to_string())
+    }
+
+    /// Error message for the topic of a derived event, from `topic` or the helpers
+    fn topic_error(input: DeriveInput) -> String {
+        let topic = EventArgs::parse(&input.attrs, true).unwrap().topic.unwrap();
+        match generate_topic(&input.data, &topic).and_then(|_| helpers(input)) {
+            Ok(_) => panic!("expected an error"),
+            Err(err) => err.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_placeholder_errors() {
+        let err = topic_error(syn::parse_quote! {
+            #[event(topic = "device/{devce_id}/status")]
+            struct Status { device_id: String, online: bool }
+        });
+        assert_eq!(
+            err,
+            "unknown field `devce_id` in topic placeholder `{devce_id}`, expected one of: device_id, online"
+        );
+
+        let err = topic_error(syn::parse_quote! {
+            #[event(topic = "ping/{id}")]
+            struct Ping;
+        });
+        assert_eq!(err, "topic placeholder `{id}` used on a type without fields");
+
+        let err = topic_error(syn::parse_quote! {
+            #[event(topic = "device/{device id}")]
+            struct Status { device: String }
+        });
+        assert!(err.starts_with("invalid placeholder `{device id}` in topic"), "{err}");
+
+        let err = topic_error(syn::parse_quote! {
+            #[event(topic = "device/{id")]
+            struct Status { id: String }
+        });
+        assert_eq!(err, "unclosed placeholder `{id` in topic");
+
+        let err = topic_error(syn::parse_quote! {
+            #[event(topic = "device/id}")]
+            struct Status { id: String }
+        });
+        assert_eq!(err, "unmatched `}` in topic");
+
+        let err = topic_error(syn::parse_quote! {
+            #[event(topic = "rack/{row}{slot}")]
+            struct Rack { row: u32, slot: u32 }
+        });
+        assert_eq!(
+            err,
+            "adjacent topic placeholders cannot be parsed, separate them with text"
+        );
+    }
+
+    #[test]
+    fn test_topic_helpers_need_placeholders() {
+        let input: DeriveInput = syn::parse_quote! {
+            #[event(topic = "sensor/temperature")]
+            struct Temperature { value: f64 }
+        };
+        assert!(helpers(input).unwrap().is_empty());
+
+        let input: DeriveInput = syn::parse_quote! {
+            #[event(topic = "sensor/{room}/temperature")]
+            struct Temperature { room: String, value: f64 }
+        };
+        assert!(helpers(input).unwrap().contains("TemperatureTopic"));
+    }
+
+    #[test]
+    fn test_colliding_placeholder_names() {
+        let input: DeriveInput = syn::parse_quote! {
+            #[event(topic = "device/{device.id}/{device_id}")]
+            struct Moved { device: Device, device_id: String }
+        };
+        let err = helpers(input).unwrap_err().to_string();
+        assert_eq!(
+            err,
+            "topic placeholders `{device.id}` and `{device_id}` both become `device_id` in `topic_for` and `parse_topic`, rename one of the fields"
+        );
+
+        // The same placeholder twice is not a collision
+        let input: DeriveInput = syn::parse_quote! {
+            #[event(topic = "device/{id}/mirror/{id}")]
+            struct Mirrored { id: String }
+        };
+        assert!(helpers(input).is_ok());
     }
 }
@@ -3,6 +3,7 @@
 use proc_macro::TokenStream;
 use syn::{parse_macro_input, DeriveInput};
 
+mod attrs;
 mod event;
 mod rpc;
 mod subscribe;
@@ -14,10 +15,10 @@ pub fn event(input: TokenStream) -> TokenStream {
     event::derive_event_impl(input)
 }
 
-/// Attribute macro for subscription methods
+/// Attribute macro for subscriber methods and the impl blocks holding them
 #[proc_macro_attribute]
 pub fn subscribe(args: TokenStream, input: TokenStream) -> TokenStream {
-    let input = parse_macro_input!(input as syn::ItemFn);
+    let input = parse_macro_input!(input as syn::Item);
     subscribe::subscribe_impl(args, input)
 }
 
@@ -2,29 +2,187 @@
 //!
 //! This is a clean rewrite focusing on the essential functionality.
 
+use crate::attrs::{MethodArgs, MethodKind, RpcArgs};
 use proc_macro::TokenStream;
-use quote::quote;
-use syn::{parse_macro_input, Attribute, FnArg, ItemTrait, ReturnType, TraitItem, Type};
-
-/// Parse RPC macro arguments
-fn parse_rpc_args(args: &str) -> RpcConfig {
-    let server = args.contains("server");
-    let client = args.contains("client");
-    let mut config = RpcConfig {
-        server,
-        client,
-        ..Default::default()
-    };
+use quote::{format_ident, quote};
+use syn::spanned::Spanned;
+use syn::{parse_macro_input, FnArg, ItemTrait, ReturnType, TraitItem, Type};
+
+/// A trait method parameter
+struct Param<'a> {
+    name: syn::Ident,
+    ty: &'a Type,
+    /// `#[serde(...)]` attributes moved onto the parameters struct field
+    serde_attrs: Vec<&'a syn::Attribute>,
+}
+
+/// Collect the typed parameters of a trait method, naming unnamed patterns by position
+///
+/// A `&RequestContext` parameter isn't sent by the client, so it's left out.
+fn method_params(sig: &syn::Signature) -> Vec<Param<'_>> {
+    sig.inputs
+        .iter()
+        .filter_map(|arg| match arg {
+            FnArg::Typed(pat_type) if !is_context_type(&pat_type.ty) => Some(pat_type),
+            _ => None,
+        })
+        .enumerate()
+        .map(|(i, pat_type)| Param {
+            name: match &*pat_type.pat {
+                syn::Pat::Ident(pat) => pat.ident.clone(),
+                _ => quote::format_ident!("arg{}", i),
+            },
+            ty: &pat_type.ty,
+            serde_attrs: pat_type
+                .attrs
+                .iter()
+                .filter(|attr| attr.path().is_ident("serde"))
+                .collect(),
+        })
+        .collect()
+}
+
+/// Whether a type is `&RequestContext`, however the path to it is written
+fn is_context_type(ty: &Type) -> bool {
+    match ty {
+        Type::Reference(reference) => matches!(
+            &*reference.elem,
+            Type::Path(path) if path.path.segments.last().is_some_and(|segment| segment.ident == "RequestContext")
+        ),
+        _ => false,
+    }
+}
 
-    // Parse namespace
-    if let Some(start) = args.find("namespace = \"") {
-        let start = start + 13;
-        if let Some(end) = args[start..].find('"') {
-            config.namespace = args[start..start + end].to_string();
+/// Whether a method opts into the request context with `ctx: &RequestContext`
+///
+/// The context must come right after `&self`, and only plain methods get one.
+fn takes_context(sig: &syn::Signature, method_type: MethodKind) -> syn::Result<bool> {
+    let typed = sig.inputs.iter().filter_map(|arg| match arg {
+        FnArg::Typed(pat_type) => Some(pat_type),
+        FnArg::Receiver(_) => None,
+    });
+
+    let mut takes_context = false;
+    for (i, pat_type) in typed.enumerate() {
+        if !is_context_type(&pat_type.ty) {
+            continue;
         }
+        if method_type == MethodKind::Subscription {
+            return Err(syn::Error::new_spanned(
+                pat_type,
+                "subscriptions don't take a `RequestContext`",
+            ));
+        }
+        if i > 0 {
+            return Err(syn::Error::new_spanned(
+                pat_type,
+                "`&RequestContext` must be the first parameter after `&self`",
+            ));
+        }
+        takes_context = true;
+    }
+    Ok(takes_context)
+}
+
+/// Generate the named struct carrying the parameters of a method
+///
+/// Every method with parameters gets one, even with a single parameter, so a
+/// defaulted parameter can later be added to any method. Parameters are encoded by name, so self-describing encodings show them, and
+/// `#[serde(...)]` on a parameter carries over to its field. Services decode it
+/// with `hsipc::decode_params`, so trailing `#[serde(default)]` parameters may be
+/// left out by callers built before they were added.
+fn generate_params_struct(
+    trait_name: &syn::Ident,
+    method_name: &syn::Ident,
+    params_struct: &syn::Ident,
+    params: &[Param],
+) -> proc_macro2::TokenStream {
+    let doc = format!(" Parameters of `{trait_name}::{method_name}`");
+    let fields = params.iter().map(|param| {
+        let name = &param.name;
+        let ty = param.ty;
+        let attrs = &param.serde_attrs;
+        quote! {
+            #(#attrs)*
+            pub #name: #ty,
+        }
+    });
+
+    quote! {
+        #[doc = #doc]
+        #[derive(::hsipc::Serialize, ::hsipc::Deserialize)]
+        #[serde(crate = "::hsipc::serde")]
+        pub struct #params_struct {
+            #(#fields)*
+        }
+    }
+}
+
+/// Reject an `async` method marked `sync`, on the method or on the whole trait
+fn check_sync(sig: &syn::Signature, trait_sync: bool, method_sync: bool) -> syn::Result<()> {
+    let Some(asyncness) = &sig.asyncness else {
+        return Ok(());
+    };
+    let method_name = &sig.ident;
+    let message = if method_sync {
+        format!("`{method_name}` is marked `sync` but is async, remove one of them")
+    } else if trait_sync {
+        format!("`#[rpc(sync)]` traits need synchronous methods, `{method_name}` is async")
+    } else {
+        return Ok(());
+    };
+    Err(syn::Error::new(asyncness.span, message))
+}
+
+/// Check that a `#[subscription(item = Type)]` type can be sent as a subscription item
+fn generate_item_check(item: &Type) -> proc_macro2::TokenStream {
+    quote::quote_spanned! {item.span()=>
+        const _: () = {
+            fn assert_subscription_item<T>()
+            where
+                T: hsipc::Serialize + for<'de> hsipc::Deserialize<'de>,
+            {
+            }
+            let _ = assert_subscription_item::<#item>;
+        };
     }
+}
+
+/// Name of the parameters struct for a method, e.g. `CalculatorAddParams`
+fn params_struct_name(trait_name: &syn::Ident, method_name: &syn::Ident) -> syn::Ident {
+    let method = method_name
+        .to_string()
+        .split('_')
+        .map(|s| {
+            let mut c = s.chars();
+            match c.next() {
+                None => String::new(),
+                Some(f) => f.to_uppercase().collect::<String>() + c.as_str(),
+            }
+        })
+        .collect::<String>();
+    quote::format_ident!("{}{}Params", trait_name, method)
+}
 
-    config
+/// Convert a trait name like `UserService` into `user_service`
+fn snake_case(name: &str) -> String {
+    let mut snake = String::new();
+    for (i, c) in name.char_indices() {
+        if c.is_uppercase() {
+            let prev_lower = name[..i].chars().last().is_some_and(|p| !p.is_uppercase());
+            let next_lower = name[i + c.len_utf8()..]
+                .chars()
+                .next()
+                .is_some_and(char::is_lowercase);
+            if i > 0 && (prev_lower || next_lower) {
+                snake.push('_');
+            }
+            snake.extend(c.to_lowercase());
+        } else {
+            snake.push(c);
+        }
+    }
+    snake
 }
 
 /// Extract the inner type from Result<T>
@@ -57,56 +215,6 @@ fn extract_result_inner_type(return_type: Option<&Type>) -> proc_macro2::TokenSt
     }
 }
 
-#[derive(Default)]
-#[allow(dead_code)]
-struct RpcConfig {
-    server: bool,
-    client: bool,
-    namespace: String,
-}
-
-/// Method type enumeration
-#[derive(Debug, PartialEq)]
-enum MethodType {
-    Method,       // Regular RPC method
-    Subscription, // Subscription method
-}
-
-/// Parse method attributes to extract method type and RPC name
-fn parse_method_attributes(attrs: &[Attribute], default_name: &str) -> (MethodType, String) {
-    for attr in attrs {
-        if attr.path().is_ident("method") {
-            // Parse #[method(name = "...")]
-            if let Ok(meta) = attr.meta.require_list() {
-                let tokens = meta.tokens.to_string();
-                if let Some(start) = tokens.find("name = \"") {
-                    let start = start + 8; // length of "name = \""
-                    if let Some(end) = tokens[start..].find('"') {
-                        let method_name = tokens[start..start + end].to_string();
-                        return (MethodType::Method, method_name);
-                    }
-                }
-            }
-            return (MethodType::Method, default_name.to_string());
-        } else if attr.path().is_ident("subscription") {
-            // Parse #[subscription(name = "...")]
-            if let Ok(meta) = attr.meta.require_list() {
-                let tokens = meta.tokens.to_string();
-                if let Some(start) = tokens.find("name = \"") {
-                    let start = start + 8; // length of "name = \""
-                    if let Some(end) = tokens[start..].find('"') {
-                        let subscription_name = tokens[start..start + end].to_string();
-                        return (MethodType::Subscription, subscription_name);
-                    }
-                }
-            }
-            return (MethodType::Subscription, default_name.to_string());
-        }
-    }
-    // Default to method if no attribute found
-    (MethodType::Method, default_name.to_string())
-}
-
 /// Generate handler for subscription methods
 fn generate_subscription_handler(
     _method_name: &syn::Ident,
@@ -122,146 +230,125 @@ fn generate_subscription_handler(
 }
 
 /// Generate handler for regular RPC methods
+///
+/// Non-async methods run on the blocking thread pool so they can't stall the
+/// async handler.
 fn generate_method_handler(
     method_name: &syn::Ident,
     rpc_method_name: &str,
-    params: &[&Type],
+    params: &[Param],
+    params_struct: &syn::Ident,
     is_async: bool,
+    takes_context: bool,
 ) -> proc_macro2::TokenStream {
-    if params.len() == 1 {
-        let param_type = params[0];
-        if is_async {
-            quote! {
-                #rpc_method_name => {
-                    let request: #param_type = bincode::deserialize(&payload)?;
-                    let response = self.inner.#method_name(request).await?;
-                    Ok(bincode::serialize(&response)?)
-                }
-            }
-        } else {
-            quote! {
-                #rpc_method_name => {
-                    let request: #param_type = bincode::deserialize(&payload)?;
-                    let response = self.inner.#method_name(request)?;
-                    Ok(bincode::serialize(&response)?)
-                }
-            }
-        }
-    } else if params.is_empty() {
-        if is_async {
-            quote! {
-                #rpc_method_name => {
-                    let response = self.inner.#method_name().await?;
-                    Ok(bincode::serialize(&response)?)
-                }
-            }
-        } else {
-            quote! {
-                #rpc_method_name => {
-                    let response = self.inner.#method_name()?;
-                    Ok(bincode::serialize(&response)?)
-                }
-            }
+    let (decode, args) = match params {
+        [] => (quote! {}, quote! {}),
+        _ => {
+            // Decode the named parameters struct, missing trailing ones defaulted
+            let names = params.iter().map(|param| &param.name);
+            (
+                quote! { let params: #params_struct = hsipc::decode_params(&payload)?; },
+                quote! { #(params.#names),* },
+            )
         }
+    };
+
+    let call = if is_async {
+        let ctx = takes_context.then(|| quote! { ctx, });
+        quote! { self.inner.#method_name(#ctx #args).await? }
     } else {
-        // Multiple parameters - serialize as tuple
-        let param_tuple = quote! { (#(#params),*) };
-        if is_async {
-            quote! {
-                #rpc_method_name => {
-                    let params: #param_tuple = bincode::deserialize(&payload)?;
-                    let response = self.inner.#method_name(params.0, params.1).await?;
-                    Ok(bincode::serialize(&response)?)
-                }
-            }
+        // The blocking closure needs its own copy of the context
+        let (clone_ctx, ctx) = if takes_context {
+            (quote! { let ctx = ctx.clone(); }, Some(quote! { &ctx, }))
         } else {
-            quote! {
-                #rpc_method_name => {
-                    let params: #param_tuple = bincode::deserialize(&payload)?;
-                    let response = self.inner.#method_name(params.0, params.1)?;
-                    Ok(bincode::serialize(&response)?)
-                }
-            }
+            (quote! {}, None)
+        };
+        quote! {{
+            let inner = self.inner.clone();
+            #clone_ctx
+            hsipc::run_blocking(move || inner.#method_name(#ctx #args)).await??
+        }}
+    };
+
+    quote! {
+        #rpc_method_name => {
+            #decode
+            let response = #call;
+            Ok(bincode::serialize(&response)?)
         }
     }
 }
 
 /// Generate client method for regular RPC calls
+///
+/// Async clients call through `ProcessHub`; blocking clients call through
+/// `SyncProcessHub`, which is safe to use from inside a Tokio runtime.
+/// Methods with `#[method(timeout = ms)]` call with that timeout instead of
+/// the default one.
+#[allow(clippy::too_many_arguments)]
 fn generate_rpc_client_method(
     method_name: &syn::Ident,
     rpc_method_name: &str,
-    params: &[&Type],
+    params: &[Param],
+    params_struct: &syn::Ident,
     client_return_type: &proc_macro2::TokenStream,
     namespace: &str,
-    is_async: bool,
+    timeout_ms: Option<u64>,
+    blocking: bool,
 ) -> proc_macro2::TokenStream {
-    if params.len() == 1 {
-        let param_type = params[0];
-        if is_async {
-            quote! {
-                pub async fn #method_name(&self, request: #param_type) -> hsipc::Result<#client_return_type> {
-                    let result: #client_return_type = self.hub.call(&format!("{}.{}", #namespace, #rpc_method_name), request).await?;
-                    Ok(result)
-                }
-            }
-        } else {
-            quote! {
-                pub fn #method_name(&self, request: #param_type) -> hsipc::Result<#client_return_type> {
-                    let result: #client_return_type = futures::executor::block_on(
-                        self.hub.call(&format!("{}.{}", #namespace, #rpc_method_name), request)
-                    )?;
-                    Ok(result)
-                }
-            }
+    let (args, request) = match params {
+        [] => (quote! {}, quote! { () }),
+        _ => {
+            let param_names: Vec<&syn::Ident> = params.iter().map(|param| &param.name).collect();
+            let param_types: Vec<&Type> = params.iter().map(|param| param.ty).collect();
+            (
+                quote! { #(#param_names: #param_types),* },
+                quote! { #params_struct { #(#param_names),* } },
+            )
         }
-    } else if params.is_empty() {
-        if is_async {
-            quote! {
-                pub async fn #method_name(&self) -> hsipc::Result<#client_return_type> {
-                    let result: #client_return_type = self.hub.call(&format!("{}.{}", #namespace, #rpc_method_name), ()).await?;
-                    Ok(result)
-                }
-            }
-        } else {
-            quote! {
-                pub fn #method_name(&self) -> hsipc::Result<#client_return_type> {
-                    let result: #client_return_type = futures::executor::block_on(
-                        self.hub.call(&format!("{}.{}", #namespace, #rpc_method_name), ())
-                    )?;
-                    Ok(result)
-                }
+    };
+
+    let call = match timeout_ms {
+        Some(millis) => quote! {
+            self.hub.call_with(
+                &format!("{}.{}", #namespace, #rpc_method_name),
+                #request,
+                hsipc::CallOptions::new().timeout(::std::time::Duration::from_millis(#millis)),
+            )
+        },
+        None => quote! {
+            self.hub.call(&format!("{}.{}", #namespace, #rpc_method_name), #request)
+        },
+    };
+
+    if blocking {
+        quote! {
+            pub fn #method_name(&self, #args) -> hsipc::Result<#client_return_type> {
+                let result: #client_return_type = #call?;
+                Ok(result)
             }
         }
     } else {
-        // Multiple parameters
-        let param_names: Vec<syn::Ident> = (0..params.len())
-            .map(|i| syn::Ident::new(&format!("p{i}"), method_name.span()))
-            .collect();
-
-        if is_async {
-            quote! {
-                pub async fn #method_name(&self, #(#param_names: #params),*) -> hsipc::Result<#client_return_type> {
-                    let params = (#(#param_names),*);
-                    let result: #client_return_type = self.hub.call(&format!("{}.{}", #namespace, #rpc_method_name), params).await?;
-                    Ok(result)
-                }
-            }
-        } else {
-            quote! {
-                pub fn #method_name(&self, #(#param_names: #params),*) -> hsipc::Result<#client_return_type> {
-                    let params = (#(#param_names),*);
-                    let result: #client_return_type = futures::executor::block_on(
-                        self.hub.call(&format!("{}.{}", #namespace, #rpc_method_name), params)
-                    )?;
-                    Ok(result)
-                }
+        quote! {
+            pub async fn #method_name(&self, #args) -> hsipc::Result<#client_return_type> {
+                let result: #client_return_type = #call.await?;
+                Ok(result)
             }
         }
     }
 }
 
+/// Remove `#[serde(...)]` parameter attributes, which only apply to the parameters struct
+fn strip_serde_attrs(sig: &mut syn::Signature) {
+    for input in &mut sig.inputs {
+        if let FnArg::Typed(pat_type) = input {
+            pat_type.attrs.retain(|attr| !attr.path().is_ident("serde"));
+        }
+    }
+}
+
 /// Transform trait to add PendingSubscriptionSink parameters to subscription methods
-fn transform_trait_for_subscription(input: &ItemTrait) -> proc_macro2::TokenStream {
+fn transform_trait_for_subscription(input: &ItemTrait) -> syn::Result<proc_macro2::TokenStream> {
     let trait_ident = &input.ident;
     let trait_generics = &input.generics;
     let trait_bounds = &input.supertraits;
@@ -274,9 +361,9 @@ fn transform_trait_for_subscription(input: &ItemTrait) -> proc_macro2::TokenStre
             let method_name_str = method_name.to_string();
 
             // Check if this is a subscription method
-            let (method_type, _) = parse_method_attributes(&method.attrs, &method_name_str);
+            let method_args = MethodArgs::parse(&method.attrs, &method_name_str)?;
 
-            if method_type == MethodType::Subscription {
+            if method_args.kind == MethodKind::Subscription {
                 // Transform subscription method to include PendingSubscriptionSink
                 let mut transformed_method = method.clone();
 
@@ -299,10 +386,13 @@ fn transform_trait_for_subscription(input: &ItemTrait) -> proc_macro2::TokenStre
                 }
 
                 transformed_method.sig.inputs = new_inputs;
+                strip_serde_attrs(&mut transformed_method.sig);
                 transformed_items.push(TraitItem::Fn(transformed_method));
             } else {
-                // Keep non-subscription methods unchanged
-                transformed_items.push(item.clone());
+                // Keep non-subscription methods unchanged apart from parameter attributes
+                let mut method = method.clone();
+                strip_serde_attrs(&mut method.sig);
+                transformed_items.push(TraitItem::Fn(method));
             }
         } else {
             // Keep non-function items unchanged
@@ -310,43 +400,92 @@ fn transform_trait_for_subscription(input: &ItemTrait) -> proc_macro2::TokenStre
         }
     }
 
-    quote! {
+    Ok(quote! {
         pub trait #trait_ident #trait_generics: #trait_bounds {
             #(#transformed_items)*
         }
+    })
+}
+
+/// Generate one method of the trait implementation for the async client
+///
+/// Methods forward to the client's inherent method, converting `hsipc::Error`
+/// into the trait's error type. Subscriptions can't be opened through the
+/// trait, so they return an error.
+fn generate_client_trait_method(
+    client_name: &syn::Ident,
+    method: &syn::TraitItemFn,
+    params: &[Param],
+    method_type: MethodKind,
+    takes_context: bool,
+) -> syn::Result<proc_macro2::TokenStream> {
+    let sig = &method.sig;
+    let method_name = &sig.ident;
+    if sig.asyncness.is_none() {
+        return Err(syn::Error::new(
+            sig.fn_token.span,
+            format!("`client_trait` needs async trait methods, `{method_name}` is not async"),
+        ));
     }
+
+    let returns_result = match &sig.output {
+        ReturnType::Type(_, ty) => matches!(
+            &**ty,
+            Type::Path(path) if path.path.segments.last().is_some_and(|segment| segment.ident == "Result")
+        ),
+        ReturnType::Default => false,
+    };
+    if !returns_result {
+        return Err(syn::Error::new(
+            sig.ident.span(),
+            format!(
+                "`client_trait` needs trait methods returning a `Result`, `{method_name}` does not"
+            ),
+        ));
+    }
+
+    let output = &sig.output;
+    let param_names: Vec<&syn::Ident> = params.iter().map(|param| &param.name).collect();
+    let param_types: Vec<&Type> = params.iter().map(|param| param.ty).collect();
+
+    // The remote service builds its own context, so the caller's is unused
+    let ctx = takes_context.then(|| quote! { _ctx: &hsipc::RequestContext, });
+
+    Ok(match method_type {
+        MethodKind::Method => quote! {
+            async fn #method_name(&self, #ctx #(#param_names: #param_types),*) #output {
+                Ok(#client_name::#method_name(self, #(#param_names),*).await?)
+            }
+        },
+        MethodKind::Subscription => {
+            let message = format!(
+                "subscription `{method_name}` is not available through the `{client_name}` trait implementation"
+            );
+            quote! {
+                #[allow(unused_variables)]
+                async fn #method_name(
+                    &self,
+                    pending: hsipc::PendingSubscriptionSink,
+                    #(#param_names: #param_types),*
+                ) #output {
+                    Err(hsipc::Error::runtime_msg(#message).into())
+                }
+            }
+        }
+    })
 }
 
 /// Generate client method for subscription calls
 fn generate_subscription_client_method(
     method_name: &syn::Ident,
     rpc_method_name: &str,
-    params: &[&Type],
+    params: &[Param],
+    params_struct: &syn::Ident,
     namespace: &str,
     _return_type: Option<&Type>,
 ) -> proc_macro2::TokenStream {
     // Generate subscription client method that sends subscription request
-    if params.len() == 1 {
-        let param_type = params[0];
-        quote! {
-            pub async fn #method_name(&self, params: #param_type) -> hsipc::Result<()> {
-                // Serialize parameters
-                let serialized_params = bincode::serialize(&params)?;
-
-                // Send subscription request
-                let request_msg = hsipc::Message::subscription_request(
-                    self.hub.name().to_string(),
-                    None, // Broadcast to all processes
-                    format!("{}.{}", #namespace, #rpc_method_name),
-                    serialized_params,
-                );
-
-                // Send the request (for now, just send and return)
-                // TODO: Handle subscription response and return RpcSubscription
-                Ok(())
-            }
-        }
-    } else if params.is_empty() {
+    if params.is_empty() {
         quote! {
             pub async fn #method_name(&self) -> hsipc::Result<()> {
                 // Send subscription request with no parameters
@@ -363,16 +502,14 @@ fn generate_subscription_client_method(
             }
         }
     } else {
-        // Multiple parameters
-        let param_names: Vec<syn::Ident> = (0..params.len())
-            .map(|i| syn::Ident::new(&format!("p{i}"), method_name.span()))
-            .collect();
+        let param_names: Vec<&syn::Ident> = params.iter().map(|param| &param.name).collect();
+        let param_types: Vec<&Type> = params.iter().map(|param| param.ty).collect();
 
         quote! {
-            pub async fn #method_name(&self, #(#param_names: #params),*) -> hsipc::Result<()> {
-                // Serialize parameters as tuple
-                let params_tuple = (#(#param_names),*);
-                let serialized_params = bincode::serialize(&params_tuple)?;
+            pub async fn #method_name(&self, #(#param_names: #param_types),*) -> hsipc::Result<()> {
+                // Serialize the named parameters struct
+                let params = #params_struct { #(#param_names),* };
+                let serialized_params = bincode::serialize(&params)?;
 
                 // Send subscription request
                 let request_msg = hsipc::Message::subscription_request(
@@ -390,22 +527,122 @@ fn generate_subscription_client_method(
     }
 }
 
-/// RPC macro implementation
+/// Pieces of a generated mock for one trait method
+struct MockMethodParts {
+    name: syn::Ident,
+    field: proc_macro2::TokenStream,
+    init: proc_macro2::TokenStream,
+    accessors: proc_macro2::TokenStream,
+    trait_method: proc_macro2::TokenStream,
+}
+
+/// Generate the expectation field, accessors and trait method of a mock
+///
+/// Calls are recorded with their parameters as a tuple, so matchers and
+/// responders receive `(a, b)` for `fn m(&self, a: A, b: B)`.
+fn generate_mock_method(
+    method: &syn::TraitItemFn,
+    params: &[Param],
+    method_type: MethodKind,
+    takes_context: bool,
+) -> MockMethodParts {
+    let sig = &method.sig;
+    let method_name = &sig.ident;
+    let method_name_str = method_name.to_string();
+    let expect_name = format_ident!("expect_{}", method_name);
+    let calls_name = format_ident!("{}_calls", method_name);
+    let asyncness = &sig.asyncness;
+    let output = &sig.output;
+
+    let param_names: Vec<&syn::Ident> = params.iter().map(|param| &param.name).collect();
+    let param_types: Vec<&Type> = params.iter().map(|param| param.ty).collect();
+    let args_type = quote! { (#(#param_types,)*) };
+    let return_type = match output {
+        ReturnType::Type(_, ty) => quote! { #ty },
+        ReturnType::Default => quote! { () },
+    };
+    let method_type_tokens = quote! { hsipc::mock::MockMethod<#args_type, #return_type> };
+
+    let ctx = takes_context.then(|| quote! { _ctx: &hsipc::RequestContext, });
+    let trait_method = match method_type {
+        MethodKind::Method => quote! {
+            #asyncness fn #method_name(&self, #ctx #(#param_names: #param_types),*) #output {
+                self.#method_name.call((#(#param_names,)*))
+            }
+        },
+        MethodKind::Subscription => quote! {
+            #[allow(unused_variables)]
+            #asyncness fn #method_name(
+                &self,
+                pending: hsipc::PendingSubscriptionSink,
+                #(#param_names: #param_types),*
+            ) #output {
+                self.#method_name.call((#(#param_names,)*))
+            }
+        },
+    };
+
+    MockMethodParts {
+        name: method_name.clone(),
+        field: quote! { #method_name: ::std::sync::Arc<#method_type_tokens>, },
+        init: quote! {
+            #method_name: ::std::sync::Arc::new(hsipc::mock::MockMethod::new(#method_name_str)),
+        },
+        accessors: quote! {
+            /// Expect a call, see [`hsipc::mock::Expectation`]
+            pub fn #expect_name(&self) -> hsipc::mock::Expectation<#args_type, #return_type> {
+                self.#method_name.expect()
+            }
+
+            /// Number of calls so far, expected or not
+            pub fn #calls_name(&self) -> usize {
+                self.#method_name.calls()
+            }
+        },
+        trait_method,
+    }
+}
+
 pub fn rpc_impl(args: TokenStream, input: TokenStream) -> TokenStream {
     let input = parse_macro_input!(input as ItemTrait);
-    let args_str = args.to_string();
-    let config = parse_rpc_args(&args_str);
+    match expand_rpc(args.into(), input) {
+        Ok(expanded) => TokenStream::from(expanded),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+fn expand_rpc(
+    args: proc_macro2::TokenStream,
+    input: ItemTrait,
+) -> syn::Result<proc_macro2::TokenStream> {
+    let config = RpcArgs::parse(args)?;
 
     let trait_name = &input.ident;
     let service_name = syn::Ident::new(&format!("{trait_name}Service"), trait_name.span());
     let client_name = syn::Ident::new(&format!("{trait_name}Client"), trait_name.span());
+    let blocking_client_name =
+        syn::Ident::new(&format!("{trait_name}BlockingClient"), trait_name.span());
 
-    let namespace = &config.namespace;
+    // Without `server` or `client`, both sides are generated
+    let generate_server = config.server || !config.client;
+    let generate_client = config.client || config.client_trait || !config.server;
+
+    // The namespace defaults to the trait name in snake case
+    let namespace = &config
+        .namespace
+        .as_ref()
+        .map(|namespace| namespace.value())
+        .unwrap_or_else(|| snake_case(&trait_name.to_string()));
 
     // Extract methods from trait
     let mut method_names = Vec::new();
     let mut service_handlers = Vec::new();
     let mut client_methods = Vec::new();
+    let mut blocking_client_methods = Vec::new();
+    let mut params_structs = Vec::new();
+    let mut client_trait_methods = Vec::new();
+    let mut mock_methods = Vec::new();
+    let mut item_checks = Vec::new();
 
     for item in &input.items {
         if let TraitItem::Fn(method) = item {
@@ -413,20 +650,28 @@ pub fn rpc_impl(args: TokenStream, input: TokenStream) -> TokenStream {
             let method_name_str = method_name.to_string();
 
             // Parse method attributes to determine type and RPC name
-            let (method_type, rpc_method_name) =
-                parse_method_attributes(&method.attrs, &method_name_str);
+            let method_args = MethodArgs::parse(&method.attrs, &method_name_str)?;
+            let method_type = method_args.kind;
+            let rpc_method_name = method_args.name.clone();
             method_names.push(rpc_method_name.clone());
 
-            // Extract parameters (skip &self)
-            let params: Vec<&Type> = method
-                .sig
-                .inputs
-                .iter()
-                .filter_map(|arg| match arg {
-                    FnArg::Typed(pat_type) => Some(&*pat_type.ty),
-                    _ => None,
-                })
-                .collect();
+            check_sync(&method.sig, config.sync, method_args.sync)?;
+            if let Some(item) = &method_args.item {
+                item_checks.push(generate_item_check(item));
+            }
+
+            // Extract parameters (skip &self); they're sent as a named struct
+            let takes_context = takes_context(&method.sig, method_type)?;
+            let params = method_params(&method.sig);
+            let params_struct = params_struct_name(trait_name, method_name);
+            if !params.is_empty() {
+                params_structs.push(generate_params_struct(
+                    trait_name,
+                    method_name,
+                    &params_struct,
+                    &params,
+                ));
+            }
 
             // Extract return type
             let return_type = match &method.sig.output {
@@ -439,58 +684,93 @@ pub fn rpc_impl(args: TokenStream, input: TokenStream) -> TokenStream {
 
             // Generate service handler based on method type
             let handler = match method_type {
-                MethodType::Subscription => {
+                MethodKind::Subscription => {
                     // For subscription methods, we need special handling
                     // These are handled through the subscription protocol, not regular RPC
                     generate_subscription_handler(method_name, &rpc_method_name)
                 }
-                MethodType::Method => {
+                MethodKind::Method => {
                     // Regular method handling
-                    generate_method_handler(method_name, &rpc_method_name, &params, is_async)
+                    generate_method_handler(
+                        method_name,
+                        &rpc_method_name,
+                        &params,
+                        &params_struct,
+                        is_async,
+                        takes_context,
+                    )
                 }
             };
             service_handlers.push(handler);
 
             // Generate client method
             let client_method = match method_type {
-                MethodType::Subscription => {
+                MethodKind::Subscription => {
                     // Generate subscription client method
                     generate_subscription_client_method(
                         method_name,
                         &rpc_method_name,
                         &params,
+                        &params_struct,
                         namespace,
                         return_type,
                     )
                 }
-                MethodType::Method => {
+                MethodKind::Method => {
                     // Generate regular RPC client method
                     let client_return_type = extract_result_inner_type(return_type);
+                    blocking_client_methods.push(generate_rpc_client_method(
+                        method_name,
+                        &rpc_method_name,
+                        &params,
+                        &params_struct,
+                        &client_return_type,
+                        namespace,
+                        method_args.timeout_ms,
+                        true,
+                    ));
                     generate_rpc_client_method(
                         method_name,
                         &rpc_method_name,
                         &params,
+                        &params_struct,
                         &client_return_type,
                         namespace,
-                        is_async,
+                        method_args.timeout_ms,
+                        false,
                     )
                 }
             };
             client_methods.push(client_method);
+
+            if config.client_trait {
+                client_trait_methods.push(generate_client_trait_method(
+                    &client_name,
+                    method,
+                    &params,
+                    method_type,
+                    takes_context,
+                )?);
+            }
+
+            if config.mock {
+                mock_methods.push(generate_mock_method(
+                    method,
+                    &params,
+                    method_type,
+                    takes_context,
+                ));
+            }
         }
     }
 
     // Transform the trait to add PendingSubscriptionSink parameters to subscription methods
-    let transformed_trait = transform_trait_for_subscription(&input);
-
-    let expanded = quote! {
-        // Generate transformed trait for implementation with PendingSubscriptionSink parameters
-        #[hsipc::async_trait]
-        #transformed_trait
+    let transformed_trait = transform_trait_for_subscription(&input)?;
 
+    let server_side = quote! {
         // Generate service struct
         pub struct #service_name<T> {
-            inner: T,
+            inner: ::std::sync::Arc<T>,
         }
 
         impl<T> #service_name<T>
@@ -498,7 +778,9 @@ pub fn rpc_impl(args: TokenStream, input: TokenStream) -> TokenStream {
             T: #trait_name + Send + Sync,
         {
             pub fn new(inner: T) -> Self {
-                Self { inner }
+                Self {
+                    inner: ::std::sync::Arc::new(inner),
+                }
             }
         }
 
@@ -516,14 +798,22 @@ pub fn rpc_impl(args: TokenStream, input: TokenStream) -> TokenStream {
                 vec![#(#method_names),*]
             }
 
-            async fn handle(&self, method: &str, payload: Vec<u8>) -> hsipc::Result<Vec<u8>> {
+            #[allow(unused_variables)]
+            async fn handle(
+                &self,
+                ctx: &hsipc::RequestContext,
+                method: &str,
+                payload: Vec<u8>,
+            ) -> hsipc::Result<Vec<u8>> {
                 match method {
                     #(#service_handlers)*
                     _ => Err(hsipc::Error::method_not_found(self.name(), method))
                 }
             }
         }
+    };
 
+    let client_side = quote! {
         // Generate client struct
         #[derive(Clone)]
         pub struct #client_name {
@@ -537,7 +827,128 @@ pub fn rpc_impl(args: TokenStream, input: TokenStream) -> TokenStream {
 
             #(#client_methods)*
         }
+
+        // Generate blocking client struct for code outside async contexts
+        #[derive(Clone)]
+        pub struct #blocking_client_name {
+            hub: hsipc::SyncProcessHub,
+        }
+
+        impl #blocking_client_name {
+            pub fn new(hub: hsipc::SyncProcessHub) -> Self {
+                Self { hub }
+            }
+
+            #(#blocking_client_methods)*
+        }
+    };
+
+    // Optionally let the client stand in for a local implementation
+    let client_trait_impl = config.client_trait.then(|| {
+        quote! {
+            #[hsipc::async_trait]
+            impl #trait_name for #client_name {
+                #(#client_trait_methods)*
+            }
+        }
+    });
+    let client_side = quote! {
+        #client_side
+        #client_trait_impl
     };
 
-    TokenStream::from(expanded)
+    // Mocks implement the trait, so they work wherever the client or a service does
+    let mock = config.mock.then(|| {
+        let mock_name = format_ident!("Mock{}", trait_name);
+        let fields = mock_methods.iter().map(|parts| &parts.field);
+        let inits = mock_methods.iter().map(|parts| &parts.init);
+        let accessors = mock_methods.iter().map(|parts| &parts.accessors);
+        let trait_methods = mock_methods.iter().map(|parts| &parts.trait_method);
+        let names = mock_methods.iter().map(|parts| &parts.name);
+        quote! {
+            /// Test double answering calls from expectations
+            ///
+            /// Clones share expectations, so a clone can be registered with
+            /// `XxxService::new` while the test keeps configuring the original.
+            /// Unexpected calls panic, so mocks are meant for tests only.
+            #[derive(Clone)]
+            pub struct #mock_name {
+                #(#fields)*
+            }
+
+            impl Default for #mock_name {
+                fn default() -> Self {
+                    Self {
+                        #(#inits)*
+                    }
+                }
+            }
+
+            impl #mock_name {
+                pub fn new() -> Self {
+                    Self::default()
+                }
+
+                #(#accessors)*
+
+                /// Check the call counts of expectations set with `times`, then clear all expectations
+                pub fn checkpoint(&self) {
+                    #(self.#names.checkpoint();)*
+                }
+            }
+
+            #[hsipc::async_trait]
+            impl #trait_name for #mock_name {
+                #(#trait_methods)*
+            }
+        }
+    });
+
+    let server_side = generate_server.then_some(server_side);
+    let client_side = generate_client.then_some(client_side);
+
+    let expanded = quote! {
+        // Generate transformed trait for implementation with PendingSubscriptionSink parameters
+        #[hsipc::async_trait]
+        #transformed_trait
+
+        #(#params_structs)*
+
+        #(#item_checks)*
+
+        #server_side
+
+        #client_side
+
+        #mock
+    };
+
+    Ok(expanded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_snake_case() {
+        assert_eq!(snake_case("Calculator"), "calculator");
+        assert_eq!(snake_case("UserService"), "user_service");
+        assert_eq!(snake_case("HTTPGateway"), "http_gateway");
+        assert_eq!(snake_case("KvStoreV2"), "kv_store_v2");
+    }
+
+    #[test]
+    fn test_sync_methods_must_not_be_async() {
+        let sync_fn: syn::Signature = syn::parse_quote!(fn add(&self, a: u32) -> Result<u32>);
+        let async_fn: syn::Signature =
+            syn::parse_quote!(async fn add(&self, a: u32) -> Result<u32>);
+        assert!(check_sync(&sync_fn, true, true).is_ok());
+        assert!(check_sync(&async_fn, false, false).is_ok());
+
+        let err = check_sync(&async_fn, false, true).unwrap_err().to_string();
+        assert!(err.contains("`add` is marked `sync` but is async"), "{err}");
+        let err = check_sync(&async_fn, true, false).unwrap_err().to_string();
+        assert!(err.contains("`#[rpc(sync)]` traits need synchronous methods"), "{err}");
+    }
 }
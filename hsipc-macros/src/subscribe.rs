@@ -1,28 +1,184 @@
 //! Subscribe macro implementation
 
+use crate::attrs::SubscribeArgs;
 use proc_macro::TokenStream;
-use quote::quote;
-use syn::{FnArg, ItemFn, PatType, Type};
+use quote::{format_ident, quote};
+use syn::spanned::Spanned;
+use syn::{FnArg, ImplItem, ImplItemFn, Item, ItemFn, ItemImpl, PatType, ReturnType, Type};
 
 /// Implementation of the #[subscribe] attribute macro
-pub fn subscribe_impl(args: TokenStream, input: ItemFn) -> TokenStream {
-    let method = &input;
-    let method_name = &method.sig.ident;
-
-    // Parse the topic from args or infer from method signature
-    let topic = parse_subscribe_topic(args).unwrap_or_else(|| {
-        // Try to extract topic from method parameters
-        extract_topic_from_method(method).unwrap_or_else(|| {
-            // Default topic based on method name
-            format!("events/{}", method_name.to_string().replace("on_", ""))
-        })
-    });
+///
+/// On an impl block, every method marked `#[subscribe(...)]` becomes a
+/// subscriber registered by the generated `register_subscribers`. On a free
+/// function, a `XxxSubscriber` implementing `Subscriber` is generated.
+pub fn subscribe_impl(args: TokenStream, input: Item) -> TokenStream {
+    let result = match input {
+        Item::Impl(item_impl) => {
+            if !args.is_empty() {
+                return syn::Error::new(
+                    proc_macro2::TokenStream::from(args).span(),
+                    "#[subscribe] on an impl block takes no arguments, put the topic on each method",
+                )
+                .to_compile_error()
+                .into();
+            }
+            subscribe_impl_block(item_impl)
+        }
+        Item::Fn(item_fn) => subscribe_fn(args.into(), item_fn),
+        other => Err(syn::Error::new(
+            other.span(),
+            "#[subscribe] can only be used on functions and impl blocks",
+        )),
+    };
 
-    // Extract the event type from the method signature
-    let event_type = extract_event_type(method);
+    match result {
+        Ok(tokens) => TokenStream::from(tokens),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+/// Generate `register_subscribers` for the `#[subscribe]` methods of an impl block
+fn subscribe_impl_block(mut item_impl: ItemImpl) -> syn::Result<proc_macro2::TokenStream> {
+    let mut registrations = Vec::new();
+
+    for item in &mut item_impl.items {
+        let ImplItem::Fn(method) = item else {
+            continue;
+        };
+        let Some(index) = method
+            .attrs
+            .iter()
+            .position(|attr| attr.path().is_ident("subscribe"))
+        else {
+            continue;
+        };
+        let attr = method.attrs.remove(index);
+        let args = match &attr.meta {
+            syn::Meta::Path(_) => proc_macro2::TokenStream::new(),
+            syn::Meta::List(list) => list.tokens.clone(),
+            syn::Meta::NameValue(meta) => {
+                return Err(syn::Error::new(
+                    meta.span(),
+                    "expected #[subscribe] or #[subscribe(\"topic\")]",
+                ))
+            }
+        };
+        registrations.push(method_registration(args, method)?);
+    }
+
+    let (impl_generics, _, where_clause) = item_impl.generics.split_for_impl();
+    let self_ty = &item_impl.self_ty;
+
+    Ok(quote! {
+        #item_impl
+
+        impl #impl_generics #self_ty #where_clause {
+            /// Subscribe every `#[subscribe]` method of this type on the hub
+            ///
+            /// Each method is called with the decoded event. Dropping the returned
+            /// subscriptions unsubscribes them.
+            pub async fn register_subscribers(
+                self: ::std::sync::Arc<Self>,
+                hub: &::hsipc::ProcessHub,
+            ) -> ::hsipc::Result<Vec<::hsipc::Subscription>>
+            where
+                Self: Send + Sync + 'static,
+            {
+                let mut subscriptions = Vec::new();
+                #(#registrations)*
+                Ok(subscriptions)
+            }
+        }
+    })
+}
+
+/// Generate the statement subscribing one method
+fn method_registration(
+    args: proc_macro2::TokenStream,
+    method: &ImplItemFn,
+) -> syn::Result<proc_macro2::TokenStream> {
+    let sig = &method.sig;
+    let method_name = &sig.ident;
+    let topic = subscribe_topic(args, &method_name.to_string())?;
+
+    let mut inputs = sig.inputs.iter();
+    match inputs.next() {
+        Some(FnArg::Receiver(receiver))
+            if receiver.reference.is_some() && receiver.mutability.is_none() => {}
+        Some(FnArg::Receiver(receiver)) => {
+            return Err(syn::Error::new(
+                receiver.span(),
+                "subscriber methods take `&self`, use interior mutability for state",
+            ))
+        }
+        _ => {
+            return Err(syn::Error::new(
+                sig.span(),
+                "subscriber methods take `&self` followed by the event",
+            ))
+        }
+    }
+
+    let (takes_topic, event_type) = handler_params(inputs.collect(), sig)?;
+    let topic_arg = takes_topic.then(|| quote! { &topic, });
+    let topic_param = topic_param(takes_topic);
+    let returns_result = !matches!(sig.output, ReturnType::Default);
+
+    let register = if sig.asyncness.is_some() {
+        let call = quote! { this.#method_name(#topic_arg event).await };
+        let body = handler_body(call, returns_result);
+        quote! {
+            hub.on(#topic, move |#topic_param: String, event: #event_type| {
+                let this = this.clone();
+                async move { #body }
+            })
+            .await?
+        }
+    } else {
+        let call = quote! { this.#method_name(#topic_arg event) };
+        let body = handler_body(call, returns_result);
+        quote! {
+            hub.on_sync(#topic, move |#topic_param: &str, event: #event_type| { #body })
+                .await?
+        }
+    };
+
+    Ok(quote! {
+        {
+            let this = self.clone();
+            subscriptions.push(#register);
+        }
+    })
+}
+
+/// Generate a `Subscriber` for a free function
+fn subscribe_fn(
+    args: proc_macro2::TokenStream,
+    method: ItemFn,
+) -> syn::Result<proc_macro2::TokenStream> {
+    let sig = &method.sig;
+    let method_name = &sig.ident;
+
+    if let Some(FnArg::Receiver(receiver)) = sig.inputs.first() {
+        return Err(syn::Error::new(
+            receiver.span(),
+            "add #[subscribe] to the enclosing impl block to subscribe methods",
+        ));
+    }
+
+    let topic = subscribe_topic(args, &method_name.to_string())?;
+    let (takes_topic, event_type) = handler_params(sig.inputs.iter().collect(), sig)?;
+    let topic_arg = takes_topic.then(|| quote! { topic, });
+    let topic_binding = takes_topic.then(|| quote! { let topic = ctx.topic.as_str(); });
+    let returns_result = !matches!(sig.output, ReturnType::Default);
+    let await_call = sig.asyncness.map(|_| quote! { .await });
+    let body = handler_body(
+        quote! { #method_name(#topic_arg event) #await_call },
+        returns_result,
+    );
 
     // Generate subscriber struct name
-    let subscriber_name = quote::format_ident!(
+    let subscriber_name = format_ident!(
         "{}Subscriber",
         method_name
             .to_string()
@@ -36,87 +192,115 @@ pub fn subscribe_impl(args: TokenStream, input: ItemFn) -> TokenStream {
             })
             .collect::<String>()
     );
+    let vis = &method.vis;
 
-    let expanded = quote! {
+    Ok(quote! {
         #method
 
-        /// Auto-generated subscriber for this method
-        pub struct #subscriber_name<T> {
-            handler: T,
-        }
+        /// Auto-generated subscriber for this function
+        #[derive(Debug, Default, Clone, Copy)]
+        #vis struct #subscriber_name;
 
-        impl<T> #subscriber_name<T> {
-            pub fn new(handler: T) -> Self {
-                Self { handler }
+        impl #subscriber_name {
+            pub fn new() -> Self {
+                Self
             }
         }
 
         #[::hsipc::async_trait]
-        impl<T> ::hsipc::Subscriber for #subscriber_name<T>
-        where
-            T: Send + Sync + 'static,
-        {
+        impl ::hsipc::Subscriber for #subscriber_name {
             fn topic_pattern(&self) -> &str {
                 #topic
             }
 
-            async fn handle(&mut self, _topic: &str, payload: Vec<u8>) -> ::hsipc::Result<()> {
-                let event: #event_type = ::bincode::deserialize(&payload)?;
-                // Call the actual handler method
-                // Note: This is a simplified version - in practice we'd need to
-                // capture the self context and call the method properly
-                Ok(())
+            async fn handle(&mut self, topic: &str, payload: Vec<u8>) -> ::hsipc::Result<()> {
+                self.handle_event(&::hsipc::EventContext::new(topic), payload).await
+            }
+
+            // Decode through the context so registered upcasters apply
+            async fn handle_event(
+                &mut self,
+                ctx: &::hsipc::EventContext,
+                payload: Vec<u8>,
+            ) -> ::hsipc::Result<()> {
+                let event: #event_type = ctx.decode(&payload)?;
+                #topic_binding
+                #body
             }
         }
-    };
+    })
+}
 
-    TokenStream::from(expanded)
+/// The topic expression from macro arguments, or one derived from the method name
+fn subscribe_topic(
+    args: proc_macro2::TokenStream,
+    method_name: &str,
+) -> syn::Result<proc_macro2::TokenStream> {
+    match SubscribeArgs::parse(args)?.topic {
+        // A string literal or any `&str` expression, such as `DeviceStatus::PATTERN`
+        Some(topic) => Ok(quote! { #topic }),
+        None => {
+            // Methods like on_temperature subscribe to events/temperature
+            let event_name = method_name.strip_prefix("on_").unwrap_or(method_name);
+            let topic = format!("events/{event_name}");
+            Ok(quote! { #topic })
+        }
+    }
 }
 
-/// Parse topic from macro arguments
-fn parse_subscribe_topic(args: TokenStream) -> Option<String> {
-    if args.is_empty() {
-        return None;
+/// Split handler parameters into an optional leading `topic: &str` and the event type
+fn handler_params(params: Vec<&FnArg>, sig: &syn::Signature) -> syn::Result<(bool, Type)> {
+    let typed: Vec<&PatType> = params
+        .iter()
+        .filter_map(|param| match param {
+            FnArg::Typed(pat_type) => Some(pat_type),
+            FnArg::Receiver(_) => None,
+        })
+        .collect();
+
+    match typed.as_slice() {
+        [event] => Ok((false, (*event.ty).clone())),
+        [topic, event] if is_str_ref(&topic.ty) => Ok((true, (*event.ty).clone())),
+        [topic, _] => Err(syn::Error::new(
+            topic.span(),
+            "the topic parameter of a subscriber must be `&str`",
+        )),
+        _ => Err(syn::Error::new(
+            sig.inputs.span(),
+            "subscribers take the event, optionally preceded by `topic: &str`",
+        )),
     }
+}
 
-    let args_str = args.to_string();
-    if args_str.starts_with('"') && args_str.ends_with('"') {
-        Some(args_str[1..args_str.len() - 1].to_string())
+/// Name of the generated topic parameter, underscored when the handler ignores it
+fn topic_param(takes_topic: bool) -> proc_macro2::Ident {
+    if takes_topic {
+        format_ident!("topic")
     } else {
-        None
+        format_ident!("_topic")
     }
 }
 
-/// Extract topic from method signature (for methods like on_temperature)
-fn extract_topic_from_method(method: &ItemFn) -> Option<String> {
-    let method_name = method.sig.ident.to_string();
-
-    method_name
-        .strip_prefix("on_")
-        .map(|event_name| format!("events/{event_name}"))
+fn is_str_ref(ty: &Type) -> bool {
+    match ty {
+        Type::Reference(reference) => {
+            matches!(&*reference.elem, Type::Path(path) if path.path.is_ident("str"))
+        }
+        _ => false,
+    }
 }
 
-/// Extract event type from method signature
-fn extract_event_type(method: &ItemFn) -> syn::Type {
-    // Look for the event parameter (usually the last parameter)
-    for input in &method.sig.inputs {
-        if let FnArg::Typed(PatType { ty, .. }) = input {
-            // Skip &self and &mut self
-            if let Type::Reference(type_ref) = ty.as_ref() {
-                if let Type::Path(type_path) = type_ref.elem.as_ref() {
-                    if let Some(segment) = type_path.path.segments.last() {
-                        if segment.ident == "Self" {
-                            continue;
-                        }
-                    }
-                }
-            }
-
-            // This is likely the event type
-            return *ty.clone();
+/// Handler body calling the subscriber, converting `()` returns into `Ok(())`
+fn handler_body(call: proc_macro2::TokenStream, returns_result: bool) -> proc_macro2::TokenStream {
+    if returns_result {
+        quote! {
+            #call?;
+            Ok(())
+        }
+    } else {
+        quote! {
+            #call;
+            Ok(())
         }
     }
-
-    // Fallback to a generic type
-    syn::parse_str("()").unwrap()
 }
@@ -0,0 +1,80 @@
+//! 阻塞客户端测试 - 同步调用不会在 tokio 运行时中死锁
+
+use hsipc::{method, rpc, ProcessHub, SyncProcessHub};
+use std::time::{Duration, Instant};
+
+#[rpc(server, client, namespace = "clock")]
+pub trait Clock {
+    #[method(name = "now")]
+    async fn now(&self) -> hsipc::Result<u64>;
+
+    #[method(name = "sleep", sync)]
+    fn sleep(&self, millis: u64) -> hsipc::Result<u64>;
+}
+
+pub struct ClockImpl;
+
+#[hsipc::async_trait]
+impl Clock for ClockImpl {
+    async fn now(&self) -> hsipc::Result<u64> {
+        Ok(42)
+    }
+
+    fn sleep(&self, millis: u64) -> hsipc::Result<u64> {
+        std::thread::sleep(Duration::from_millis(millis));
+        Ok(millis)
+    }
+}
+
+#[test]
+fn test_blocking_client_outside_runtime() {
+    let hub = SyncProcessHub::new("blocking_client_plain").unwrap();
+    hub.register_service(ClockService::new(ClockImpl)).unwrap();
+
+    let client = ClockBlockingClient::new(hub.clone());
+    assert_eq!(client.now().unwrap(), 42);
+    assert_eq!(client.sleep(10).unwrap(), 10);
+}
+
+#[tokio::test]
+async fn test_blocking_client_inside_runtime() {
+    // Calling the blocking client from async code must neither panic nor deadlock
+    let hub = SyncProcessHub::new("blocking_client_nested").unwrap();
+    hub.register_service(ClockService::new(ClockImpl)).unwrap();
+
+    let client = ClockBlockingClient::new(hub);
+    assert_eq!(client.now().unwrap(), 42);
+
+    let client = client.clone();
+    let slept = tokio::task::spawn_blocking(move || client.sleep(5).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(slept, 5);
+}
+
+#[tokio::test]
+async fn test_sync_methods_run_on_blocking_pool() {
+    let hub = ProcessHub::new("blocking_pool_server").await.unwrap();
+    hub.register_service(ClockService::new(ClockImpl))
+        .await
+        .unwrap();
+    let client = ClockClient::new(hub.clone());
+
+    // On the single-threaded test runtime a sync method run inline would hold
+    // up the other call for its whole duration
+    let start = Instant::now();
+    let (slow, fast) = tokio::join!(
+        async {
+            client.sleep(300).await.unwrap();
+            start.elapsed()
+        },
+        async {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            client.now().await.unwrap();
+            start.elapsed()
+        }
+    );
+    assert!(fast < slow, "fast call took {fast:?}, slow call {slow:?}");
+
+    hub.shutdown().await.unwrap();
+}
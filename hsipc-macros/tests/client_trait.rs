@@ -0,0 +1,98 @@
+//! client_trait 测试 - 客户端实现服务 trait，可与本地实现互换
+
+use hsipc::{method, rpc, subscription, PendingSubscriptionSink, ProcessHub};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct GreetError(pub String);
+
+impl std::fmt::Display for GreetError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for GreetError {}
+
+impl From<GreetError> for hsipc::Error {
+    fn from(err: GreetError) -> Self {
+        hsipc::Error::runtime_msg(err.0)
+    }
+}
+
+impl From<hsipc::Error> for GreetError {
+    fn from(err: hsipc::Error) -> Self {
+        GreetError(err.to_string())
+    }
+}
+
+#[rpc(server, client, client_trait, namespace = "greeter")]
+pub trait Greeter {
+    #[method(name = "greet")]
+    async fn greet(&self, name: String) -> std::result::Result<String, GreetError>;
+
+    #[method(name = "greet_many")]
+    async fn greet_many(&self, names: Vec<String>, separator: String) -> hsipc::Result<String>;
+
+    #[subscription(name = "greetings", item = String)]
+    async fn subscribe_greetings(&self) -> std::result::Result<(), GreetError>;
+}
+
+pub struct LocalGreeter;
+
+#[hsipc::async_trait]
+impl Greeter for LocalGreeter {
+    async fn greet(&self, name: String) -> std::result::Result<String, GreetError> {
+        if name.is_empty() {
+            return Err(GreetError("empty name".to_string()));
+        }
+        Ok(format!("hello {name}"))
+    }
+
+    async fn greet_many(&self, names: Vec<String>, separator: String) -> hsipc::Result<String> {
+        Ok(names
+            .iter()
+            .map(|name| format!("hello {name}"))
+            .collect::<Vec<_>>()
+            .join(&separator))
+    }
+
+    async fn subscribe_greetings(
+        &self,
+        _pending: PendingSubscriptionSink,
+    ) -> std::result::Result<(), GreetError> {
+        Ok(())
+    }
+}
+
+/// Application code written against the trait only
+async fn welcome(greeter: Arc<dyn Greeter>) -> String {
+    let one = greeter.greet("ada".to_string()).await.unwrap();
+    let many = greeter
+        .greet_many(vec!["bob".to_string(), "cy".to_string()], ", ".to_string())
+        .await
+        .unwrap();
+    format!("{one}; {many}")
+}
+
+#[tokio::test]
+async fn test_client_and_local_impl_are_interchangeable() {
+    let hub = ProcessHub::new("client_trait_hub").await.unwrap();
+    hub.register_service(GreeterService::new(LocalGreeter))
+        .await
+        .unwrap();
+
+    let local: Arc<dyn Greeter> = Arc::new(LocalGreeter);
+    let remote: Arc<dyn Greeter> = Arc::new(GreeterClient::new(hub.clone()));
+
+    let expected = "hello ada; hello bob, hello cy";
+    assert_eq!(welcome(local).await, expected);
+    assert_eq!(welcome(remote.clone()).await, expected);
+
+    // Errors from the hub are converted into the trait's error type
+    let err = remote.greet(String::new()).await.unwrap_err();
+    assert!(err.0.contains("empty name"), "{err}");
+
+    hub.shutdown().await.unwrap();
+}
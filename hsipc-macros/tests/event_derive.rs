@@ -0,0 +1,165 @@
+//! Event derive 测试 - 动态主题与 schema 版本
+
+use hsipc::Event;
+use serde::{Deserialize, Serialize};
+
+#[derive(Event, Serialize, Deserialize, Debug, Clone)]
+#[event(topic = "sensor/version/temperature")]
+pub struct UnversionedEvent {
+    pub value: f64,
+}
+
+#[derive(Event, Serialize, Deserialize, Debug, Clone)]
+#[event(topic = "sensor/version/temperature", version = 3)]
+pub struct VersionedEvent {
+    pub value: f64,
+    pub unit: String,
+}
+
+/// Topics without placeholders get no helpers, so this name stays free
+#[allow(dead_code)]
+pub struct UnversionedEventTopic;
+
+#[test]
+fn test_event_version_defaults_to_zero() {
+    assert_eq!(UnversionedEvent::VERSION, 0);
+}
+
+#[test]
+fn test_event_version_attribute() {
+    assert_eq!(VersionedEvent::VERSION, 3);
+
+    // A topic mentioning "version" is not mistaken for the version key
+    let event = VersionedEvent {
+        value: 21.5,
+        unit: "C".to_string(),
+    };
+    assert_eq!(event.topic(), "sensor/version/temperature");
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Device {
+    pub id: String,
+    pub zone: u32,
+}
+
+#[derive(Event, Serialize, Deserialize, Debug, Clone)]
+#[event(topic = "device/{device.id}/zone/{device.zone}")]
+pub struct DeviceMoved {
+    pub device: Device,
+}
+
+#[derive(Event, Serialize, Deserialize, Debug, Clone)]
+#[event(topic = "sensor/{0}/reading/{1}")]
+pub struct Reading(pub String, pub u32);
+
+#[derive(Event, Serialize, Deserialize, Debug, Clone)]
+#[event(topic = "order/{id}/changed")]
+pub enum OrderEvent {
+    #[event(topic = "order/{id}/created")]
+    Created {
+        id: u64,
+        total: f64,
+    },
+    #[event(topic = "order/{0}/cancelled")]
+    Cancelled(u64),
+    Updated {
+        id: u64,
+    },
+    #[event(topic = "order/purged")]
+    Purged,
+}
+
+#[test]
+fn test_nested_placeholders() {
+    let event = DeviceMoved {
+        device: Device {
+            id: "sensor-1".to_string(),
+            zone: 4,
+        },
+    };
+    assert_eq!(event.topic(), "device/sensor-1/zone/4");
+}
+
+#[test]
+fn test_tuple_placeholders() {
+    let event = Reading("kitchen".to_string(), 2);
+    assert_eq!(event.topic(), "sensor/kitchen/reading/2");
+}
+
+#[test]
+fn test_enum_variant_topics() {
+    let created = OrderEvent::Created { id: 1, total: 9.5 };
+    assert_eq!(created.topic(), "order/1/created");
+    assert_eq!(OrderEvent::Cancelled(2).topic(), "order/2/cancelled");
+
+    // Variants without their own topic fall back to the type's topic
+    assert_eq!(OrderEvent::Updated { id: 3 }.topic(), "order/3/changed");
+    assert_eq!(OrderEvent::Purged.topic(), "order/purged");
+}
+
+#[derive(Event, Serialize, Deserialize, Debug, Clone)]
+#[event(topic = "device/{device_id}/status")]
+pub struct DeviceStatus {
+    pub device_id: String,
+    pub online: bool,
+}
+
+#[derive(Event, Serialize, Deserialize, Debug, Clone)]
+#[event(topic = "site/{site}/rack-{rack}.{slot}/power")]
+pub struct RackPower {
+    pub site: String,
+    pub rack: u32,
+    pub slot: u32,
+    pub watts: f64,
+}
+
+#[test]
+fn test_topic_pattern_and_builder() {
+    assert_eq!(DeviceStatus::PATTERN, "device/+/status");
+    assert_eq!(
+        DeviceStatus::topic_for("sensor-1"),
+        "device/sensor-1/status"
+    );
+
+    let event = DeviceStatus {
+        device_id: "sensor-1".to_string(),
+        online: true,
+    };
+    assert_eq!(DeviceStatus::topic_for(&event.device_id), event.topic());
+
+    assert_eq!(DeviceMoved::PATTERN, "device/+/zone/+");
+    assert_eq!(Reading::topic_for("hall", 3), "sensor/hall/reading/3");
+}
+
+#[test]
+fn test_parse_topic() {
+    let fields = DeviceStatus::parse_topic("device/sensor-1/status").unwrap();
+    assert_eq!(fields.device_id, "sensor-1");
+    assert!(DeviceStatus::parse_topic("device/sensor-1/online").is_none());
+    assert!(DeviceStatus::parse_topic("device/sensor-1/status/extra").is_none());
+
+    let fields = DeviceMoved::parse_topic("device/sensor-2/zone/4").unwrap();
+    assert_eq!(fields.device_id, "sensor-2");
+    assert_eq!(fields.device_zone, "4");
+
+    let fields = Reading::parse_topic("sensor/hall/reading/3").unwrap();
+    assert_eq!(
+        (fields.field0.as_str(), fields.field1.as_str()),
+        ("hall", "3")
+    );
+
+    // Placeholders sharing a level are split on the text between them
+    assert_eq!(RackPower::PATTERN, "site/+/+/power");
+    let topic = RackPower::topic_for("ams", 12, 3);
+    assert_eq!(topic, "site/ams/rack-12.3/power");
+    assert_eq!(
+        RackPower::parse_topic(&topic),
+        Some(RackPowerTopic {
+            site: "ams".to_string(),
+            rack: "12".to_string(),
+            slot: "3".to_string(),
+        })
+    );
+    assert!(RackPower::parse_topic("site/ams/shelf-12.3/power").is_none());
+}
@@ -142,7 +142,7 @@ mod tests {
         assert_eq!(response.result, 20);
 
         // 2. 测试同步方法
-        let result = client.sync_method(5).unwrap();
+        let result = client.sync_method(5).await.unwrap();
         assert_eq!(result, 6);
 
         // 3. 测试多参数方法
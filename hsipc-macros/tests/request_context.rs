@@ -0,0 +1,94 @@
+//! RequestContext 测试 - #[rpc] 方法通过 ctx 参数获取调用方信息
+
+use hsipc::{method, rpc, Priority, ProcessHub, RequestContext};
+
+#[rpc(server, client, mock, namespace = "ctx_audit")]
+pub trait Audit {
+    #[method(name = "whoami")]
+    async fn whoami(&self, ctx: &RequestContext) -> hsipc::Result<String>;
+
+    #[method(name = "record")]
+    async fn record(
+        &self,
+        ctx: &RequestContext,
+        action: String,
+        amount: u32,
+    ) -> hsipc::Result<String>;
+
+    #[method(name = "deadline_secs", sync)]
+    fn deadline_secs(&self, ctx: &RequestContext) -> hsipc::Result<Option<u64>>;
+
+    #[method(name = "short_deadline_secs", sync, timeout = 5000)]
+    fn short_deadline_secs(&self, ctx: &RequestContext) -> hsipc::Result<Option<u64>>;
+
+    #[method(name = "plain")]
+    async fn plain(&self, value: u32) -> hsipc::Result<u32>;
+}
+
+pub struct AuditImpl;
+
+#[hsipc::async_trait]
+impl Audit for AuditImpl {
+    async fn whoami(&self, ctx: &RequestContext) -> hsipc::Result<String> {
+        assert_eq!(ctx.priority, Priority::Normal);
+        assert_eq!(ctx.correlation_id, Some(ctx.message_id));
+        Ok(ctx.caller.clone())
+    }
+
+    async fn record(
+        &self,
+        ctx: &RequestContext,
+        action: String,
+        amount: u32,
+    ) -> hsipc::Result<String> {
+        Ok(format!("{} {action} {amount}", ctx.caller))
+    }
+
+    fn deadline_secs(&self, ctx: &RequestContext) -> hsipc::Result<Option<u64>> {
+        Ok(ctx.remaining().map(|remaining| remaining.as_secs()))
+    }
+
+    fn short_deadline_secs(&self, ctx: &RequestContext) -> hsipc::Result<Option<u64>> {
+        self.deadline_secs(ctx)
+    }
+
+    async fn plain(&self, value: u32) -> hsipc::Result<u32> {
+        Ok(value + 1)
+    }
+}
+
+#[tokio::test]
+async fn test_methods_receive_request_context() {
+    let hub = ProcessHub::new("request_context_hub").await.unwrap();
+    hub.register_service(AuditService::new(AuditImpl))
+        .await
+        .unwrap();
+
+    // Clients don't pass the context, the service side builds it
+    let client = AuditClient::new(hub.clone());
+    assert_eq!(client.whoami().await.unwrap(), "request_context_hub");
+    assert_eq!(
+        client.record("deposit".to_string(), 5).await.unwrap(),
+        "request_context_hub deposit 5"
+    );
+    let secs = client.deadline_secs().await.unwrap().unwrap();
+    assert!(secs > 20 && secs <= 30, "{secs}");
+
+    // `#[method(timeout = ms)]` replaces the clients' default timeout
+    let secs = client.short_deadline_secs().await.unwrap().unwrap();
+    assert!(secs <= 5, "{secs}");
+    assert_eq!(client.plain(1).await.unwrap(), 2);
+
+    // Mocks keep the context parameter but match on the sent parameters only
+    let ctx = RequestContext::new("local");
+    let mock = MockAudit::new();
+    mock.expect_record()
+        .with(|(action, _)| action == "withdraw")
+        .returning(|(action, amount)| Ok(format!("mock {action} {amount}")));
+    assert_eq!(
+        mock.record(&ctx, "withdraw".to_string(), 3).await.unwrap(),
+        "mock withdraw 3"
+    );
+
+    hub.shutdown().await.unwrap();
+}
@@ -0,0 +1,83 @@
+//! #[rpc] 参数测试 - server/client 标志与默认命名空间
+
+use hsipc::{method, rpc, RequestContext, Service};
+
+pub mod interface {
+    use super::*;
+
+    /// Client-only: no `InventoryService` is generated, so this one doesn't clash
+    #[rpc(client)]
+    pub trait Inventory {
+        #[method(name = "count")]
+        async fn count(&self, sku: String) -> hsipc::Result<u32>;
+    }
+
+    pub struct InventoryService;
+}
+
+pub mod server {
+    use super::*;
+
+    /// Server-only: no `InventoryClient` is generated
+    #[rpc(server)]
+    pub trait Inventory {
+        #[method(name = "count")]
+        async fn count(&self, sku: String) -> hsipc::Result<u32>;
+    }
+
+    pub struct InventoryClient;
+    pub struct InventoryBlockingClient;
+
+    pub struct Warehouse;
+
+    #[hsipc::async_trait]
+    impl Inventory for Warehouse {
+        async fn count(&self, sku: String) -> hsipc::Result<u32> {
+            Ok(sku.len() as u32)
+        }
+    }
+}
+
+pub mod both {
+    use super::*;
+
+    /// Without flags both sides are generated, under the trait's snake-case name
+    #[rpc]
+    pub trait UserDirectory {
+        #[method(name = "lookup")]
+        async fn lookup(&self, id: u64) -> hsipc::Result<String>;
+    }
+
+    pub struct Directory;
+
+    #[hsipc::async_trait]
+    impl UserDirectory for Directory {
+        async fn lookup(&self, id: u64) -> hsipc::Result<String> {
+            Ok(format!("user-{id}"))
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_server_only_and_default_namespace() {
+    let service = server::InventoryService::new(server::Warehouse);
+    assert_eq!(service.name(), "inventory");
+    let response = service
+        .handle(
+            &RequestContext::new("test"),
+            "count",
+            bincode::serialize("abc").unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(bincode::deserialize::<u32>(&response).unwrap(), 3);
+
+    let service = both::UserDirectoryService::new(both::Directory);
+    assert_eq!(service.name(), "user_directory");
+    let _client: Option<both::UserDirectoryClient> = None;
+
+    // The hand-written types stand where generated ones would clash
+    let _ = (interface::InventoryService, server::InventoryClient);
+    let _ = server::InventoryBlockingClient;
+    let _client: Option<interface::InventoryClient> = None;
+}
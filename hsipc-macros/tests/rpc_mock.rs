@@ -0,0 +1,112 @@
+//! mock 测试 - #[rpc(mock)] 生成的 MockXxx 可替代客户端或注册为服务
+
+use hsipc::{method, rpc, subscription, PendingSubscriptionSink, ProcessHub};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Reading {
+    pub sensor: String,
+    pub value: f64,
+}
+
+#[rpc(mock, namespace = "mock_sensors")]
+pub trait Sensors {
+    #[method(name = "read")]
+    async fn read(&self, sensor: String) -> hsipc::Result<Reading>;
+
+    #[method(name = "calibrate")]
+    async fn calibrate(&self, sensor: String, offset: f64) -> hsipc::Result<()>;
+
+    #[method(name = "count", sync)]
+    fn count(&self) -> hsipc::Result<u32>;
+
+    #[subscription(name = "readings", item = Reading)]
+    async fn subscribe_readings(&self, sensor: String) -> hsipc::Result<()>;
+}
+
+/// Application code written against the trait only
+async fn average(sensors: Arc<dyn Sensors>, names: &[&str]) -> f64 {
+    let mut total = 0.0;
+    for name in names {
+        total += sensors.read(name.to_string()).await.unwrap().value;
+    }
+    total / names.len() as f64
+}
+
+#[tokio::test]
+async fn test_mock_stands_in_for_the_client() {
+    let mock = MockSensors::new();
+    mock.expect_read()
+        .with(|(sensor,)| sensor == "a")
+        .returning(|(sensor,)| Ok(Reading { sensor, value: 1.0 }));
+    let others = mock
+        .expect_read()
+        .returning(|(sensor,)| Ok(Reading { sensor, value: 3.0 }));
+
+    assert_eq!(
+        average(Arc::new(mock.clone()), &["a", "b", "c"]).await,
+        7.0 / 3.0
+    );
+    assert_eq!(mock.read_calls(), 3);
+    assert_eq!(others.calls(), 2);
+
+    mock.expect_calibrate()
+        .with(|(sensor, offset)| sensor == "a" && *offset < 0.0)
+        .times(1)
+        .return_ok(());
+    mock.expect_count().return_ok(7);
+    mock.calibrate("a".to_string(), -0.5).await.unwrap();
+    assert_eq!(mock.count().unwrap(), 7);
+    mock.checkpoint();
+}
+
+#[tokio::test]
+async fn test_mock_registers_as_a_service() {
+    let hub = ProcessHub::new("rpc_mock_service_hub").await.unwrap();
+    let mock = MockSensors::new();
+    let calibrations = mock.expect_calibrate().times(2).returning(|(_, offset)| {
+        if offset.abs() > 1.0 {
+            Err(hsipc::Error::invalid_request("offset out of range", None))
+        } else {
+            Ok(())
+        }
+    });
+    hub.register_service(SensorsService::new(mock.clone()))
+        .await
+        .unwrap();
+
+    let client = SensorsClient::new(hub.clone());
+    client.calibrate("a".to_string(), 0.5).await.unwrap();
+    let err = client.calibrate("a".to_string(), 5.0).await.unwrap_err();
+    assert!(err.to_string().contains("offset out of range"), "{err}");
+
+    assert_eq!(calibrations.calls(), 2);
+    mock.checkpoint();
+
+    hub.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+#[should_panic(expected = "`calibrate` expected 1 call(s), got 0")]
+async fn test_mock_checkpoint_reports_missing_calls() {
+    let mock = MockSensors::new();
+    mock.expect_calibrate().times(1).return_ok(());
+    mock.checkpoint();
+}
+
+#[tokio::test]
+async fn test_mock_subscription_records_parameters() {
+    let mock = MockSensors::new();
+    mock.expect_subscribe_readings()
+        .with(|(sensor,)| sensor == "a")
+        .return_ok(());
+
+    let (sender, _receiver) = tokio::sync::mpsc::unbounded_channel();
+    let pending =
+        PendingSubscriptionSink::new(uuid::Uuid::new_v4(), "readings".to_string(), sender);
+    mock.subscribe_readings(pending, "a".to_string())
+        .await
+        .unwrap();
+    assert_eq!(mock.subscribe_readings_calls(), 1);
+}
@@ -0,0 +1,198 @@
+//! RPC 参数测试 - 多参数方法通过命名参数结构体编码
+
+use hsipc::{method, rpc, ProcessHub, RequestContext, Service};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct GeometryError {
+    pub message: String,
+}
+
+impl std::fmt::Display for GeometryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for GeometryError {}
+
+impl From<GeometryError> for hsipc::Error {
+    fn from(err: GeometryError) -> Self {
+        hsipc::Error::runtime_msg(err.message)
+    }
+}
+
+#[rpc(server, client, namespace = "geometry")]
+pub trait Geometry {
+    #[method(name = "volume")]
+    async fn volume(
+        &self,
+        width: u32,
+        height: u32,
+        depth: u32,
+    ) -> std::result::Result<u32, GeometryError>;
+
+    #[method(name = "describe")]
+    async fn describe(
+        &self,
+        name: String,
+        sides: u8,
+        scale: f64,
+        #[serde(default)] unit: Option<String>,
+    ) -> std::result::Result<String, GeometryError>;
+
+    #[method(name = "square")]
+    async fn square(
+        &self,
+        side: u32,
+        #[serde(default)] unit: Option<String>,
+    ) -> std::result::Result<String, GeometryError>;
+}
+
+/// The interface before `unit` was added, as built into older callers
+pub mod old {
+    use super::*;
+
+    #[rpc(client, namespace = "geometry")]
+    pub trait Geometry {
+        #[method(name = "describe")]
+        async fn describe(
+            &self,
+            name: String,
+            sides: u8,
+            scale: f64,
+        ) -> std::result::Result<String, GeometryError>;
+
+        #[method(name = "square")]
+        async fn square(&self, side: u32) -> std::result::Result<String, GeometryError>;
+    }
+}
+
+pub struct GeometryImpl;
+
+#[hsipc::async_trait]
+impl Geometry for GeometryImpl {
+    async fn volume(
+        &self,
+        width: u32,
+        height: u32,
+        depth: u32,
+    ) -> std::result::Result<u32, GeometryError> {
+        Ok(width * height * depth)
+    }
+
+    async fn describe(
+        &self,
+        name: String,
+        sides: u8,
+        scale: f64,
+        unit: Option<String>,
+    ) -> std::result::Result<String, GeometryError> {
+        Ok(format!(
+            "{name}: {sides} sides x{scale} {}",
+            unit.unwrap_or_else(|| "cm".to_string())
+        ))
+    }
+
+    async fn square(
+        &self,
+        side: u32,
+        unit: Option<String>,
+    ) -> std::result::Result<String, GeometryError> {
+        Ok(format!(
+            "{} {}2",
+            side * side,
+            unit.unwrap_or_else(|| "cm".to_string())
+        ))
+    }
+}
+
+#[tokio::test]
+async fn test_params_struct_is_named() {
+    let service = GeometryService::new(GeometryImpl);
+
+    // Parameters are encoded as a struct with the trait's parameter names
+    let params = GeometryVolumeParams {
+        width: 2,
+        height: 3,
+        depth: 4,
+    };
+    let response = service
+        .handle(
+            &RequestContext::new("test"),
+            "volume",
+            bincode::serialize(&params).unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(bincode::deserialize::<u32>(&response).unwrap(), 24);
+
+    let params = GeometryDescribeParams {
+        name: "cube".to_string(),
+        sides: 6,
+        unit: None,
+        scale: 1.5,
+    };
+    let response = service
+        .handle(
+            &RequestContext::new("test"),
+            "describe",
+            bincode::serialize(&params).unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(
+        bincode::deserialize::<String>(&response).unwrap(),
+        "cube: 6 sides x1.5 cm"
+    );
+}
+
+#[tokio::test]
+async fn test_client_calls_methods_with_many_parameters() {
+    let hub = ProcessHub::new("rpc_params_hub").await.unwrap();
+    hub.register_service(GeometryService::new(GeometryImpl))
+        .await
+        .unwrap();
+
+    let client = GeometryClient::new(hub.clone());
+    assert_eq!(client.volume(5, 6, 7).await.unwrap(), 210);
+    assert_eq!(
+        client
+            .describe("prism".to_string(), 5, 2.0, Some("mm".to_string()))
+            .await
+            .unwrap(),
+        "prism: 5 sides x2 mm"
+    );
+
+    hub.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_older_callers_leave_out_trailing_default_parameters() {
+    let hub = ProcessHub::new("rpc_params_old_caller").await.unwrap();
+    hub.register_service(GeometryService::new(GeometryImpl))
+        .await
+        .unwrap();
+
+    // The old client sends no `unit`, so the service sees its default
+    let client = old::GeometryClient::new(hub.clone());
+    assert_eq!(
+        client.describe("pyramid".to_string(), 4, 3.0).await.unwrap(),
+        "pyramid: 4 sides x3 cm"
+    );
+
+    // A method that had a single parameter is encoded as that parameter alone
+    assert_eq!(client.square(3).await.unwrap(), "9 cm2");
+    let service = GeometryService::new(GeometryImpl);
+    let response = service
+        .handle(
+            &RequestContext::new("test"),
+            "square",
+            bincode::serialize(&4u32).unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(bincode::deserialize::<String>(&response).unwrap(), "16 cm2");
+
+    hub.shutdown().await.unwrap();
+}
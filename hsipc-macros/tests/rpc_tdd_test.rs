@@ -166,8 +166,8 @@ mod tests {
 
         let client = CalculatorClient::new(hub);
 
-        // Sync method call - should not need .await
-        let _result = client.multiply(6, 7).unwrap();
+        // Sync trait methods run on the server's blocking pool; the async client awaits them
+        let _result = client.multiply(6, 7).await.unwrap();
         // For now, just verify the call succeeds
     }
 
@@ -0,0 +1,123 @@
+//! #[subscribe] 宏测试 - impl 块中的订阅方法和订阅函数
+
+use hsipc::{subscribe, Event, ProcessHub};
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+#[derive(Event, Serialize, Deserialize, Debug, Clone)]
+#[event(topic = "subscribe_macro/{room}/temperature")]
+pub struct Temperature {
+    pub room: String,
+    pub value: f64,
+}
+
+#[derive(Event, Serialize, Deserialize, Debug, Clone)]
+#[event(topic = "subscribe_macro/humidity")]
+pub struct HumidityV1 {
+    pub percent: u8,
+}
+
+#[derive(Event, Serialize, Deserialize, Debug, Clone)]
+#[event(topic = "subscribe_macro/humidity", version = 2)]
+pub struct Humidity {
+    pub ratio: f64,
+}
+
+static HUMIDITY: Mutex<Vec<(String, f64)>> = Mutex::new(Vec::new());
+
+#[subscribe("subscribe_macro/humidity")]
+async fn on_humidity(topic: &str, event: Humidity) {
+    HUMIDITY
+        .lock()
+        .unwrap()
+        .push((topic.to_string(), event.ratio));
+}
+
+#[derive(Default)]
+struct Thermostat {
+    readings: Mutex<Vec<(String, f64)>>,
+    alerts: Mutex<Vec<f64>>,
+}
+
+#[subscribe]
+impl Thermostat {
+    #[subscribe(Temperature::PATTERN)]
+    async fn on_temperature(&self, topic: &str, event: Temperature) {
+        let room = Temperature::parse_topic(topic).unwrap().room;
+        self.readings.lock().unwrap().push((room, event.value));
+    }
+
+    #[subscribe("subscribe_macro/+/temperature")]
+    fn check_alert(&self, event: Temperature) -> hsipc::Result<()> {
+        if event.value > 30.0 {
+            self.alerts.lock().unwrap().push(event.value);
+        }
+        Ok(())
+    }
+
+    // Methods without #[subscribe] are left alone
+    fn reading_count(&self) -> usize {
+        self.readings.lock().unwrap().len()
+    }
+}
+
+#[tokio::test]
+async fn test_register_subscribers_dispatches_to_methods() {
+    let hub = ProcessHub::new("subscribe_macro_hub").await.unwrap();
+    let thermostat = Arc::new(Thermostat::default());
+
+    let subscriptions = thermostat.clone().register_subscribers(&hub).await.unwrap();
+    assert_eq!(subscriptions.len(), 2);
+
+    for (room, value) in [("kitchen", 21.5), ("attic", 34.0)] {
+        let event = Temperature {
+            room: room.to_string(),
+            value,
+        };
+        hub.publish_event(event).await.unwrap();
+    }
+    tokio::time::sleep(Duration::from_millis(300)).await;
+
+    assert_eq!(thermostat.reading_count(), 2);
+    assert_eq!(
+        *thermostat.readings.lock().unwrap(),
+        vec![("kitchen".to_string(), 21.5), ("attic".to_string(), 34.0)]
+    );
+    assert_eq!(*thermostat.alerts.lock().unwrap(), vec![34.0]);
+
+    // Dropping the subscriptions stops delivery
+    drop(subscriptions);
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    let event = Temperature {
+        room: "hall".to_string(),
+        value: 40.0,
+    };
+    hub.publish_event(event).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert_eq!(thermostat.reading_count(), 2);
+
+    hub.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_subscriber_functions_upcast_older_versions() {
+    let hub = ProcessHub::new("subscribe_fn_hub").await.unwrap();
+    hub.register_upcaster(0, |old: HumidityV1| Humidity {
+        ratio: old.percent as f64 / 100.0,
+    })
+    .unwrap();
+    let _subscription = hub.subscribe(OnHumiditySubscriber::new()).await.unwrap();
+
+    hub.publish_event(HumidityV1 { percent: 40 }).await.unwrap();
+    hub.publish_event(Humidity { ratio: 0.55 }).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(300)).await;
+
+    let topic = "subscribe_macro/humidity".to_string();
+    assert_eq!(
+        *HUMIDITY.lock().unwrap(),
+        vec![(topic.clone(), 0.4), (topic, 0.55)]
+    );
+
+    hub.shutdown().await.unwrap();
+}
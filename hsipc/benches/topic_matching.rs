@@ -0,0 +1,64 @@
+//! Topic matching benchmarks
+//!
+//! Compares the topic trie used by the subscription registry with a linear
+//! scan over every registered pattern.
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use hsipc::topic::{topic_matches, TopicTrie};
+
+/// Build device-specific subscriptions plus a few wildcard ones
+fn device_patterns(count: usize) -> Vec<String> {
+    let mut patterns: Vec<String> = (0..count).map(|i| format!("device/{i}/status")).collect();
+    patterns.push("device/+/status".to_string());
+    patterns.push("device/#".to_string());
+    patterns.push("sensor/+/temperature".to_string());
+    patterns
+}
+
+fn benchmark_topic_lookup(c: &mut Criterion) {
+    let mut group = c.benchmark_group("topic_lookup");
+
+    for count in [100, 1_000, 10_000].iter() {
+        let patterns = device_patterns(*count);
+        let topic = format!("device/{}/status", count / 2);
+
+        let scan: Vec<(String, usize)> = patterns
+            .iter()
+            .cloned()
+            .enumerate()
+            .map(|(i, pattern)| (pattern, i))
+            .collect();
+
+        let mut trie = TopicTrie::new();
+        for (i, pattern) in patterns.iter().enumerate() {
+            trie.insert(pattern, i);
+        }
+
+        group.bench_with_input(
+            BenchmarkId::new("linear_scan", count),
+            &topic,
+            |b, topic| {
+                b.iter(|| {
+                    let matched: Vec<usize> = scan
+                        .iter()
+                        .filter(|(pattern, _)| topic_matches(topic, pattern))
+                        .map(|(_, id)| *id)
+                        .collect();
+                    black_box(matched);
+                });
+            },
+        );
+
+        group.bench_with_input(BenchmarkId::new("trie", count), &topic, |b, topic| {
+            b.iter(|| {
+                black_box(trie.matches(topic));
+            });
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, benchmark_topic_lookup);
+
+criterion_main!(benches);
@@ -0,0 +1,223 @@
+//! Per-request information: options set by callers, and the context
+//! service handlers and event subscribers receive
+
+use crate::message::{Message, Priority};
+use crate::version::Upcasters;
+use crate::{Event, Result};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use uuid::Uuid;
+
+/// Options for a service call
+#[derive(Debug, Clone)]
+pub struct CallOptions {
+    /// How long to wait for a response, sent to the service as its deadline
+    pub timeout: Duration,
+    pub priority: Priority,
+    /// Custom headers sent with the request
+    pub headers: HashMap<String, String>,
+}
+
+impl Default for CallOptions {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(30),
+            priority: Priority::default(),
+            headers: HashMap::new(),
+        }
+    }
+}
+
+impl CallOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set how long to wait for a response
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Set the request priority
+    pub fn priority(mut self, priority: Priority) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// Add a custom header
+    pub fn header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.insert(name.into(), value.into());
+        self
+    }
+}
+
+/// Who sent a request, and how it should be handled
+///
+/// Built by the hub from the request message and passed to
+/// [`Service::handle`](crate::Service::handle). `#[rpc]` trait methods receive
+/// it by declaring `ctx: &RequestContext` as their first parameter.
+#[derive(Debug, Clone)]
+pub struct RequestContext {
+    /// Name of the calling process
+    pub caller: String,
+
+    /// ID of the request message
+    pub message_id: Uuid,
+
+    /// ID the response will be correlated by
+    pub correlation_id: Option<Uuid>,
+
+    /// When the caller stops waiting for a response
+    pub deadline: Option<SystemTime>,
+
+    pub priority: Priority,
+
+    /// Custom headers attached to the request
+    pub headers: HashMap<String, String>,
+}
+
+impl RequestContext {
+    /// Context of a request from `caller` without a deadline, for calling a `Service` directly
+    pub fn new(caller: impl Into<String>) -> Self {
+        let message_id = Uuid::new_v4();
+        Self {
+            caller: caller.into(),
+            message_id,
+            correlation_id: Some(message_id),
+            deadline: None,
+            priority: Priority::default(),
+            headers: HashMap::new(),
+        }
+    }
+
+    /// Context of a request message, with the deadline taken from its time-to-live
+    pub fn from_message(msg: &Message) -> Self {
+        let deadline = msg.metadata.ttl.map(|ttl| {
+            UNIX_EPOCH + Duration::from_millis(msg.metadata.timestamp.saturating_add(ttl))
+        });
+
+        Self {
+            caller: msg.source.clone(),
+            message_id: msg.id,
+            correlation_id: msg.correlation_id,
+            deadline,
+            priority: msg.metadata.priority,
+            headers: msg.metadata.headers.clone(),
+        }
+    }
+
+    /// Time left until the deadline, zero once it has passed
+    pub fn remaining(&self) -> Option<Duration> {
+        self.deadline.map(|deadline| {
+            deadline
+                .duration_since(SystemTime::now())
+                .unwrap_or(Duration::ZERO)
+        })
+    }
+
+    /// Whether the caller has already given up on this request
+    pub fn is_expired(&self) -> bool {
+        self.remaining() == Some(Duration::ZERO)
+    }
+
+    /// Value of a custom header
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name).map(String::as_str)
+    }
+}
+
+/// Where an event came from, and how it was published
+///
+/// Built by the hub for each delivered event and passed to
+/// [`Subscriber::handle_event`](crate::Subscriber::handle_event), to closures
+/// subscribed with `ProcessHub::on_event`, and alongside the events of
+/// `EventStream::with_context`.
+#[derive(Debug, Clone)]
+pub struct EventContext {
+    /// Topic the event was published on
+    pub topic: String,
+
+    /// Name of the publishing process
+    pub source: String,
+
+    /// ID of the event message
+    pub message_id: Uuid,
+
+    /// Offset in the hub's event store, if the event was stored
+    pub offset: Option<u64>,
+
+    /// Custom headers the event was published with
+    pub headers: HashMap<String, String>,
+
+    /// Schema version the event was published with, 0 for unversioned events
+    pub schema_version: u32,
+
+    /// Upcasters registered with the hub, applied by `decode`
+    pub(crate) upcasters: Arc<Upcasters>,
+}
+
+impl EventContext {
+    /// Context of an unversioned event on `topic` without a source, for
+    /// calling a `Subscriber` directly
+    pub fn new(topic: impl Into<String>) -> Self {
+        Self {
+            topic: topic.into(),
+            source: String::new(),
+            message_id: Uuid::new_v4(),
+            offset: None,
+            headers: HashMap::new(),
+            schema_version: 0,
+            upcasters: Arc::default(),
+        }
+    }
+
+    /// Value of a custom header
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name).map(String::as_str)
+    }
+
+    /// Decode the event payload as `E`
+    ///
+    /// Payloads published with an older schema version go through the
+    /// upcaster registered for it with `ProcessHub::register_upcaster`.
+    pub fn decode<E: Event>(&self, payload: &[u8]) -> Result<E> {
+        self.upcasters.decode(self.schema_version, payload)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_context_from_request_message() {
+        let mut msg = Message::request(
+            "caller".to_string(),
+            "server".to_string(),
+            "calc.add".to_string(),
+            Vec::new(),
+        );
+        msg.metadata.priority = Priority::High;
+        msg.metadata
+            .headers
+            .insert("trace".to_string(), "abc".to_string());
+
+        let ctx = RequestContext::from_message(&msg);
+        assert_eq!(ctx.caller, "caller");
+        assert_eq!(ctx.message_id, msg.id);
+        assert_eq!(ctx.correlation_id, Some(msg.id));
+        assert_eq!(ctx.priority, Priority::High);
+        assert_eq!(ctx.header("trace"), Some("abc"));
+        assert_eq!(ctx.deadline, None);
+        assert!(!ctx.is_expired());
+
+        msg.metadata.ttl = Some(60_000);
+        let remaining = RequestContext::from_message(&msg).remaining().unwrap();
+        assert!(remaining > Duration::from_secs(50) && remaining <= Duration::from_secs(60));
+
+        msg.metadata.timestamp -= 120_000;
+        assert!(RequestContext::from_message(&msg).is_expired());
+    }
+}
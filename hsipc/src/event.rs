@@ -1,16 +1,34 @@
 //! Event trait and subscription system for publish/subscribe pattern
 
-use crate::Result;
+use crate::context::EventContext;
+use crate::failure::Failure;
+use crate::filter::{ContentFilter, EventViews, Filter, JsonView};
+use crate::message::{Message, MessageType};
+use crate::qos::DedupeWindow;
+use crate::queue::{Delivery, DeliveryQueue, OverflowPolicy, PushOutcome};
+use crate::shared::{SharedGroups, SharedMember};
+use crate::store::{EventStore, ReplayFrom};
+use crate::topic::{parse_shared_pattern, validate_topic_filter, validate_topic_name, TopicTrie};
+use crate::version::Upcasters;
+use crate::{Error, Result};
 use async_trait::async_trait;
 use dashmap::DashMap;
+use futures::Stream;
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::future::Future;
+use std::marker::PhantomData;
+use std::pin::Pin;
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use std::task::{Context, Poll};
+use tokio::sync::{mpsc, RwLock};
 use uuid::Uuid;
 
 /// Trait for events that can be published
 pub trait Event: Serialize + for<'de> Deserialize<'de> + Send + Sync + 'static {
+    /// Schema version stamped into published events, 0 for unversioned
+    const VERSION: u32 = 0;
+
     /// Get the topic for this event
     fn topic(&self) -> String;
 }
@@ -23,6 +41,15 @@ pub trait Subscriber: Send + Sync + 'static {
 
     /// Handle an event
     async fn handle(&mut self, topic: &str, payload: Vec<u8>) -> Result<()>;
+
+    /// Handle an event along with its context, such as the headers it was
+    /// published with
+    ///
+    /// Events are delivered through this method; override it to read the
+    /// context.
+    async fn handle_event(&mut self, ctx: &EventContext, payload: Vec<u8>) -> Result<()> {
+        self.handle(&ctx.topic, payload).await
+    }
 }
 
 /// Sync subscriber trait
@@ -32,13 +59,21 @@ pub trait SyncSubscriber: Send + Sync + 'static {
 
     /// Handle an event synchronously
     fn handle_sync(&mut self, topic: &str, payload: Vec<u8>) -> Result<()>;
+
+    /// Handle an event synchronously along with its context
+    fn handle_sync_event(&mut self, ctx: &EventContext, payload: Vec<u8>) -> Result<()> {
+        self.handle_sync(&ctx.topic, payload)
+    }
 }
 
 /// Subscription handle
 pub struct Subscription {
     pub id: Uuid,
     pub topic_pattern: String,
+    queue: Arc<DeliveryQueue>,
     registry: Arc<SubscriptionRegistry>,
+    /// Runtime the subscription was created on, used to unsubscribe on drop
+    runtime: tokio::runtime::Handle,
 }
 
 impl Subscription {
@@ -46,71 +81,632 @@ impl Subscription {
     pub async fn unsubscribe(self) -> Result<()> {
         self.registry.unsubscribe(&self.id).await
     }
+
+    /// Number of events discarded because the subscription queue was full
+    pub fn dropped_count(&self) -> u64 {
+        self.queue.dropped()
+    }
+
+    /// Whether the subscription was closed by the `Disconnect` overflow policy
+    pub fn is_disconnected(&self) -> bool {
+        self.queue.is_disconnected()
+    }
 }
 
 impl Drop for Subscription {
     fn drop(&mut self) {
-        // Queue unsubscribe for later processing
+        // Queue unsubscribe for later processing; the subscription may be
+        // dropped outside the runtime, e.g. by `SyncProcessHub` users
         let id = self.id;
         let registry = self.registry.clone();
-        tokio::spawn(async move {
+        self.runtime.spawn(async move {
             let _ = registry.unsubscribe(&id).await;
         });
     }
 }
 
+/// Subscriber that forwards raw events into a channel
+struct ChannelSubscriber {
+    topic_pattern: String,
+    sender: mpsc::Sender<(EventContext, Vec<u8>)>,
+}
+
+#[async_trait]
+impl Subscriber for ChannelSubscriber {
+    fn topic_pattern(&self) -> &str {
+        &self.topic_pattern
+    }
+
+    async fn handle(&mut self, topic: &str, payload: Vec<u8>) -> Result<()> {
+        self.handle_event(&EventContext::new(topic), payload).await
+    }
+
+    async fn handle_event(&mut self, ctx: &EventContext, payload: Vec<u8>) -> Result<()> {
+        // The stream may already be gone while the unsubscribe is in flight
+        let _ = self.sender.send((ctx.clone(), payload)).await;
+        Ok(())
+    }
+}
+
+/// Subscriber backed by an async closure
+struct FnSubscriber<E, F> {
+    topic_pattern: String,
+    handler: F,
+    _phantom: PhantomData<fn(E)>,
+}
+
+#[async_trait]
+impl<E, F, Fut> Subscriber for FnSubscriber<E, F>
+where
+    E: Event,
+    F: Fn(EventContext, E) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Result<()>> + Send + 'static,
+{
+    fn topic_pattern(&self) -> &str {
+        &self.topic_pattern
+    }
+
+    async fn handle(&mut self, topic: &str, payload: Vec<u8>) -> Result<()> {
+        self.handle_event(&EventContext::new(topic), payload).await
+    }
+
+    async fn handle_event(&mut self, ctx: &EventContext, payload: Vec<u8>) -> Result<()> {
+        let event: E = ctx.decode(&payload)?;
+        (self.handler)(ctx.clone(), event).await
+    }
+}
+
+/// Subscriber backed by a synchronous closure
+///
+/// Subscribed through `SyncSubscriberAdapter`, so the closure runs on the
+/// blocking thread pool.
+struct SyncFnSubscriber<E, F> {
+    topic_pattern: String,
+    handler: F,
+    _phantom: PhantomData<fn(E)>,
+}
+
+impl<E, F> SyncSubscriber for SyncFnSubscriber<E, F>
+where
+    E: Event,
+    F: Fn(&EventContext, E) -> Result<()> + Send + Sync + 'static,
+{
+    fn topic_pattern(&self) -> &str {
+        &self.topic_pattern
+    }
+
+    fn handle_sync(&mut self, topic: &str, payload: Vec<u8>) -> Result<()> {
+        self.handle_sync_event(&EventContext::new(topic), payload)
+    }
+
+    fn handle_sync_event(&mut self, ctx: &EventContext, payload: Vec<u8>) -> Result<()> {
+        let event: E = ctx.decode(&payload)?;
+        (self.handler)(ctx, event)
+    }
+}
+
+/// Stream of typed events for a topic pattern
+///
+/// Yields `(topic, event)` pairs. Dropping the stream unsubscribes.
+pub struct EventStream<E> {
+    receiver: mpsc::Receiver<(EventContext, Vec<u8>)>,
+    subscription: Subscription,
+    _phantom: PhantomData<fn() -> E>,
+}
+
+impl<E> EventStream<E> {
+    /// Get the underlying subscription handle
+    pub fn subscription(&self) -> &Subscription {
+        &self.subscription
+    }
+
+    /// Yield each event with its full [`EventContext`] instead of its topic
+    pub fn with_context(self) -> EventContextStream<E> {
+        EventContextStream { inner: self }
+    }
+}
+
+impl<E: Event> EventStream<E> {
+    fn poll_event(&mut self, cx: &mut Context<'_>) -> Poll<Option<Result<(EventContext, E)>>> {
+        self.receiver.poll_recv(cx).map(|item| {
+            item.map(|(ctx, payload)| ctx.decode::<E>(&payload).map(|event| (ctx, event)))
+        })
+    }
+}
+
+impl<E: Event> Stream for EventStream<E> {
+    type Item = Result<(String, E)>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.poll_event(cx)
+            .map(|item| item.map(|result| result.map(|(ctx, event)| (ctx.topic, event))))
+    }
+}
+
+/// Stream of typed events along with their context
+///
+/// Yields `(context, event)` pairs. Dropping the stream unsubscribes.
+pub struct EventContextStream<E> {
+    inner: EventStream<E>,
+}
+
+impl<E> EventContextStream<E> {
+    /// Get the underlying subscription handle
+    pub fn subscription(&self) -> &Subscription {
+        self.inner.subscription()
+    }
+}
+
+impl<E: Event> Stream for EventContextStream<E> {
+    type Item = Result<(EventContext, E)>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.inner.poll_event(cx)
+    }
+}
+
+/// Options controlling how events are queued for a subscription
+#[derive(Debug, Clone)]
+pub struct SubscribeOptions {
+    /// Maximum number of events queued for the subscriber
+    pub capacity: usize,
+    /// What to do when the queue is full
+    pub overflow: OverflowPolicy,
+    /// Ignore events published by this process
+    pub no_local: bool,
+    /// Replay stored events from the hub's event store before live delivery
+    pub replay: Option<ReplayFrom>,
+    /// Content filter over the event fields, for typed subscriptions
+    pub filter: Option<String>,
+}
+
+impl Default for SubscribeOptions {
+    fn default() -> Self {
+        Self {
+            capacity: 1024,
+            overflow: OverflowPolicy::Block,
+            no_local: false,
+            replay: None,
+            filter: None,
+        }
+    }
+}
+
+impl SubscribeOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the queue capacity
+    pub fn capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity;
+        self
+    }
+
+    /// Set the overflow policy
+    pub fn overflow(mut self, overflow: OverflowPolicy) -> Self {
+        self.overflow = overflow;
+        self
+    }
+
+    /// Ignore events published by this process
+    pub fn no_local(mut self, no_local: bool) -> Self {
+        self.no_local = no_local;
+        self
+    }
+
+    /// Replay stored events from `from` before switching to live delivery
+    pub fn replay(mut self, from: ReplayFrom) -> Self {
+        self.replay = Some(from);
+        self
+    }
+
+    /// Only deliver events passing a content filter such as
+    /// `value > 80 && unit == "C"`
+    ///
+    /// The expression is checked when subscribing. Only typed subscriptions
+    /// can be filtered, since the event type is needed to inspect its fields.
+    pub fn filter(mut self, filter: impl Into<String>) -> Self {
+        self.filter = Some(filter.into());
+        self
+    }
+}
+
+/// Registry entry for an active subscription
+struct SubscriptionEntry {
+    /// Pattern used for the topic index (the filter part of shared patterns)
+    filter: String,
+    queue: Arc<DeliveryQueue>,
+    /// Group membership for shared subscriptions
+    shared: Option<SharedMember>,
+    /// Skip events published by this process
+    no_local: bool,
+    /// Only deliver events whose content passes this filter
+    content: Option<Arc<ContentFilter>>,
+}
+
 /// Subscription registry
 pub struct SubscriptionRegistry {
-    /// Map of subscription ID to subscriber
-    subscribers: Arc<DashMap<Uuid, Box<dyn Subscriber>>>,
+    /// Name of the owning process, used to identify shared group members
+    process_name: String,
+
+    /// Map of subscription ID to its delivery queue
+    subscribers: Arc<DashMap<Uuid, SubscriptionEntry>>,
+
+    /// Index of topic pattern to subscription IDs
+    topic_subscriptions: Arc<RwLock<TopicTrie<Uuid>>>,
+
+    /// Shared subscription groups across all processes
+    shared_groups: Arc<SharedGroups>,
+
+    /// Outgoing membership announcements, forwarded to the bus by the hub
+    announcements: Option<mpsc::UnboundedSender<Message>>,
+
+    /// Subscriber failures, reported to the hub
+    failures: Option<mpsc::UnboundedSender<Failure>>,
+
+    /// Upcasters applied when decoding typed events
+    upcasters: Arc<Upcasters>,
 
-    /// Map of topic pattern to subscription IDs
-    topic_subscriptions: Arc<RwLock<HashMap<String, Vec<Uuid>>>>,
+    /// Event store that dispatched events are appended to
+    store: Arc<std::sync::RwLock<Option<Arc<EventStore>>>>,
+
+    /// Recently delivered at-least-once events
+    dedupe: Arc<DedupeWindow>,
 }
 
 impl SubscriptionRegistry {
     pub fn new() -> Self {
         Self {
+            process_name: "local".to_string(),
             subscribers: Arc::new(DashMap::new()),
-            topic_subscriptions: Arc::new(RwLock::new(HashMap::new())),
+            topic_subscriptions: Arc::new(RwLock::new(TopicTrie::new())),
+            shared_groups: Arc::new(SharedGroups::default()),
+            announcements: None,
+            failures: None,
+            upcasters: Arc::new(Upcasters::default()),
+            store: Arc::new(std::sync::RwLock::new(None)),
+            dedupe: Arc::new(DedupeWindow::default()),
+        }
+    }
+
+    /// Create the registry of a hub, announcing shared group membership
+    /// through `announcements` and reporting subscriber errors to `failures`
+    pub(crate) fn for_hub(
+        process_name: &str,
+        announcements: mpsc::UnboundedSender<Message>,
+        failures: mpsc::UnboundedSender<Failure>,
+    ) -> Self {
+        Self {
+            process_name: process_name.to_string(),
+            announcements: Some(announcements),
+            failures: Some(failures),
+            ..Self::new()
+        }
+    }
+
+    fn announce(&self, msg: Message) {
+        if let Some(announcements) = &self.announcements {
+            let _ = announcements.send(msg);
         }
     }
 
     /// Subscribe to a topic pattern
     pub async fn subscribe<S: Subscriber>(&self, subscriber: S) -> Result<Subscription> {
-        let id = Uuid::new_v4();
+        self.subscribe_with(subscriber, SubscribeOptions::default())
+            .await
+    }
+
+    /// Subscribe to a topic pattern with explicit queueing options
+    ///
+    /// The subscriber is moved onto its own task, fed through a bounded queue.
+    pub async fn subscribe_with<S: Subscriber>(
+        &self,
+        subscriber: S,
+        options: SubscribeOptions,
+    ) -> Result<Subscription> {
+        self.subscribe_viewed(subscriber, options, None).await
+    }
+
+    /// Subscribe with `view` decoding payloads for the content filter
+    async fn subscribe_viewed<S: Subscriber>(
+        &self,
+        mut subscriber: S,
+        options: SubscribeOptions,
+        view: Option<JsonView>,
+    ) -> Result<Subscription> {
         let topic_pattern = subscriber.topic_pattern().to_string();
+        validate_topic_filter(&topic_pattern)?;
+
+        let content = match (&options.filter, view) {
+            (Some(filter), Some(view)) => Some(Arc::new(ContentFilter {
+                filter: Filter::parse(filter)?,
+                view,
+            })),
+            (Some(_), None) => {
+                return Err(Error::subscription_error(
+                    "content filters require a typed subscription",
+                    Some(topic_pattern),
+                ));
+            }
+            (None, _) => None,
+        };
+
+        let id = Uuid::new_v4();
+        let queue = Arc::new(DeliveryQueue::new(options.capacity, options.overflow));
+        let (filter, shared) = match parse_shared_pattern(&topic_pattern) {
+            Some((group, filter)) => (
+                filter.to_string(),
+                Some(SharedMember {
+                    group: group.to_string(),
+                    filter: filter.to_string(),
+                    process: self.process_name.clone(),
+                    subscription: id,
+                }),
+            ),
+            None => (topic_pattern.clone(), None),
+        };
+        if shared.is_some() && options.no_local {
+            return Err(Error::subscription_error(
+                "no_local is not supported for shared subscriptions",
+                Some(topic_pattern),
+            ));
+        }
+        let store = match options.replay {
+            Some(_) if shared.is_some() => {
+                return Err(Error::subscription_error(
+                    "replay is not supported for shared subscriptions",
+                    Some(topic_pattern),
+                ));
+            }
+            Some(_) => match self.event_store() {
+                Some(store) => Some(store),
+                None => {
+                    return Err(Error::subscription_error(
+                        "replay requires an event store",
+                        Some(topic_pattern),
+                    ));
+                }
+            },
+            None => None,
+        };
+
+        // Join the shared group and tell the other processes
+        if let Some(member) = &shared {
+            self.shared_groups.join(member);
+            self.announce(Message::shared_join(
+                self.process_name.clone(),
+                None,
+                member,
+            ));
+        }
+
+        // Update topic index
+        self.topic_subscriptions.write().await.insert(&filter, id);
+
+        // Store subscription
+        self.subscribers.insert(
+            id,
+            SubscriptionEntry {
+                filter: filter.clone(),
+                queue: queue.clone(),
+                shared,
+                no_local: options.no_local,
+                content: content.clone(),
+            },
+        );
 
-        // Store subscriber
-        self.subscribers.insert(id, Box::new(subscriber));
+        // Events stored from here on are delivered live; earlier ones are replayed
+        let replay = match store.zip(options.replay) {
+            Some((store, from)) => {
+                let end = match store.next_offset() {
+                    Ok(end) => end,
+                    Err(e) => {
+                        self.unsubscribe(&id).await?;
+                        return Err(e);
+                    }
+                };
+                Some(Replay {
+                    store,
+                    from,
+                    end,
+                    filter,
+                    skip_source: options.no_local.then(|| self.process_name.clone()),
+                    content,
+                    upcasters: self.upcasters.clone(),
+                })
+            }
+            None => None,
+        };
 
-        // Update topic mapping
-        let mut topics = self.topic_subscriptions.write().await;
-        topics
-            .entry(topic_pattern.clone())
-            .or_insert_with(Vec::new)
-            .push(id);
+        // Drain the queue on a dedicated task
+        let task_queue = queue.clone();
+        let failures = self.failures.clone();
+        tokio::spawn(async move {
+            let mut replayed_until = 0;
+            if let Some(replay) = replay {
+                replayed_until = replay.end;
+                replay.run(&mut subscriber, id, failures.as_ref()).await;
+            }
+
+            while let Some(delivery) = task_queue.pop().await {
+                if delivery
+                    .context
+                    .offset
+                    .is_some_and(|offset| offset < replayed_until)
+                {
+                    continue;
+                }
+                deliver(&mut subscriber, delivery, id, failures.as_ref()).await;
+            }
+        });
 
         Ok(Subscription {
             id,
             topic_pattern,
+            queue,
             registry: Arc::new(self.clone()),
+            runtime: tokio::runtime::Handle::current(),
+        })
+    }
+
+    /// Subscribe to a topic pattern and receive typed events as a stream
+    pub async fn subscribe_typed<E: Event>(&self, pattern: &str) -> Result<EventStream<E>> {
+        self.subscribe_typed_with(pattern, SubscribeOptions::default())
+            .await
+    }
+
+    /// Subscribe to a topic pattern as a typed stream with explicit queueing options
+    pub async fn subscribe_typed_with<E: Event>(
+        &self,
+        pattern: &str,
+        options: SubscribeOptions,
+    ) -> Result<EventStream<E>> {
+        // Hand over one event at a time so a slow consumer fills the subscription queue
+        let (sender, receiver) = mpsc::channel(1);
+        let subscription = self
+            .subscribe_viewed(
+                ChannelSubscriber {
+                    topic_pattern: pattern.to_string(),
+                    sender,
+                },
+                options,
+                Some(JsonView::of::<E>()),
+            )
+            .await?;
+
+        Ok(EventStream {
+            receiver,
+            subscription,
+            _phantom: PhantomData,
+        })
+    }
+
+    /// Subscribe to a topic pattern with an async closure
+    pub async fn subscribe_fn<E, F, Fut>(
+        &self,
+        pattern: &str,
+        options: SubscribeOptions,
+        handler: F,
+    ) -> Result<Subscription>
+    where
+        E: Event,
+        F: Fn(String, E) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<()>> + Send + 'static,
+    {
+        self.subscribe_event_fn(pattern, options, move |ctx: EventContext, event| {
+            handler(ctx.topic, event)
         })
+        .await
+    }
+
+    /// Subscribe to a topic pattern with an async closure receiving each
+    /// event's context
+    pub async fn subscribe_event_fn<E, F, Fut>(
+        &self,
+        pattern: &str,
+        options: SubscribeOptions,
+        handler: F,
+    ) -> Result<Subscription>
+    where
+        E: Event,
+        F: Fn(EventContext, E) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<()>> + Send + 'static,
+    {
+        let subscriber = FnSubscriber {
+            topic_pattern: pattern.to_string(),
+            handler,
+            _phantom: PhantomData,
+        };
+        self.subscribe_viewed(subscriber, options, Some(JsonView::of::<E>()))
+            .await
+    }
+
+    /// Subscribe to a topic pattern with a synchronous closure
+    ///
+    /// The closure runs on the blocking thread pool, one event at a time.
+    pub async fn subscribe_sync_fn<E, F>(
+        &self,
+        pattern: &str,
+        options: SubscribeOptions,
+        handler: F,
+    ) -> Result<Subscription>
+    where
+        E: Event,
+        F: Fn(&str, E) -> Result<()> + Send + Sync + 'static,
+    {
+        self.subscribe_sync_event_fn(pattern, options, move |ctx: &EventContext, event| {
+            handler(&ctx.topic, event)
+        })
+        .await
+    }
+
+    /// Subscribe to a topic pattern with a synchronous closure receiving each
+    /// event's context
+    ///
+    /// The closure runs on the blocking thread pool, one event at a time.
+    pub async fn subscribe_sync_event_fn<E, F>(
+        &self,
+        pattern: &str,
+        options: SubscribeOptions,
+        handler: F,
+    ) -> Result<Subscription>
+    where
+        E: Event,
+        F: Fn(&EventContext, E) -> Result<()> + Send + Sync + 'static,
+    {
+        let subscriber = SyncSubscriberAdapter::new(SyncFnSubscriber {
+            topic_pattern: pattern.to_string(),
+            handler,
+            _phantom: PhantomData,
+        });
+        self.subscribe_viewed(subscriber, options, Some(JsonView::of::<E>()))
+            .await
+    }
+
+    /// Decode `E` events published with schema version `from_version` as `Old`
+    ///
+    /// Applies to every typed and closure subscription of this registry.
+    pub fn register_upcaster<E, Old, F>(&self, from_version: u32, upcast: F) -> Result<()>
+    where
+        E: Event,
+        Old: DeserializeOwned + 'static,
+        F: Fn(Old) -> E + Send + Sync + 'static,
+    {
+        self.upcasters.register(from_version, upcast)
+    }
+
+    /// Append dispatched events to `store`, or stop storing them with `None`
+    pub fn set_event_store(&self, store: Option<Arc<EventStore>>) {
+        *self.store.write().unwrap() = store;
+    }
+
+    /// The event store dispatched events are appended to
+    pub fn event_store(&self) -> Option<Arc<EventStore>> {
+        self.store.read().unwrap().clone()
+    }
+
+    /// Number of active subscriptions
+    pub fn subscription_count(&self) -> usize {
+        self.subscribers.len()
     }
 
     /// Unsubscribe
     pub async fn unsubscribe(&self, id: &Uuid) -> Result<()> {
-        // Remove subscriber
-        if let Some((_, subscriber)) = self.subscribers.remove(id) {
-            let topic_pattern = subscriber.topic_pattern();
-
-            // Remove from topic mapping
-            let mut topics = self.topic_subscriptions.write().await;
-            if let Some(subs) = topics.get_mut(topic_pattern) {
-                subs.retain(|sub_id| sub_id != id);
-                if subs.is_empty() {
-                    topics.remove(topic_pattern);
-                }
+        // Remove subscription and stop its delivery task
+        if let Some((_, entry)) = self.subscribers.remove(id) {
+            entry.queue.close();
+
+            // Remove from topic index
+            self.topic_subscriptions
+                .write()
+                .await
+                .remove(&entry.filter, id);
+
+            // Leave the shared group
+            if let Some(member) = &entry.shared {
+                self.shared_groups.leave(member);
+                self.announce(Message::shared_leave(self.process_name.clone(), member));
             }
         }
 
@@ -118,101 +714,418 @@ impl SubscriptionRegistry {
     }
 
     /// Publish an event to matching subscribers
+    ///
+    /// Events are queued per subscription; this only waits when a subscription
+    /// with the `Block` overflow policy is full.
     pub async fn publish(&self, topic: &str, payload: Vec<u8>) -> Result<()> {
-        let topics = self.topic_subscriptions.read().await;
-        let mut matching_ids = Vec::new();
+        let msg = Message::event(self.process_name.clone(), topic.to_string(), payload);
+        self.dispatch(topic, &msg).await?;
+        Ok(())
+    }
+
+    /// Publish an event message to matching subscribers
+    ///
+    /// Used both for messages received from the bus and for local publishes.
+    /// The message ID selects the receiving member of shared groups, and
+    /// messages from this process skip `no_local` subscriptions.
+    ///
+    /// Returns the number of subscriptions the event was queued for.
+    pub(crate) async fn publish_message(&self, msg: &Message) -> Result<usize> {
+        match &msg.topic {
+            Some(topic) => self.dispatch(topic, msg).await,
+            None => Ok(0),
+        }
+    }
+
+    /// Deliver an event received from the bus, returning the ack to send back
+    ///
+    /// At-least-once events are acked once queued for a subscription. Only the
+    /// first copy is dispatched and stored; copies resent within the dedupe
+    /// window are acked again if the first was, and otherwise ignored.
+    pub(crate) async fn receive_message(&self, msg: &Message) -> Result<Option<Message>> {
+        if !msg.wants_ack() {
+            self.publish_message(msg).await?;
+            return Ok(None);
+        }
+
+        let queued = match self.dedupe.get(&msg.id) {
+            Some(queued) => queued,
+            None => {
+                let queued = self.publish_message(msg).await? > 0;
+                self.dedupe.insert(msg.id, queued);
+                queued
+            }
+        };
+        Ok(queued.then(|| Message::event_ack(self.process_name.clone(), msg)))
+    }
+
+    async fn dispatch(&self, topic: &str, msg: &Message) -> Result<usize> {
+        validate_topic_name(topic)?;
+        let id = msg.id;
+        let local = msg.source == self.process_name;
+
+        // Store before delivering, so replaying subscriptions can tell which
+        // live events they have already seen
+        let offset = match self.event_store() {
+            Some(store) => {
+                let (stored_topic, stored_msg) = (topic.to_string(), msg.clone());
+                tokio::task::spawn_blocking(move || {
+                    store.append(
+                        &stored_topic,
+                        &stored_msg.source,
+                        stored_msg.id,
+                        stored_msg.metadata.timestamp,
+                        &stored_msg.payload,
+                        &stored_msg.extension(),
+                    )
+                })
+                .await
+                .map_err(Error::from)
+                .and_then(|offset| offset)
+                .unwrap_or_else(|e| {
+                    tracing::warn!("Failed to store event on {}: {}", topic, e);
+                    None
+                })
+            }
+            None => None,
+        };
 
         // Find matching subscriptions
-        for (pattern, ids) in topics.iter() {
-            if topic_matches(topic, pattern) {
-                matching_ids.extend(ids.iter().copied());
+        let matching_ids = self.topic_subscriptions.read().await.matches(topic);
+
+        // Collect queues first so no map guard is held across an await
+        let mut queues: Vec<(Uuid, Arc<DeliveryQueue>, Option<Arc<ContentFilter>>)> = Vec::new();
+        let mut shared_groups: Vec<(String, String)> = Vec::new();
+        for id in &matching_ids {
+            let Some(entry) = self.subscribers.get(id) else {
+                continue;
+            };
+            if local && entry.no_local {
+                continue;
+            }
+            match &entry.shared {
+                None => queues.push((*id, entry.queue.clone(), entry.content.clone())),
+                Some(member) => {
+                    let group = (member.group.clone(), member.filter.clone());
+                    if !shared_groups.contains(&group) {
+                        shared_groups.push(group);
+                    }
+                }
             }
         }
-        drop(topics);
 
-        // Deliver to subscribers
-        for id in matching_ids {
-            if let Some(mut subscriber) = self.subscribers.get_mut(&id) {
-                // Clone payload for each subscriber
-                let _ = subscriber.handle(topic, payload.clone()).await;
+        // Each shared group delivers to one member, which may live elsewhere
+        for (group, filter) in shared_groups {
+            let Some((process, member)) = self.shared_groups.select(&group, &filter, id) else {
+                continue;
+            };
+            if process != self.process_name {
+                continue;
+            }
+            if let Some(entry) = self.subscribers.get(&member) {
+                queues.push((member, entry.queue.clone(), entry.content.clone()));
             }
         }
 
-        Ok(())
+        // Deliver to subscriber queues
+        let context = EventContext {
+            topic: topic.to_string(),
+            source: msg.source.clone(),
+            message_id: id,
+            offset,
+            headers: msg.metadata.headers.clone(),
+            schema_version: msg.metadata.schema_version,
+            upcasters: self.upcasters.clone(),
+        };
+        let mut views = EventViews::default();
+        let mut queued = 0;
+        for (subscription, queue, content) in queues {
+            if let Some(content) = content {
+                if !content.accepts(&mut views, &context, &msg.payload) {
+                    continue;
+                }
+            }
+            let delivery = Delivery {
+                context: context.clone(),
+                payload: msg.payload.clone(),
+            };
+            // Only local publishers wait for room; events from the bus are
+            // pushed by the message loop, which must keep serving RPC traffic
+            let outcome = if local {
+                queue.push(delivery).await
+            } else {
+                queue.push_nowait(delivery)
+            };
+            match outcome {
+                PushOutcome::Queued | PushOutcome::Displaced => queued += 1,
+                PushOutcome::Dropped => {}
+                PushOutcome::Closed => {
+                    if queue.is_disconnected() {
+                        tracing::warn!("Subscription {} disconnected: queue full", subscription);
+                        self.unsubscribe(&subscription).await?;
+                    }
+                }
+            }
+        }
+
+        Ok(queued)
     }
-}
 
-impl Clone for SubscriptionRegistry {
-    fn clone(&self) -> Self {
-        Self {
-            subscribers: self.subscribers.clone(),
-            topic_subscriptions: self.topic_subscriptions.clone(),
+    /// Apply a shared group membership announcement from another process
+    ///
+    /// Returns the replies to send: when a remote member joins, it is told
+    /// about the local members of the same group.
+    pub(crate) fn handle_membership(&self, msg: &Message) -> Vec<Message> {
+        let Ok(member) = bincode::deserialize::<SharedMember>(&msg.payload) else {
+            return Vec::new();
+        };
+        if member.process == self.process_name {
+            return Vec::new();
+        }
+
+        match msg.msg_type {
+            MessageType::Subscribe => {
+                let is_new = self.shared_groups.join(&member);
+                // Only answer broadcasts, so replies never trigger more replies
+                if !is_new || msg.target.is_some() {
+                    return Vec::new();
+                }
+                self.local_shared_members()
+                    .into_iter()
+                    .filter(|local| local.group == member.group && local.filter == member.filter)
+                    .map(|local| {
+                        Message::shared_join(
+                            self.process_name.clone(),
+                            Some(msg.source.clone()),
+                            &local,
+                        )
+                    })
+                    .collect()
+            }
+            MessageType::Unsubscribe => {
+                self.shared_groups.leave(&member);
+                Vec::new()
+            }
+            _ => Vec::new(),
         }
     }
-}
 
-impl Default for SubscriptionRegistry {
-    fn default() -> Self {
-        Self::new()
+    /// Refresh the shared group members repeated in another process's heartbeat
+    pub(crate) fn handle_heartbeat(&self, msg: &Message) {
+        let Ok(members) = bincode::deserialize::<Vec<SharedMember>>(&msg.payload) else {
+            return;
+        };
+        for member in &members {
+            if member.process != self.process_name {
+                self.shared_groups.join(member);
+            }
+        }
     }
-}
 
-/// Check if a topic matches a pattern
-/// Supports wildcards: + (single level), # (multi level)
-fn topic_matches(topic: &str, pattern: &str) -> bool {
-    // Exact match
-    if topic == pattern {
-        return true;
+    /// Drop shared group members of processes not heard from within `timeout`
+    ///
+    /// Returns the number of members dropped.
+    pub(crate) fn expire_shared_members(&self, timeout: std::time::Duration) -> usize {
+        self.shared_groups.expire(&self.process_name, timeout)
     }
 
-    let topic_parts: Vec<&str> = topic.split('/').collect();
-    let pattern_parts: Vec<&str> = pattern.split('/').collect();
+    /// Shared group memberships of this process
+    pub(crate) fn local_shared_members(&self) -> Vec<SharedMember> {
+        self.subscribers
+            .iter()
+            .filter_map(|entry| entry.shared.clone())
+            .collect()
+    }
+}
+
+/// Stored events to hand to a new subscription before live delivery
+struct Replay {
+    store: Arc<EventStore>,
+    from: ReplayFrom,
+    /// Offset of the first live event
+    end: u64,
+    filter: String,
+    /// Skip events from this process (`no_local`)
+    skip_source: Option<String>,
+    content: Option<Arc<ContentFilter>>,
+    upcasters: Arc<Upcasters>,
+}
 
-    let mut t_idx = 0;
-    let mut p_idx = 0;
+impl Replay {
+    /// Events read from the store at a time
+    const BATCH: usize = 256;
 
-    while p_idx < pattern_parts.len() && t_idx < topic_parts.len() {
-        match pattern_parts[p_idx] {
-            "#" => return true, // Multi-level wildcard matches everything
-            "+" => {
-                // Single-level wildcard matches one part
-                t_idx += 1;
-                p_idx += 1;
-            }
-            part => {
-                if part != topic_parts[t_idx] {
-                    return false;
+    async fn run<S: Subscriber>(
+        self,
+        subscriber: &mut S,
+        id: Uuid,
+        failures: Option<&mpsc::UnboundedSender<Failure>>,
+    ) {
+        let (mut start, min_timestamp) = match self.from {
+            ReplayFrom::Offset(offset) => (offset, 0),
+            ReplayFrom::Timestamp(timestamp) => (0, timestamp),
+        };
+
+        while start < self.end {
+            let store = self.store.clone();
+            let filter = self.filter.clone();
+            let end = self.end;
+            let batch = tokio::task::spawn_blocking(move || {
+                store.read_range(&filter, start..end, min_timestamp, Self::BATCH)
+            })
+            .await
+            .map_err(Error::from)
+            .and_then(|batch| batch);
+
+            let events = match batch {
+                Ok(events) => events,
+                Err(e) => {
+                    tracing::warn!("Replay for subscription {} stopped: {}", id, e);
+                    return;
                 }
-                t_idx += 1;
-                p_idx += 1;
+            };
+            let Some(last) = events.last() else {
+                return;
+            };
+            start = last.offset + 1;
+
+            for event in events {
+                if self.skip_source.as_ref() == Some(&event.source) {
+                    continue;
+                }
+                let context = EventContext {
+                    topic: event.topic,
+                    source: event.source,
+                    message_id: event.id,
+                    offset: Some(event.offset),
+                    headers: event.headers,
+                    schema_version: event.schema_version,
+                    upcasters: self.upcasters.clone(),
+                };
+                if let Some(content) = &self.content {
+                    if !content.accepts(&mut EventViews::default(), &context, &event.payload) {
+                        continue;
+                    }
+                }
+                let delivery = Delivery {
+                    context,
+                    payload: event.payload,
+                };
+                deliver(subscriber, delivery, id, failures).await;
             }
         }
     }
+}
+
+/// Hand a delivery to a subscriber, reporting any failure
+async fn deliver<S: Subscriber>(
+    subscriber: &mut S,
+    delivery: Delivery,
+    id: Uuid,
+    failures: Option<&mpsc::UnboundedSender<Failure>>,
+) {
+    let ctx = delivery.context;
+    // Keep the payload only when someone may want it back
+    let payload = failures.map(|_| delivery.payload.clone());
+    if let Err(error) = subscriber.handle_event(&ctx, delivery.payload).await {
+        let failure = Failure {
+            topic: Some(ctx.topic),
+            subscription: Some(id),
+            source: ctx.source,
+            message_id: ctx.message_id,
+            payload: payload.unwrap_or_default(),
+            headers: ctx.headers,
+            schema_version: ctx.schema_version,
+            error: Arc::new(error),
+        };
+        report_failure(failures, failure);
+    }
+}
+
+/// Send a failure to the hub, or log it when there is no hub to receive it
+fn report_failure(failures: Option<&mpsc::UnboundedSender<Failure>>, failure: Failure) {
+    let failure = match failures {
+        Some(failures) => match failures.send(failure) {
+            Ok(()) => return,
+            Err(mpsc::error::SendError(failure)) => failure,
+        },
+        None => failure,
+    };
+    tracing::warn!(
+        "Subscriber {:?} failed on {:?}: {}",
+        failure.subscription,
+        failure.topic,
+        failure.error
+    );
+}
 
-    // Both should be exhausted for a match
-    t_idx == topic_parts.len() && p_idx == pattern_parts.len()
+impl Clone for SubscriptionRegistry {
+    fn clone(&self) -> Self {
+        Self {
+            process_name: self.process_name.clone(),
+            subscribers: self.subscribers.clone(),
+            topic_subscriptions: self.topic_subscriptions.clone(),
+            shared_groups: self.shared_groups.clone(),
+            announcements: self.announcements.clone(),
+            failures: self.failures.clone(),
+            upcasters: self.upcasters.clone(),
+            store: self.store.clone(),
+            dedupe: self.dedupe.clone(),
+        }
+    }
+}
+
+impl Default for SubscriptionRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 /// Adapter for sync subscribers
+///
+/// Each event is handled on the blocking thread pool. Events are handled one
+/// at a time, in the order they were delivered.
 pub struct SyncSubscriberAdapter<S: SyncSubscriber> {
-    inner: S,
+    topic_pattern: String,
+    /// The subscriber, moved onto the blocking pool while it handles an event
+    inner: Option<S>,
 }
 
 impl<S: SyncSubscriber> SyncSubscriberAdapter<S> {
     pub fn new(subscriber: S) -> Self {
-        Self { inner: subscriber }
+        Self {
+            topic_pattern: subscriber.topic_pattern().to_string(),
+            inner: Some(subscriber),
+        }
     }
 }
 
 #[async_trait]
 impl<S: SyncSubscriber> Subscriber for SyncSubscriberAdapter<S> {
     fn topic_pattern(&self) -> &str {
-        self.inner.topic_pattern()
+        &self.topic_pattern
     }
 
-    async fn handle(&mut self, _topic: &str, _payload: Vec<u8>) -> Result<()> {
-        // Note: This is a simplified implementation
-        // In practice, we'd need better integration between sync and async
-        Ok(())
+    async fn handle(&mut self, topic: &str, payload: Vec<u8>) -> Result<()> {
+        self.handle_event(&EventContext::new(topic), payload).await
+    }
+
+    async fn handle_event(&mut self, ctx: &EventContext, payload: Vec<u8>) -> Result<()> {
+        let Some(mut inner) = self.inner.take() else {
+            return Err(Error::subscription_error(
+                "sync subscriber panicked while handling an earlier event",
+                Some(self.topic_pattern.clone()),
+            ));
+        };
+
+        let ctx = ctx.clone();
+        let (inner, result) = tokio::task::spawn_blocking(move || {
+            let result = inner.handle_sync_event(&ctx, payload);
+            (inner, result)
+        })
+        .await?;
+
+        self.inner = Some(inner);
+        result
     }
 }
@@ -0,0 +1,91 @@
+//! Failure reporting for subscribers and message handling
+//!
+//! Errors returned by subscribers, and errors raised while a hub processes an
+//! incoming message, are turned into [`Failure`] records instead of being
+//! dropped. A hub broadcasts them to every receiver returned by
+//! [`ProcessHub::failures`](crate::ProcessHub::failures) and can republish
+//! failed events to a dead-letter topic.
+
+use crate::Error;
+use std::collections::HashMap;
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// A subscriber or message handling failure
+#[derive(Debug, Clone)]
+pub struct Failure {
+    /// Topic of the failed message, if it had one
+    pub topic: Option<String>,
+    /// Subscription whose handler failed, or `None` for message handling
+    pub subscription: Option<Uuid>,
+    /// Process that sent the message
+    pub source: String,
+    /// ID of the failed message
+    pub message_id: Uuid,
+    /// Payload of the failed event
+    ///
+    /// Only subscriber failures carry the payload; it is empty otherwise.
+    pub payload: Vec<u8>,
+    /// Custom headers of the failed event, kept on its dead-letter copy
+    pub headers: HashMap<String, String>,
+    /// Schema version of the failed event, kept on its dead-letter copy
+    pub schema_version: u32,
+    /// The error that caused the failure
+    pub error: Arc<Error>,
+}
+
+impl Failure {
+    /// Topic under `prefix` where this failure is republished, if any
+    ///
+    /// Failures without a payload, and failures of events that were already
+    /// dead-lettered, are not republished.
+    pub(crate) fn dead_letter_topic(&self, prefix: &str) -> Option<String> {
+        self.subscription?;
+        let topic = self.topic.as_deref()?;
+        if topic == prefix || topic.starts_with(&format!("{prefix}/")) {
+            return None;
+        }
+        Some(format!("{prefix}/{topic}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn failure(topic: &str, subscription: Option<Uuid>) -> Failure {
+        Failure {
+            topic: Some(topic.to_string()),
+            subscription,
+            source: "test".to_string(),
+            message_id: Uuid::new_v4(),
+            payload: vec![1, 2, 3],
+            headers: HashMap::new(),
+            schema_version: 0,
+            error: Arc::new(Error::runtime_msg("boom")),
+        }
+    }
+
+    #[test]
+    fn test_dead_letter_topic() {
+        let id = Some(Uuid::new_v4());
+        assert_eq!(
+            failure("sensor/temp", id).dead_letter_topic("dlq"),
+            Some("dlq/sensor/temp".to_string())
+        );
+
+        // Already dead-lettered events are not republished again
+        assert_eq!(
+            failure("dlq/sensor/temp", id).dead_letter_topic("dlq"),
+            None
+        );
+        assert_eq!(failure("dlq", id).dead_letter_topic("dlq"), None);
+        assert_eq!(
+            failure("dlqx/a", id).dead_letter_topic("dlq"),
+            Some("dlq/dlqx/a".to_string())
+        );
+
+        // Message handling failures have no payload to republish
+        assert_eq!(failure("sensor/temp", None).dead_letter_topic("dlq"), None);
+    }
+}
@@ -0,0 +1,499 @@
+//! Content-based event filters
+//!
+//! A filter is a boolean expression over the fields of an event, evaluated
+//! against its JSON form before the event is queued for a subscription:
+//!
+//! ```text
+//! value > 80 && unit == "C"
+//! !(status == "ok") || reading.retries >= 3
+//! ```
+//!
+//! Operands are field paths (`a.b`, `items.0`) or literals (numbers, strings,
+//! `true`, `false`, `null`). Comparisons use `==`, `!=`, `<`, `<=`, `>`, `>=`
+//! and combine with `&&`, `||`, `!` and parentheses. A comparison involving a
+//! missing field, or values of different kinds, is false. A field on its own
+//! is true only if it holds `true`.
+
+use crate::{Error, Event, EventContext, Result};
+use serde_json::Value;
+use std::any::TypeId;
+use std::cmp::Ordering;
+use std::sync::Arc;
+
+/// Parsed content filter
+#[derive(Debug, Clone)]
+pub struct Filter {
+    source: String,
+    expr: Expr,
+}
+
+impl Filter {
+    /// Parse a filter expression
+    pub fn parse(source: &str) -> Result<Self> {
+        let tokens = tokenize(source).map_err(|message| invalid(source, message))?;
+        let mut parser = Parser { tokens, pos: 0 };
+        let expr = parser.or().map_err(|message| invalid(source, message))?;
+        if let Some((token, at)) = parser.tokens.get(parser.pos) {
+            return Err(invalid(
+                source,
+                format!("unexpected {token:?} at position {at}"),
+            ));
+        }
+        Ok(Self {
+            source: source.to_string(),
+            expr,
+        })
+    }
+
+    /// The expression the filter was parsed from
+    pub fn as_str(&self) -> &str {
+        &self.source
+    }
+
+    /// Whether a JSON value passes the filter
+    pub fn matches(&self, value: &Value) -> bool {
+        self.expr.eval(value)
+    }
+}
+
+fn invalid(source: &str, message: String) -> Error {
+    Error::subscription_error(format!("invalid filter '{source}': {message}"), None)
+}
+
+/// Decodes payloads of one event type into the JSON view filters are
+/// evaluated against
+#[derive(Clone)]
+pub(crate) struct JsonView {
+    /// Event type decoded, so subscriptions of the same type share a view
+    event_type: TypeId,
+    decode: Arc<dyn Fn(&EventContext, &[u8]) -> Result<Value> + Send + Sync>,
+}
+
+impl JsonView {
+    /// View of `E` events, decoded with the upcasters of their context
+    pub fn of<E: Event>() -> Self {
+        Self {
+            event_type: TypeId::of::<E>(),
+            decode: Arc::new(|ctx, payload| {
+                let event: E = ctx.decode(payload)?;
+                serde_json::to_value(&event)
+                    .map_err(|e| Error::serialization("failed to build JSON view of event", e))
+            }),
+        }
+    }
+}
+
+/// JSON views of one event, decoded the first time a filter needs them
+///
+/// Shared by all filtered subscriptions an event is dispatched to, so each
+/// event is decoded at most once per event type.
+#[derive(Default)]
+pub(crate) struct EventViews {
+    /// View per event type, `None` when the payload does not decode
+    views: Vec<(TypeId, Option<Value>)>,
+}
+
+impl EventViews {
+    fn get(&mut self, view: &JsonView, ctx: &EventContext, payload: &[u8]) -> Option<&Value> {
+        let index = match self.views.iter().position(|(t, _)| *t == view.event_type) {
+            Some(index) => index,
+            None => {
+                let value = (view.decode)(ctx, payload).ok();
+                self.views.push((view.event_type, value));
+                self.views.len() - 1
+            }
+        };
+        self.views[index].1.as_ref()
+    }
+}
+
+/// A filter together with the view of the subscription's event type
+pub(crate) struct ContentFilter {
+    pub filter: Filter,
+    pub view: JsonView,
+}
+
+impl ContentFilter {
+    /// Whether an event should be delivered
+    ///
+    /// Payloads that cannot be decoded are let through so the subscriber
+    /// reports the failure.
+    pub fn accepts(&self, views: &mut EventViews, ctx: &EventContext, payload: &[u8]) -> bool {
+        match views.get(&self.view, ctx, payload) {
+            Some(value) => self.filter.matches(value),
+            None => true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Path(Vec<String>),
+    Literal(Value),
+    Op(CmpOp),
+    And,
+    Or,
+    Not,
+    Open,
+    Close,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CmpOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Debug, Clone)]
+enum Operand {
+    Path(Vec<String>),
+    Literal(Value),
+}
+
+#[derive(Debug, Clone)]
+enum Expr {
+    Or(Box<Expr>, Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Compare(Operand, CmpOp, Operand),
+    Truthy(Operand),
+}
+
+fn tokenize(source: &str) -> std::result::Result<Vec<(Token, usize)>, String> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let start = i;
+        let two = |next: char| chars.get(i + 1) == Some(&next);
+        let token = match c {
+            c if c.is_whitespace() => {
+                i += 1;
+                continue;
+            }
+            '(' => Token::Open,
+            ')' => Token::Close,
+            '&' if two('&') => Token::And,
+            '|' if two('|') => Token::Or,
+            '=' if two('=') => Token::Op(CmpOp::Eq),
+            '!' if two('=') => Token::Op(CmpOp::Ne),
+            '<' if two('=') => Token::Op(CmpOp::Le),
+            '>' if two('=') => Token::Op(CmpOp::Ge),
+            '!' => Token::Not,
+            '<' => Token::Op(CmpOp::Lt),
+            '>' => Token::Op(CmpOp::Gt),
+            '"' => {
+                let mut text = String::new();
+                i += 1;
+                loop {
+                    match chars.get(i) {
+                        None => return Err(format!("unterminated string at position {start}")),
+                        Some('"') => break,
+                        Some('\\') => {
+                            match chars.get(i + 1) {
+                                Some('n') => text.push('\n'),
+                                Some('t') => text.push('\t'),
+                                Some(&c @ ('"' | '\\')) => text.push(c),
+                                _ => return Err(format!("invalid escape at position {i}")),
+                            }
+                            i += 2;
+                        }
+                        Some(&c) => {
+                            text.push(c);
+                            i += 1;
+                        }
+                    }
+                }
+                tokens.push((Token::Literal(Value::String(text)), start));
+                i += 1;
+                continue;
+            }
+            c if c.is_ascii_digit() || c == '-' => {
+                let mut end = i + 1;
+                while end < chars.len()
+                    && (chars[end].is_ascii_alphanumeric()
+                        || chars[end] == '.'
+                        || ((chars[end] == '-' || chars[end] == '+')
+                            && matches!(chars[end - 1], 'e' | 'E')))
+                {
+                    end += 1;
+                }
+                let text: String = chars[i..end].iter().collect();
+                let number = text
+                    .parse::<f64>()
+                    .ok()
+                    .and_then(serde_json::Number::from_f64)
+                    .ok_or_else(|| format!("invalid number '{text}' at position {start}"))?;
+                tokens.push((Token::Literal(Value::Number(number)), start));
+                i = end;
+                continue;
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                let mut end = i;
+                while end < chars.len()
+                    && (chars[end].is_alphanumeric() || chars[end] == '_' || chars[end] == '.')
+                {
+                    end += 1;
+                }
+                let text: String = chars[i..end].iter().collect();
+                i = end;
+                let token = match text.as_str() {
+                    "true" => Token::Literal(Value::Bool(true)),
+                    "false" => Token::Literal(Value::Bool(false)),
+                    "null" => Token::Literal(Value::Null),
+                    _ => {
+                        let path: Vec<String> = text.split('.').map(str::to_string).collect();
+                        if path.iter().any(String::is_empty) {
+                            return Err(format!("invalid field path '{text}' at position {start}"));
+                        }
+                        Token::Path(path)
+                    }
+                };
+                tokens.push((token, start));
+                continue;
+            }
+            c => return Err(format!("unexpected '{c}' at position {start}")),
+        };
+        i += match token {
+            Token::And | Token::Or => 2,
+            Token::Op(CmpOp::Eq | CmpOp::Ne | CmpOp::Le | CmpOp::Ge) => 2,
+            _ => 1,
+        };
+        tokens.push((token, start));
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(token, _)| token)
+    }
+
+    fn next(&mut self) -> std::result::Result<Token, String> {
+        let token = self
+            .tokens
+            .get(self.pos)
+            .map(|(token, _)| token.clone())
+            .ok_or_else(|| "unexpected end of expression".to_string())?;
+        self.pos += 1;
+        Ok(token)
+    }
+
+    fn or(&mut self) -> std::result::Result<Expr, String> {
+        let mut expr = self.and()?;
+        while self.peek() == Some(&Token::Or) {
+            self.pos += 1;
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> std::result::Result<Expr, String> {
+        let mut expr = self.unary()?;
+        while self.peek() == Some(&Token::And) {
+            self.pos += 1;
+            expr = Expr::And(Box::new(expr), Box::new(self.unary()?));
+        }
+        Ok(expr)
+    }
+
+    fn unary(&mut self) -> std::result::Result<Expr, String> {
+        match self.peek() {
+            Some(Token::Not) => {
+                self.pos += 1;
+                Ok(Expr::Not(Box::new(self.unary()?)))
+            }
+            Some(Token::Open) => {
+                self.pos += 1;
+                let expr = self.or()?;
+                match self.next()? {
+                    Token::Close => Ok(expr),
+                    token => Err(format!("expected ')', found {token:?}")),
+                }
+            }
+            _ => self.comparison(),
+        }
+    }
+
+    fn comparison(&mut self) -> std::result::Result<Expr, String> {
+        let left = self.operand()?;
+        match self.peek() {
+            Some(Token::Op(op)) => {
+                let op = *op;
+                self.pos += 1;
+                Ok(Expr::Compare(left, op, self.operand()?))
+            }
+            _ => Ok(Expr::Truthy(left)),
+        }
+    }
+
+    fn operand(&mut self) -> std::result::Result<Operand, String> {
+        match self.next()? {
+            Token::Path(path) => Ok(Operand::Path(path)),
+            Token::Literal(value) => Ok(Operand::Literal(value)),
+            token => Err(format!("expected a field or value, found {token:?}")),
+        }
+    }
+}
+
+impl Operand {
+    fn resolve<'a>(&'a self, root: &'a Value) -> Option<&'a Value> {
+        match self {
+            Operand::Literal(value) => Some(value),
+            Operand::Path(path) => path.iter().try_fold(root, |value, key| match value {
+                Value::Object(map) => map.get(key),
+                Value::Array(items) => items.get(key.parse::<usize>().ok()?),
+                _ => None,
+            }),
+        }
+    }
+}
+
+impl Expr {
+    fn eval(&self, root: &Value) -> bool {
+        match self {
+            Expr::Or(left, right) => left.eval(root) || right.eval(root),
+            Expr::And(left, right) => left.eval(root) && right.eval(root),
+            Expr::Not(expr) => !expr.eval(root),
+            Expr::Truthy(operand) => operand.resolve(root) == Some(&Value::Bool(true)),
+            Expr::Compare(left, op, right) => {
+                let (Some(left), Some(right)) = (left.resolve(root), right.resolve(root)) else {
+                    return false;
+                };
+                match op {
+                    CmpOp::Eq => values_equal(left, right),
+                    CmpOp::Ne => !values_equal(left, right),
+                    CmpOp::Lt => compare(left, right) == Some(Ordering::Less),
+                    CmpOp::Le => {
+                        matches!(compare(left, right), Some(Ordering::Less | Ordering::Equal))
+                    }
+                    CmpOp::Gt => compare(left, right) == Some(Ordering::Greater),
+                    CmpOp::Ge => matches!(
+                        compare(left, right),
+                        Some(Ordering::Greater | Ordering::Equal)
+                    ),
+                }
+            }
+        }
+    }
+}
+
+fn values_equal(left: &Value, right: &Value) -> bool {
+    match (left.as_f64(), right.as_f64()) {
+        (Some(left), Some(right)) => left == right,
+        _ => left == right,
+    }
+}
+
+fn compare(left: &Value, right: &Value) -> Option<Ordering> {
+    match (left, right) {
+        (Value::Number(left), Value::Number(right)) => left.as_f64()?.partial_cmp(&right.as_f64()?),
+        (Value::String(left), Value::String(right)) => Some(left.cmp(right)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+
+    fn reading() -> Value {
+        json!({
+            "value": 85.5,
+            "unit": "C",
+            "ok": false,
+            "device": { "id": "d-1", "tags": ["roof", "north"] },
+        })
+    }
+
+    #[test]
+    fn test_evaluation() {
+        let cases = [
+            (r#"value > 80 && unit == "C""#, true),
+            (r#"value > 80 && unit == "F""#, false),
+            ("value >= 85.5 && value <= 85.5", true),
+            ("value < -1e3", false),
+            (r#"unit != "F""#, true),
+            (r#"device.id == "d-1""#, true),
+            (r#"device.tags.1 == "north""#, true),
+            ("missing > 1", false),
+            ("missing != 1", false),
+            (r#"value == "85.5""#, false),
+            ("!ok", true),
+            ("ok || value > 100", false),
+            ("!(value > 80) || unit == \"C\" && ok", false),
+            ("(value > 80 || ok) && !ok", true),
+            ("device.missing == null", false),
+        ];
+        for (source, expected) in cases {
+            let filter = Filter::parse(source).unwrap();
+            assert_eq!(filter.matches(&reading()), expected, "{source}");
+        }
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        for source in [
+            "",
+            "value >",
+            "value > 80 &&",
+            "(value > 80",
+            "value > 80)",
+            "value = 80",
+            r#"unit == "C"#,
+            "value > 8x",
+            "a..b == 1",
+            "value > 80 unit",
+        ] {
+            assert!(Filter::parse(source).is_err(), "{source}");
+        }
+    }
+
+    #[test]
+    fn test_views_are_decoded_once_per_event_type() {
+        let decoded = Arc::new(AtomicUsize::new(0));
+        let view = |event_type: TypeId| {
+            let decoded = decoded.clone();
+            JsonView {
+                event_type,
+                decode: Arc::new(move |_, _| {
+                    decoded.fetch_add(1, AtomicOrdering::SeqCst);
+                    Ok(reading())
+                }),
+            }
+        };
+        let filter = |source: &str, view: JsonView| ContentFilter {
+            filter: Filter::parse(source).unwrap(),
+            view,
+        };
+        let hot = filter("value > 80", view(TypeId::of::<u8>()));
+        let cold = filter("value < 0", view(TypeId::of::<u8>()));
+        let other = filter("ok", view(TypeId::of::<u16>()));
+
+        let ctx = EventContext::new("sensor/roof");
+        let mut views = EventViews::default();
+        assert!(hot.accepts(&mut views, &ctx, &[]));
+        assert!(!cold.accepts(&mut views, &ctx, &[]));
+        assert_eq!(decoded.load(AtomicOrdering::SeqCst), 1);
+
+        // Subscriptions of another event type decode their own view
+        assert!(!other.accepts(&mut views, &ctx, &[]));
+        assert_eq!(decoded.load(AtomicOrdering::SeqCst), 2);
+
+        // The next event starts over
+        assert!(hot.accepts(&mut EventViews::default(), &ctx, &[]));
+        assert_eq!(decoded.load(AtomicOrdering::SeqCst), 3);
+    }
+}
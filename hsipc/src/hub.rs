@@ -7,8 +7,18 @@ use tokio::sync::RwLock;
 use uuid::Uuid;
 
 use crate::{
-    event::{Event, Subscriber, Subscription, SubscriptionRegistry},
+    context::{CallOptions, EventContext, RequestContext},
+    event::{
+        Event, EventStream, SubscribeOptions, Subscriber, Subscription, SubscriptionRegistry,
+        SyncSubscriber, SyncSubscriberAdapter,
+    },
+    failure::Failure,
+    layer::{Call, CallLayer, CallNext, LayeredService, ServiceLayer},
     message::{MessageType, ServiceDirectory, ServiceInfo},
+    qos::{PublishOptions, PublishReceipt, Qos},
+    shared::{HEARTBEAT_INTERVAL, MEMBER_TIMEOUT},
+    store::EventStore,
+    topic::validate_topic_name,
     transport::Transport,
     Error, Message, Result,
 };
@@ -21,7 +31,22 @@ use crate::transport::IpmbTransport;
 pub trait Service: Send + Sync + 'static {
     fn name(&self) -> &'static str;
     fn methods(&self) -> Vec<&'static str>;
-    async fn handle(&self, method: &str, payload: Vec<u8>) -> Result<Vec<u8>>;
+    async fn handle(&self, ctx: &RequestContext, method: &str, payload: Vec<u8>)
+        -> Result<Vec<u8>>;
+}
+
+/// Run a blocking closure on the blocking thread pool
+///
+/// Generated services call non-async methods through this, so a slow method
+/// doesn't stall the async handler.
+pub async fn run_blocking<F, R>(f: F) -> Result<R>
+where
+    F: FnOnce() -> R + Send + 'static,
+    R: Send + 'static,
+{
+    tokio::task::spawn_blocking(f)
+        .await
+        .map_err(|e| Error::runtime_msg(format!("Blocking task failed: {e}")))
 }
 
 // Service registry for managing RPC services
@@ -48,7 +73,23 @@ impl ServiceRegistry {
         Ok(())
     }
 
-    pub async fn call(&self, service_method: &str, payload: Vec<u8>) -> Result<Vec<u8>> {
+    /// Handle a request with a local service
+    ///
+    /// Requests whose deadline has already passed are rejected without calling
+    /// the service, since nobody is waiting for the response.
+    pub async fn call(
+        &self,
+        ctx: &RequestContext,
+        service_method: &str,
+        payload: Vec<u8>,
+    ) -> Result<Vec<u8>> {
+        if ctx.is_expired() {
+            return Err(Error::timeout(
+                format!("{service_method} from {}", ctx.caller),
+                0,
+            ));
+        }
+
         // Parse service.method format
         let parts: Vec<&str> = service_method.split('.').collect();
         if parts.len() != 2 {
@@ -61,12 +102,20 @@ impl ServiceRegistry {
         let (service_name, method) = (parts[0], parts[1]);
         let services = self.services.read().await;
         if let Some(service) = services.get(service_name) {
-            service.handle(method, payload).await
+            service.handle(ctx, method, payload).await
         } else {
             Err(Error::service_not_found(service_name))
         }
     }
 
+    /// Whether a local service handles `service.method`
+    pub async fn handles(&self, service_method: &str) -> bool {
+        match service_method.split_once('.') {
+            Some((service_name, _)) => self.services.read().await.contains_key(service_name),
+            None => false,
+        }
+    }
+
     pub async fn list_services(&self) -> Vec<String> {
         let services = self.services.read().await;
         services.keys().cloned().collect()
@@ -92,22 +141,52 @@ pub struct ProcessHub {
         Arc<RwLock<std::collections::HashMap<Uuid, tokio::sync::oneshot::Sender<Message>>>>,
     /// Remote service directory for cross-process service discovery
     remote_services: Arc<RwLock<std::collections::HashMap<String, ServiceInfo>>>,
+    /// Subscriber and message handling failures
+    failures: tokio::sync::broadcast::Sender<Failure>,
+    /// Topic prefix where events that failed a subscriber are republished
+    dead_letter_topic: Arc<std::sync::RwLock<Option<String>>>,
+    /// Layers wrapped around services as they are registered
+    service_layers: Arc<std::sync::RwLock<Vec<Arc<dyn ServiceLayer>>>>,
+    /// Layers wrapped around outgoing calls
+    call_layers: Arc<std::sync::RwLock<Vec<Arc<dyn CallLayer>>>>,
+    /// Task repeating shared group memberships, stopped on shutdown
+    heartbeat: Arc<tokio::task::AbortHandle>,
 }
 
 impl ProcessHub {
     /// Create a new ProcessHub
     pub async fn new(name: &str) -> Result<Self> {
-        let transport = IpmbTransport::new(name).await?;
+        let transport: Arc<dyn Transport> = Arc::new(IpmbTransport::new(name).await?);
+
+        // Forward shared subscription announcements to the bus
+        let (announce_tx, mut announce_rx) = tokio::sync::mpsc::unbounded_channel();
+        let announce_transport = transport.clone();
+        tokio::spawn(async move {
+            while let Some(msg) = announce_rx.recv().await {
+                let _ = announce_transport.send(msg).await;
+            }
+        });
 
+        let (failure_tx, failure_rx) = tokio::sync::mpsc::unbounded_channel();
+        let subscription_registry =
+            Arc::new(SubscriptionRegistry::for_hub(name, announce_tx, failure_tx));
+        let heartbeat = Self::start_heartbeat(name, &transport, &subscription_registry);
         let hub = Self {
             name: name.to_string(),
-            transport: Arc::new(transport),
+            transport,
             service_registry: Arc::new(ServiceRegistry::new()),
-            subscription_registry: Arc::new(SubscriptionRegistry::new()),
+            subscription_registry,
             pending_requests: Arc::new(RwLock::new(std::collections::HashMap::new())),
             remote_services: Arc::new(RwLock::new(std::collections::HashMap::new())),
+            failures: tokio::sync::broadcast::channel(256).0,
+            dead_letter_topic: Arc::new(std::sync::RwLock::new(None)),
+            service_layers: Arc::new(std::sync::RwLock::new(Vec::new())),
+            call_layers: Arc::new(std::sync::RwLock::new(Vec::new())),
+            heartbeat: Arc::new(heartbeat),
         };
 
+        hub.start_failure_loop(failure_rx);
+
         // Start message processing
         hub.start_message_loop().await;
 
@@ -122,6 +201,78 @@ impl ProcessHub {
         Ok(hub)
     }
 
+    /// Repeat local shared group memberships and drop silent remote members
+    fn start_heartbeat(
+        name: &str,
+        transport: &Arc<dyn Transport>,
+        subscription_registry: &Arc<SubscriptionRegistry>,
+    ) -> tokio::task::AbortHandle {
+        let transport = transport.clone();
+        let subscription_registry = subscription_registry.clone();
+        let hub_name = name.to_string();
+
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(HEARTBEAT_INTERVAL);
+            loop {
+                interval.tick().await;
+                let members = subscription_registry.local_shared_members();
+                if !members.is_empty() {
+                    let heartbeat = Message::shared_heartbeat(hub_name.clone(), &members);
+                    let _ = transport.send(heartbeat).await;
+                }
+
+                let expired = subscription_registry.expire_shared_members(MEMBER_TIMEOUT);
+                if expired > 0 {
+                    tracing::warn!(
+                        "Dropped {} shared subscription members that stopped sending heartbeats",
+                        expired
+                    );
+                }
+            }
+        })
+        .abort_handle()
+    }
+
+    /// Report subscriber failures and republish them to the dead-letter topic
+    fn start_failure_loop(&self, mut failure_rx: tokio::sync::mpsc::UnboundedReceiver<Failure>) {
+        let transport = self.transport.clone();
+        // The registry owns the sending side, so only hold it weakly here
+        let subscription_registry = Arc::downgrade(&self.subscription_registry);
+        let failures = self.failures.clone();
+        let dead_letter_topic = self.dead_letter_topic.clone();
+        let hub_name = self.name.clone();
+
+        tokio::spawn(async move {
+            while let Some(failure) = failure_rx.recv().await {
+                tracing::warn!(
+                    "Subscriber {:?} failed on {:?}: {}",
+                    failure.subscription,
+                    failure.topic,
+                    failure.error
+                );
+
+                let dead_letter = dead_letter_topic
+                    .read()
+                    .unwrap()
+                    .as_deref()
+                    .and_then(|prefix| failure.dead_letter_topic(prefix));
+                if let (Some(topic), Some(registry)) =
+                    (dead_letter, subscription_registry.upgrade())
+                {
+                    // Keep the headers and schema version, so tracing and upcasting still work
+                    let mut msg =
+                        Message::event(hub_name.clone(), topic, failure.payload.clone());
+                    msg.metadata.headers = failure.headers.clone();
+                    msg.metadata.schema_version = failure.schema_version;
+                    let _ = registry.publish_message(&msg).await;
+                    let _ = transport.send(msg).await;
+                }
+
+                let _ = failures.send(failure);
+            }
+        });
+    }
+
     /// Start the message processing loop
     async fn start_message_loop(&self) {
         let transport = self.transport.clone();
@@ -130,10 +281,12 @@ impl ProcessHub {
         let pending_requests = self.pending_requests.clone();
         let remote_services = self.remote_services.clone();
         let hub_name = self.name.clone();
+        let failures = self.failures.clone();
 
         tokio::spawn(async move {
             while let Ok(msg) = transport.recv().await {
-                let _ = Self::process_message(
+                let (topic, source, message_id) = (msg.topic.clone(), msg.source.clone(), msg.id);
+                let result = Self::process_message(
                     msg,
                     &service_registry,
                     &subscription_registry,
@@ -143,6 +296,20 @@ impl ProcessHub {
                     &hub_name,
                 )
                 .await;
+
+                if let Err(error) = result {
+                    tracing::warn!("Failed to process message {}: {}", message_id, error);
+                    let _ = failures.send(Failure {
+                        topic,
+                        subscription: None,
+                        source,
+                        message_id,
+                        payload: Vec::new(),
+                        headers: Default::default(),
+                        schema_version: 0,
+                        error: Arc::new(error),
+                    });
+                }
             }
         });
     }
@@ -163,7 +330,11 @@ impl ProcessHub {
             MessageType::Request => {
                 // Handle service request
                 if let Some(ref topic) = msg.topic {
-                    match service_registry.call(topic, msg.payload.clone()).await {
+                    let ctx = RequestContext::from_message(&msg);
+                    match service_registry
+                        .call(&ctx, topic, msg.payload.clone())
+                        .await
+                    {
                         Ok(result) => {
                             let response = Message::response(&msg, result);
                             let _ = transport.send(response).await;
@@ -174,6 +345,7 @@ impl ProcessHub {
                                 Message::response(&msg, error_msg.into_bytes());
                             error_response.msg_type = MessageType::Error;
                             let _ = transport.send(error_response).await;
+                            return Err(e);
                         }
                     }
                 }
@@ -187,12 +359,21 @@ impl ProcessHub {
                     }
                 }
             }
-            MessageType::Event => {
-                // Handle event for subscribers
-                if let Some(ref topic) = msg.topic {
-                    let _ = subscription_registry.publish(topic, msg.payload).await;
+            // Our own events were already delivered locally when published
+            MessageType::Event if msg.source != hub_name => {
+                if let Some(ack) = subscription_registry.receive_message(&msg).await? {
+                    let _ = transport.send(ack).await;
                 }
             }
+            MessageType::Subscribe | MessageType::Unsubscribe => {
+                // Handle shared subscription membership changes
+                for reply in subscription_registry.handle_membership(&msg) {
+                    let _ = transport.send(reply).await;
+                }
+            }
+            MessageType::Heartbeat if msg.source != hub_name => {
+                subscription_registry.handle_heartbeat(&msg);
+            }
             MessageType::ServiceRegister => {
                 // Handle remote service registration
                 if let Ok(service_info) = bincode::deserialize::<ServiceInfo>(&msg.payload) {
@@ -322,10 +503,13 @@ impl ProcessHub {
         let service_name = service.name().to_string();
         let methods: Vec<String> = service.methods().iter().map(|&s| s.to_string()).collect();
 
-        // Register locally first
-        self.service_registry
-            .register(std::sync::Arc::new(service))
-            .await?;
+        // Register locally first, wrapped in the current service layers
+        let mut service: Arc<dyn Service> = Arc::new(service);
+        let layers = self.service_layers.read().unwrap().clone();
+        if !layers.is_empty() {
+            service = Arc::new(LayeredService::new(service, layers));
+        }
+        self.service_registry.register(service).await?;
 
         // Broadcast service registration to other processes
         let service_info = ServiceInfo {
@@ -357,13 +541,65 @@ impl ProcessHub {
         service_method: &str,
         request: T,
     ) -> Result<R> {
-        // First try local service
-        if let Ok(result) = self
-            .service_registry
-            .call(service_method, bincode::serialize(&request)?)
+        self.call_with(service_method, request, CallOptions::default())
             .await
-        {
-            return Ok(bincode::deserialize(&result)?);
+    }
+
+    /// Call a service method with a timeout, priority and headers
+    pub async fn call_with<T: Serialize, R: for<'de> Deserialize<'de>>(
+        &self,
+        service_method: &str,
+        request: T,
+        options: CallOptions,
+    ) -> Result<R> {
+        let call = Call {
+            service_method: service_method.to_string(),
+            payload: bincode::serialize(&request)?,
+            options,
+        };
+
+        let layers = self.call_layers.read().unwrap().clone();
+        let response = CallNext::new(&layers, self).run(call).await?;
+        Ok(bincode::deserialize(&response)?)
+    }
+
+    /// Send a call past the call layers, returning the serialized response
+    pub(crate) async fn send_call(&self, call: Call) -> Result<Vec<u8>> {
+        let Call {
+            service_method,
+            payload,
+            options,
+        } = call;
+        let service_method = service_method.as_str();
+        let timeout_ms = options.timeout.as_millis() as u64;
+        let request_id = uuid::Uuid::new_v4();
+        let mut msg = Message {
+            id: request_id,
+            msg_type: MessageType::Request,
+            source: self.name.clone(),
+            target: None,
+            topic: Some(service_method.to_string()),
+            payload,
+            correlation_id: Some(request_id),
+            metadata: crate::message::MessageMetadata {
+                priority: options.priority,
+                ttl: Some(timeout_ms),
+                headers: options.headers,
+                ..Default::default()
+            },
+        };
+
+        // Local services are called directly, without a transport round trip,
+        // but still only waited on for the call's timeout
+        if self.service_registry.handles(service_method).await {
+            let ctx = RequestContext::from_message(&msg);
+            return tokio::time::timeout(
+                options.timeout,
+                self.service_registry
+                    .call(&ctx, service_method, msg.payload),
+            )
+            .await
+            .map_err(|_| Error::timeout("service call", timeout_ms))?;
         }
 
         // If not found locally, check remote services
@@ -393,18 +629,8 @@ impl ProcessHub {
                 .map(|info| info.process_name.clone())
         };
 
-        let payload = bincode::serialize(&request)?;
-        let request_id = uuid::Uuid::new_v4();
-        let msg = Message {
-            id: request_id,
-            msg_type: MessageType::Request,
-            source: self.name.clone(),
-            target: target_process, // Direct to specific process if known
-            topic: Some(service_method.to_string()),
-            payload,
-            correlation_id: Some(request_id),
-            metadata: crate::message::MessageMetadata::default(),
-        };
+        // Direct to specific process if known
+        msg.target = target_process;
 
         // Set up response receiver
         let (tx, rx) = tokio::sync::oneshot::channel();
@@ -417,16 +643,13 @@ impl ProcessHub {
         self.transport.send(msg).await?;
 
         // Wait for response with timeout
-        let response = tokio::time::timeout(Duration::from_secs(30), rx)
+        let response = tokio::time::timeout(options.timeout, rx)
             .await
-            .map_err(|_| Error::timeout("service call", 30000))?
+            .map_err(|_| Error::timeout("service call", timeout_ms))?
             .map_err(|_| Error::connection_msg("response channel closed"))?;
 
         match response.msg_type {
-            MessageType::Response => {
-                let result: R = bincode::deserialize(&response.payload)?;
-                Ok(result)
-            }
+            MessageType::Response => Ok(response.payload),
             MessageType::Error => {
                 let error_msg = String::from_utf8_lossy(&response.payload);
                 Err(Error::runtime_msg(format!("Remote error: {error_msg}")))
@@ -450,21 +673,366 @@ impl ProcessHub {
         self.subscription_registry.subscribe(subscriber).await
     }
 
+    /// Subscribe to events with explicit queueing options
+    pub async fn subscribe_with<S: Subscriber>(
+        &self,
+        subscriber: S,
+        options: SubscribeOptions,
+    ) -> Result<Subscription> {
+        self.subscription_registry
+            .subscribe_with(subscriber, options)
+            .await
+    }
+
+    /// Subscribe to events of type `E` as a stream of `(topic, event)` pairs
+    ///
+    /// Dropping the returned stream unsubscribes.
+    pub async fn subscribe_typed<E: Event>(&self, pattern: &str) -> Result<EventStream<E>> {
+        self.subscribe_typed_with(pattern, SubscribeOptions::default())
+            .await
+    }
+
+    /// Subscribe to events of type `E` as a stream, with explicit queueing options
+    pub async fn subscribe_typed_with<E: Event>(
+        &self,
+        pattern: &str,
+        options: SubscribeOptions,
+    ) -> Result<EventStream<E>> {
+        self.subscription_registry
+            .subscribe_typed_with(pattern, options)
+            .await
+    }
+
+    /// Subscribe to events of type `E` with an async closure
+    ///
+    /// ```rust,ignore
+    /// let _sub = hub
+    ///     .on("sensor/+", |topic, event: TemperatureEvent| async move {
+    ///         println!("{topic}: {}", event.value);
+    ///         Ok(())
+    ///     })
+    ///     .await?;
+    /// ```
+    pub async fn on<E, F, Fut>(&self, pattern: &str, handler: F) -> Result<Subscription>
+    where
+        E: Event,
+        F: Fn(String, E) -> Fut + Send + Sync + 'static,
+        Fut: std::future::Future<Output = Result<()>> + Send + 'static,
+    {
+        self.on_with(pattern, SubscribeOptions::default(), handler)
+            .await
+    }
+
+    /// Subscribe to events of type `E` with an async closure and explicit queueing options
+    pub async fn on_with<E, F, Fut>(
+        &self,
+        pattern: &str,
+        options: SubscribeOptions,
+        handler: F,
+    ) -> Result<Subscription>
+    where
+        E: Event,
+        F: Fn(String, E) -> Fut + Send + Sync + 'static,
+        Fut: std::future::Future<Output = Result<()>> + Send + 'static,
+    {
+        self.subscription_registry
+            .subscribe_fn(pattern, options, handler)
+            .await
+    }
+
+    /// Subscribe to events of type `E` with a synchronous closure
+    ///
+    /// The closure runs on the blocking thread pool, one event at a time in
+    /// delivery order, so it may block without stalling the hub.
+    pub async fn on_sync<E, F>(&self, pattern: &str, handler: F) -> Result<Subscription>
+    where
+        E: Event,
+        F: Fn(&str, E) -> Result<()> + Send + Sync + 'static,
+    {
+        self.on_sync_with(pattern, SubscribeOptions::default(), handler)
+            .await
+    }
+
+    /// Subscribe to events of type `E` with a synchronous closure and explicit queueing options
+    pub async fn on_sync_with<E, F>(
+        &self,
+        pattern: &str,
+        options: SubscribeOptions,
+        handler: F,
+    ) -> Result<Subscription>
+    where
+        E: Event,
+        F: Fn(&str, E) -> Result<()> + Send + Sync + 'static,
+    {
+        self.subscription_registry
+            .subscribe_sync_fn(pattern, options, handler)
+            .await
+    }
+
+    /// Subscribe to events of type `E` with an async closure receiving each
+    /// event's [`EventContext`], such as its source and headers
+    ///
+    /// ```rust,ignore
+    /// let _sub = hub
+    ///     .on_event("sensor/+", |ctx, event: TemperatureEvent| async move {
+    ///         println!("{} from {}: {}", ctx.topic, ctx.source, event.value);
+    ///         Ok(())
+    ///     })
+    ///     .await?;
+    /// ```
+    pub async fn on_event<E, F, Fut>(&self, pattern: &str, handler: F) -> Result<Subscription>
+    where
+        E: Event,
+        F: Fn(EventContext, E) -> Fut + Send + Sync + 'static,
+        Fut: std::future::Future<Output = Result<()>> + Send + 'static,
+    {
+        self.on_event_with(pattern, SubscribeOptions::default(), handler)
+            .await
+    }
+
+    /// Subscribe to events of type `E` and their context with an async
+    /// closure and explicit queueing options
+    pub async fn on_event_with<E, F, Fut>(
+        &self,
+        pattern: &str,
+        options: SubscribeOptions,
+        handler: F,
+    ) -> Result<Subscription>
+    where
+        E: Event,
+        F: Fn(EventContext, E) -> Fut + Send + Sync + 'static,
+        Fut: std::future::Future<Output = Result<()>> + Send + 'static,
+    {
+        self.subscription_registry
+            .subscribe_event_fn(pattern, options, handler)
+            .await
+    }
+
+    /// Subscribe to events of type `E` and their context with a synchronous closure
+    ///
+    /// Runs like [`on_sync`](Self::on_sync) closures, on the blocking thread pool.
+    pub async fn on_sync_event<E, F>(&self, pattern: &str, handler: F) -> Result<Subscription>
+    where
+        E: Event,
+        F: Fn(&EventContext, E) -> Result<()> + Send + Sync + 'static,
+    {
+        self.on_sync_event_with(pattern, SubscribeOptions::default(), handler)
+            .await
+    }
+
+    /// Subscribe to events of type `E` and their context with a synchronous
+    /// closure and explicit queueing options
+    pub async fn on_sync_event_with<E, F>(
+        &self,
+        pattern: &str,
+        options: SubscribeOptions,
+        handler: F,
+    ) -> Result<Subscription>
+    where
+        E: Event,
+        F: Fn(&EventContext, E) -> Result<()> + Send + Sync + 'static,
+    {
+        self.subscription_registry
+            .subscribe_sync_event_fn(pattern, options, handler)
+            .await
+    }
+
+    /// Record every event this hub publishes or receives in `store`
+    ///
+    /// Subscriptions created with `SubscribeOptions::replay` read it back
+    /// before switching to live delivery. Pass `None` to stop recording.
+    pub fn set_event_store(&self, store: Option<EventStore>) {
+        self.subscription_registry
+            .set_event_store(store.map(Arc::new));
+    }
+
+    /// Decode `E` events published with schema version `from_version` as `Old`
+    ///
+    /// Lets subscribers of the current `E` keep receiving events from
+    /// processes that still publish an older version.
+    pub fn register_upcaster<E, Old, F>(&self, from_version: u32, upcast: F) -> Result<()>
+    where
+        E: Event,
+        Old: for<'de> Deserialize<'de> + 'static,
+        F: Fn(Old) -> E + Send + Sync + 'static,
+    {
+        self.subscription_registry
+            .register_upcaster(from_version, upcast)
+    }
+
     /// Publish an event
+    ///
+    /// Events with a non-zero `Event::VERSION` carry their schema version in
+    /// the message metadata.
     pub async fn publish_event<E: Event>(&self, event: E) -> Result<()> {
+        self.publish_event_with(event, PublishOptions::default())
+            .await?;
+        Ok(())
+    }
+
+    /// Publish an event with explicit delivery options
+    ///
+    /// With `Qos::AtLeastOnce` this resolves once a hub has confirmed one of
+    /// its subscribers has the event, or fails with a timeout once the retries
+    /// run out. When this hub's own subscription confirms, the copy for other
+    /// processes keeps being resent in the background until one of them acks.
+    pub async fn publish_event_with<E: Event>(
+        &self,
+        event: E,
+        options: PublishOptions,
+    ) -> Result<PublishReceipt> {
         let topic = event.topic();
+        validate_topic_name(&topic)?;
         let payload = bincode::serialize(&event)?;
-        let msg = Message::event(self.name.clone(), topic, payload);
+        let mut msg = Message::event(self.name.clone(), topic, payload);
+        msg.metadata.schema_version = E::VERSION;
 
-        self.transport.send(msg).await
+        self.send_event(msg, &options).await
     }
 
     /// Publish to a specific topic
     pub async fn publish<T: Serialize>(&self, topic: &str, payload: T) -> Result<()> {
+        self.publish_with(topic, payload, PublishOptions::default())
+            .await?;
+        Ok(())
+    }
+
+    /// Publish to a specific topic with explicit delivery options
+    pub async fn publish_with<T: Serialize>(
+        &self,
+        topic: &str,
+        payload: T,
+        options: PublishOptions,
+    ) -> Result<PublishReceipt> {
+        validate_topic_name(topic)?;
         let serialized = bincode::serialize(&payload)?;
         let msg = Message::event(self.name.clone(), topic.to_string(), serialized);
 
-        self.transport.send(msg).await
+        self.send_event(msg, &options).await
+    }
+
+    /// Deliver an event to local subscribers directly, then to other processes
+    async fn send_event(
+        &self,
+        mut msg: Message,
+        options: &PublishOptions,
+    ) -> Result<PublishReceipt> {
+        let message_id = msg.id;
+        msg.metadata.headers.extend(options.headers.clone());
+        if options.qos == Qos::AtMostOnce {
+            self.subscription_registry.publish_message(&msg).await?;
+            self.transport.send(msg).await?;
+            return Ok(PublishReceipt {
+                message_id,
+                confirmed_by: None,
+                attempts: 1,
+            });
+        }
+
+        // Ask receivers to ack; acks arrive as responses to the event ID
+        msg.correlation_id = Some(message_id);
+        let (tx, mut rx) = tokio::sync::oneshot::channel();
+        self.pending_requests.write().await.insert(message_id, tx);
+
+        // A local subscription taking the event confirms it right away, while
+        // the copy for other processes is still resent until one of them acks
+        if self.subscription_registry.publish_message(&msg).await? > 0 {
+            let transport = self.transport.clone();
+            let pending_requests = self.pending_requests.clone();
+            let options = options.clone();
+            tokio::spawn(async move {
+                if Self::send_until_acked(&transport, &msg, &mut rx, &options)
+                    .await
+                    .is_none()
+                {
+                    pending_requests.write().await.remove(&message_id);
+                    tracing::debug!("No other process confirmed event {}", message_id);
+                }
+            });
+            return Ok(PublishReceipt {
+                message_id,
+                confirmed_by: Some(self.name.clone()),
+                attempts: 1,
+            });
+        }
+
+        if let Some((ack, attempts)) =
+            Self::send_until_acked(&self.transport, &msg, &mut rx, options).await
+        {
+            return Ok(PublishReceipt {
+                message_id,
+                confirmed_by: Some(ack.source),
+                attempts,
+            });
+        }
+
+        self.pending_requests.write().await.remove(&message_id);
+        let waited = options.ack_timeout * options.max_retries.saturating_add(1);
+        Err(Error::timeout(
+            format!(
+                "delivery confirmation of event on {}",
+                msg.topic.as_deref().unwrap_or_default()
+            ),
+            waited.as_millis() as u64,
+        ))
+    }
+
+    /// Send an at-least-once event to other processes until one of them acks it
+    ///
+    /// Returns the ack and the number of sends, or `None` once the retries run out.
+    async fn send_until_acked(
+        transport: &Arc<dyn Transport>,
+        msg: &Message,
+        rx: &mut tokio::sync::oneshot::Receiver<Message>,
+        options: &PublishOptions,
+    ) -> Option<(Message, u32)> {
+        for attempt in 1..=options.max_retries.saturating_add(1) {
+            if let Err(e) = transport.send(msg.clone()).await {
+                tracing::warn!("Failed to send event {}: {}", msg.id, e);
+            }
+            match tokio::time::timeout(options.ack_timeout, &mut *rx).await {
+                Ok(Ok(ack)) => return Some((ack, attempt)),
+                Ok(Err(_)) => break,
+                Err(_) => {}
+            }
+        }
+        None
+    }
+
+    /// Receive subscriber and message handling failures
+    ///
+    /// Each receiver gets every failure reported after it was created. Failed
+    /// service requests are also answered with an error to the caller.
+    pub fn failures(&self) -> tokio::sync::broadcast::Receiver<Failure> {
+        self.failures.subscribe()
+    }
+
+    /// Republish events that a subscriber failed to handle under `prefix`
+    ///
+    /// An event on `sensor/temp` that fails is published again, unchanged and
+    /// with its headers and schema version, on `{prefix}/sensor/temp`. Failures of dead-lettered events are only
+    /// reported. Pass `None` to turn dead-lettering off.
+    pub fn set_dead_letter_topic(&self, prefix: Option<&str>) -> Result<()> {
+        if let Some(prefix) = prefix {
+            validate_topic_name(prefix)?;
+        }
+        *self.dead_letter_topic.write().unwrap() = prefix.map(str::to_string);
+        Ok(())
+    }
+
+    /// Wrap services registered from now on in `layer`
+    ///
+    /// Layers run in the order they were added, the first one outermost.
+    /// Services registered earlier keep the layers they were registered with.
+    pub fn add_service_layer<L: ServiceLayer>(&self, layer: L) {
+        self.service_layers.write().unwrap().push(Arc::new(layer));
+    }
+
+    /// Wrap the send path of every call from now on in `layer`
+    ///
+    /// Layers run in the order they were added, the first one outermost.
+    pub fn add_call_layer<L: CallLayer>(&self, layer: L) {
+        self.call_layers.write().unwrap().push(Arc::new(layer));
     }
 
     /// Get the process name
@@ -473,26 +1041,80 @@ impl ProcessHub {
     }
 
     /// Shutdown the hub
+    ///
+    /// Shared subscription groups are told that this process is leaving.
     pub async fn shutdown(&self) -> Result<()> {
+        self.heartbeat.abort();
+        for member in self.subscription_registry.local_shared_members() {
+            let _ = self
+                .transport
+                .send(Message::shared_leave(self.name.clone(), &member))
+                .await;
+        }
         self.transport.close().await
     }
 }
 
 /// Synchronous wrapper for ProcessHub
+#[derive(Clone)]
 pub struct SyncProcessHub {
-    runtime: tokio::runtime::Runtime,
+    runtime: Arc<OwnedRuntime>,
     hub: ProcessHub,
 }
 
+/// The Tokio runtime driving a `SyncProcessHub`
+struct OwnedRuntime(Option<tokio::runtime::Runtime>);
+
+impl OwnedRuntime {
+    /// Run a future to completion on this runtime
+    ///
+    /// Blocking on a runtime from inside another one panics, so from async
+    /// contexts and blocking-pool threads the future is driven on a helper
+    /// thread instead. The hub's message loop keeps running on this runtime
+    /// either way, so the caller can't starve it.
+    fn block_on<F>(&self, future: F) -> F::Output
+    where
+        F: std::future::Future + Send,
+        F::Output: Send,
+    {
+        let runtime = self.0.as_ref().expect("runtime is only taken on drop");
+        if tokio::runtime::Handle::try_current().is_err() {
+            return runtime.block_on(future);
+        }
+
+        std::thread::scope(|scope| {
+            scope
+                .spawn(|| runtime.block_on(future))
+                .join()
+                .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+        })
+    }
+}
+
+impl Drop for OwnedRuntime {
+    fn drop(&mut self) {
+        // Dropping a runtime inside another one panics; shut it down in the background
+        if let Some(runtime) = self.0.take() {
+            if tokio::runtime::Handle::try_current().is_ok() {
+                runtime.shutdown_background();
+            }
+        }
+    }
+}
+
 impl SyncProcessHub {
     /// Create a new synchronous ProcessHub
     pub fn new(name: &str) -> Result<Self> {
         let runtime = tokio::runtime::Runtime::new()
             .map_err(|e| Error::runtime_msg(format!("Failed to create runtime: {e}")))?;
 
+        let runtime = OwnedRuntime(Some(runtime));
         let hub = runtime.block_on(ProcessHub::new(name))?;
 
-        Ok(Self { runtime, hub })
+        Ok(Self {
+            runtime: Arc::new(runtime),
+            hub,
+        })
     }
 
     /// Register a service synchronously
@@ -501,7 +1123,7 @@ impl SyncProcessHub {
     }
 
     /// Call a service method synchronously
-    pub fn call<T: Serialize, R: for<'de> Deserialize<'de>>(
+    pub fn call<T: Serialize + Send, R: for<'de> Deserialize<'de> + Send>(
         &self,
         service_method: &str,
         request: T,
@@ -510,18 +1132,37 @@ impl SyncProcessHub {
             .block_on(self.hub.call(service_method, request))
     }
 
+    /// Call a service method synchronously with a timeout, priority and headers
+    pub fn call_with<T: Serialize + Send, R: for<'de> Deserialize<'de> + Send>(
+        &self,
+        service_method: &str,
+        request: T,
+        options: CallOptions,
+    ) -> Result<R> {
+        self.runtime
+            .block_on(self.hub.call_with(service_method, request, options))
+    }
+
     /// Subscribe to events synchronously
     pub fn subscribe<S: Subscriber>(&self, subscriber: S) -> Result<Subscription> {
         self.runtime.block_on(self.hub.subscribe(subscriber))
     }
 
+    /// Subscribe with a synchronous subscriber
+    ///
+    /// `handle_sync` runs on the blocking thread pool, one event at a time in
+    /// delivery order, so it may block without stalling the hub.
+    pub fn subscribe_sync<S: SyncSubscriber>(&self, subscriber: S) -> Result<Subscription> {
+        self.subscribe(SyncSubscriberAdapter::new(subscriber))
+    }
+
     /// Publish an event synchronously
     pub fn publish_event<E: Event>(&self, event: E) -> Result<()> {
         self.runtime.block_on(self.hub.publish_event(event))
     }
 
     /// Publish to a specific topic synchronously
-    pub fn publish<T: Serialize>(&self, topic: &str, payload: T) -> Result<()> {
+    pub fn publish<T: Serialize + Send>(&self, topic: &str, payload: T) -> Result<()> {
         self.runtime.block_on(self.hub.publish(topic, payload))
     }
 
@@ -0,0 +1,315 @@
+//! Middleware layers around services and service calls
+//!
+//! A [`ServiceLayer`] wraps `Service::handle` of every service registered on a
+//! hub after the layer was added; a [`CallLayer`] wraps the send path of
+//! `ProcessHub::call`. Layers run in the order they were added, the first one
+//! outermost, and pass the request on with `next.run(...)`. A layer may stop
+//! early by returning without calling `next`, or call it more than once to
+//! retry.
+//!
+//! ```rust,ignore
+//! struct RequireAuth;
+//!
+//! #[hsipc::async_trait]
+//! impl ServiceLayer for RequireAuth {
+//!     async fn handle(
+//!         &self,
+//!         ctx: &RequestContext,
+//!         method: &str,
+//!         payload: Vec<u8>,
+//!         next: ServiceNext<'_>,
+//!     ) -> Result<Vec<u8>> {
+//!         if ctx.header("authorization").is_none() {
+//!             return Err(Error::invalid_request("missing authorization", None));
+//!         }
+//!         next.run(ctx, method, payload).await
+//!     }
+//! }
+//!
+//! hub.add_service_layer(RequireAuth);
+//! hub.add_call_layer(LoggingLayer);
+//! ```
+
+use crate::context::{CallOptions, RequestContext};
+use crate::hub::{ProcessHub, Service};
+use crate::Result;
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Middleware around the `Service::handle` of registered services
+#[async_trait]
+pub trait ServiceLayer: Send + Sync + 'static {
+    async fn handle(
+        &self,
+        ctx: &RequestContext,
+        method: &str,
+        payload: Vec<u8>,
+        next: ServiceNext<'_>,
+    ) -> Result<Vec<u8>>;
+}
+
+/// The layers and service after a [`ServiceLayer`]
+#[derive(Clone, Copy)]
+pub struct ServiceNext<'a> {
+    layers: &'a [Arc<dyn ServiceLayer>],
+    service: &'a dyn Service,
+}
+
+impl<'a> ServiceNext<'a> {
+    /// Name of the wrapped service
+    pub fn service_name(&self) -> &'static str {
+        self.service.name()
+    }
+
+    /// Pass the request to the next layer, or to the service after the last one
+    pub async fn run(
+        self,
+        ctx: &RequestContext,
+        method: &str,
+        payload: Vec<u8>,
+    ) -> Result<Vec<u8>> {
+        match self.layers.split_first() {
+            Some((layer, layers)) => {
+                let next = ServiceNext {
+                    layers,
+                    service: self.service,
+                };
+                layer.handle(ctx, method, payload, next).await
+            }
+            None => self.service.handle(ctx, method, payload).await,
+        }
+    }
+}
+
+/// A service wrapped in the hub's service layers when it was registered
+pub(crate) struct LayeredService {
+    inner: Arc<dyn Service>,
+    layers: Vec<Arc<dyn ServiceLayer>>,
+}
+
+impl LayeredService {
+    pub fn new(inner: Arc<dyn Service>, layers: Vec<Arc<dyn ServiceLayer>>) -> Self {
+        Self { inner, layers }
+    }
+}
+
+#[async_trait]
+impl Service for LayeredService {
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+
+    fn methods(&self) -> Vec<&'static str> {
+        self.inner.methods()
+    }
+
+    async fn handle(
+        &self,
+        ctx: &RequestContext,
+        method: &str,
+        payload: Vec<u8>,
+    ) -> Result<Vec<u8>> {
+        let next = ServiceNext {
+            layers: &self.layers,
+            service: &*self.inner,
+        };
+        next.run(ctx, method, payload).await
+    }
+}
+
+/// An outgoing service call, before it is sent
+#[derive(Debug, Clone)]
+pub struct Call {
+    /// `service.method` being called
+    pub service_method: String,
+    /// Serialized request
+    pub payload: Vec<u8>,
+    pub options: CallOptions,
+}
+
+/// Middleware around the send path of `ProcessHub::call`
+///
+/// Layers receive the serialized request and return the serialized response,
+/// so they can add headers, time or retry calls of any type.
+#[async_trait]
+pub trait CallLayer: Send + Sync + 'static {
+    async fn call(&self, call: Call, next: CallNext<'_>) -> Result<Vec<u8>>;
+}
+
+/// The layers and hub after a [`CallLayer`]
+#[derive(Clone, Copy)]
+pub struct CallNext<'a> {
+    layers: &'a [Arc<dyn CallLayer>],
+    hub: &'a ProcessHub,
+}
+
+impl<'a> CallNext<'a> {
+    pub(crate) fn new(layers: &'a [Arc<dyn CallLayer>], hub: &'a ProcessHub) -> Self {
+        Self { layers, hub }
+    }
+
+    /// Pass the call to the next layer, or send it after the last one
+    pub async fn run(self, call: Call) -> Result<Vec<u8>> {
+        match self.layers.split_first() {
+            Some((layer, layers)) => {
+                let next = CallNext {
+                    layers,
+                    hub: self.hub,
+                };
+                layer.call(call, next).await
+            }
+            None => self.hub.send_call(call).await,
+        }
+    }
+}
+
+/// Logs each request and its outcome with `tracing`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LoggingLayer;
+
+#[async_trait]
+impl ServiceLayer for LoggingLayer {
+    async fn handle(
+        &self,
+        ctx: &RequestContext,
+        method: &str,
+        payload: Vec<u8>,
+        next: ServiceNext<'_>,
+    ) -> Result<Vec<u8>> {
+        let service = next.service_name();
+        tracing::info!("📥 {}.{} from {}", service, method, ctx.caller);
+        let result = next.run(ctx, method, payload).await;
+        match &result {
+            Ok(_) => tracing::info!("✅ {}.{} from {} handled", service, method, ctx.caller),
+            Err(e) => tracing::warn!(
+                "❌ {}.{} from {} failed: {}",
+                service,
+                method,
+                ctx.caller,
+                e
+            ),
+        }
+        result
+    }
+}
+
+#[async_trait]
+impl CallLayer for LoggingLayer {
+    async fn call(&self, call: Call, next: CallNext<'_>) -> Result<Vec<u8>> {
+        let service_method = call.service_method.clone();
+        tracing::info!("📤 Calling {}", service_method);
+        let result = next.run(call).await;
+        match &result {
+            Ok(_) => tracing::info!("✅ {} returned", service_method),
+            Err(e) => tracing::warn!("❌ {} failed: {}", service_method, e),
+        }
+        result
+    }
+}
+
+/// Durations of the requests seen by a [`TimingLayer`] for one method
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Timing {
+    pub calls: u64,
+    pub errors: u64,
+    pub total: Duration,
+    pub max: Duration,
+}
+
+impl Timing {
+    /// Average duration of a request
+    pub fn mean(&self) -> Duration {
+        match self.calls {
+            0 => Duration::ZERO,
+            calls => Duration::from_nanos((self.total.as_nanos() / u128::from(calls)) as u64),
+        }
+    }
+
+    fn record(&mut self, elapsed: Duration, failed: bool) {
+        self.calls += 1;
+        self.errors += u64::from(failed);
+        self.total += elapsed;
+        self.max = self.max.max(elapsed);
+    }
+}
+
+/// Measures how long requests take, per `service.method`
+///
+/// Clones share their timings, so keep one to read them after adding the layer.
+#[derive(Debug, Clone, Default)]
+pub struct TimingLayer {
+    timings: Arc<Mutex<HashMap<String, Timing>>>,
+}
+
+impl TimingLayer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Timings recorded so far, keyed by `service.method`
+    pub fn timings(&self) -> HashMap<String, Timing> {
+        self.timings.lock().unwrap().clone()
+    }
+
+    fn record<T>(&self, service_method: String, started: Instant, result: &Result<T>) {
+        let elapsed = started.elapsed();
+        tracing::debug!("⏱️ {} took {:?}", service_method, elapsed);
+        self.timings
+            .lock()
+            .unwrap()
+            .entry(service_method)
+            .or_default()
+            .record(elapsed, result.is_err());
+    }
+}
+
+#[async_trait]
+impl ServiceLayer for TimingLayer {
+    async fn handle(
+        &self,
+        ctx: &RequestContext,
+        method: &str,
+        payload: Vec<u8>,
+        next: ServiceNext<'_>,
+    ) -> Result<Vec<u8>> {
+        let started = Instant::now();
+        let result = next.run(ctx, method, payload).await;
+        self.record(
+            format!("{}.{}", next.service_name(), method),
+            started,
+            &result,
+        );
+        result
+    }
+}
+
+#[async_trait]
+impl CallLayer for TimingLayer {
+    async fn call(&self, call: Call, next: CallNext<'_>) -> Result<Vec<u8>> {
+        let service_method = call.service_method.clone();
+        let started = Instant::now();
+        let result = next.run(call).await;
+        self.record(service_method, started, &result);
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_timing_records_calls_and_errors() {
+        let mut timing = Timing::default();
+        timing.record(Duration::from_millis(10), false);
+        timing.record(Duration::from_millis(30), true);
+
+        assert_eq!(timing.calls, 2);
+        assert_eq!(timing.errors, 1);
+        assert_eq!(timing.max, Duration::from_millis(30));
+        assert_eq!(timing.mean(), Duration::from_millis(20));
+        assert_eq!(Timing::default().mean(), Duration::ZERO);
+    }
+}
@@ -69,7 +69,7 @@
 //!
 //! - `#[service]` - **CURRENT**: Generate service wrappers and clients from impl blocks
 //! - `#[derive(Event)]` - **PRIMARY**: Implement Event trait for pub/sub
-//! - `#[subscribe]` - Mark subscriber functions (experimental)
+//! - `#[subscribe]` - Subscribe methods of an impl block to event topics
 //! - `#[service_impl]` - **TRAIT-BASED**: Service implementation from trait (alternative approach)
 //!
 //! ### Design Trade-offs
@@ -86,13 +86,24 @@
 //!
 //! For detailed macro usage, see the [`macros`] module.
 
+pub mod context;
 pub mod error;
 pub mod event;
+pub mod failure;
+pub mod filter;
 pub mod hub;
+pub mod layer;
 pub mod message;
+pub mod mock;
+pub mod qos;
+pub mod queue;
+pub mod shared;
+pub mod store;
 pub mod subscription;
+pub mod topic;
 pub mod transport;
 pub mod transport_ipmb;
+mod version;
 
 #[cfg(test)]
 mod tests;
@@ -101,11 +112,21 @@ mod tests;
 mod error_tests;
 
 // Re-exports
+pub use context::{CallOptions, EventContext, RequestContext};
 pub use error::{Error, Result};
-pub use event::{Event, Subscriber, Subscription};
+pub use event::{
+    Event, EventContextStream, EventStream, SubscribeOptions, Subscriber, Subscription,
+    SyncSubscriber,
+};
+pub use failure::Failure;
+pub use filter::Filter;
+pub use hub::{run_blocking, Service, ServiceRegistry};
 pub use hub::{ProcessHub, SyncProcessHub};
-pub use hub::{Service, ServiceRegistry};
-pub use message::{Message, Request, Response};
+pub use layer::{CallLayer, LoggingLayer, ServiceLayer, TimingLayer};
+pub use message::{decode_params, Message, Priority, Request, Response};
+pub use qos::{PublishOptions, PublishReceipt, Qos};
+pub use queue::OverflowPolicy;
+pub use store::{EventStore, EventStoreConfig, ReplayFrom, StoredEvent};
 pub use subscription::{PendingSubscriptionSink, RpcSubscription, SubscriptionSink};
 
 // Type alias for subscription results
@@ -204,11 +225,35 @@ pub mod macros {
     ///     Ok(())
     /// }
     /// ```
+    ///
+    /// ## Versioning
+    ///
+    /// `version = N` publishes events with that schema version in their
+    /// metadata. Subscribers register upcasters to keep decoding events from
+    /// older publishers:
+    ///
+    /// ```rust,ignore
+    /// #[derive(Event, Serialize, Deserialize, Debug, Clone)]
+    /// #[event(topic = "sensor/temperature", version = 2)]
+    /// pub struct TemperatureEvent {
+    ///     pub value: f64,
+    ///     pub unit: String,
+    /// }
+    ///
+    /// hub.register_upcaster(1, |old: TemperatureEventV1| TemperatureEvent {
+    ///     value: old.value,
+    ///     unit: "Celsius".to_string(),
+    /// })?;
+    /// ```
     pub use crate::Event;
 
     /// # Subscribe Macro (`#[subscribe]`)
     ///
-    /// Marks functions as event subscribers (experimental).
+    /// Marks methods as event subscribers.
+    ///
+    /// Put `#[subscribe]` on the impl block and `#[subscribe("pattern")]` on each
+    /// handler. The generated `register_subscribers` subscribes every handler
+    /// and calls it with the decoded event.
     ///
     /// ## Usage
     /// ```rust,ignore
@@ -216,15 +261,16 @@ pub mod macros {
     ///
     /// struct EventHandler;
     ///
+    /// #[subscribe]
     /// impl EventHandler {
     ///     #[subscribe("sensor/temperature")]
     ///     async fn handle_temperature(&self, event: TemperatureEvent) {
     ///         println!("Temperature: {}°{}", event.value, event.unit);
     ///     }
     /// }
-    /// ```
     ///
-    /// **Note**: This macro is experimental and may change in future versions.
+    /// let subscriptions = Arc::new(EventHandler).register_subscribers(&hub).await?;
+    /// ```
     pub use crate::subscribe;
 
     /// # Service Implementation Macro (`#[service_impl]`)
@@ -336,6 +382,7 @@ pub mod macros {
 // Re-export commonly used dependencies
 pub use async_trait::async_trait;
 pub use bincode;
+pub use serde;
 pub use serde::{Deserialize, Serialize};
 
 // Runtime support is provided by SyncProcessHub in hub module
@@ -1,6 +1,9 @@
 //! Message types and serialization
 
-use serde::{Deserialize, Serialize};
+use bincode::Options;
+use serde::de::{self, DeserializeOwned, DeserializeSeed, SeqAccess, Visitor};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::HashMap;
 use uuid::Uuid;
 
 /// Core message type for IPC communication
@@ -66,6 +69,62 @@ pub struct MessageMetadata {
 
     /// Whether to retain this message
     pub retain: bool,
+
+    /// Custom headers, such as auth tokens, tenant ids or trace baggage
+    ///
+    /// Not part of the serde encoding of the metadata, whose layout older
+    /// peers expect unchanged. Transports carry headers in the message's
+    /// [`MessageExtension`].
+    #[serde(skip)]
+    pub headers: HashMap<String, String>,
+
+    /// Schema version of an event payload, 0 for unversioned events
+    ///
+    /// Set from [`Event::VERSION`](crate::Event::VERSION) when publishing, and
+    /// carried in the [`MessageExtension`] like `headers`.
+    #[serde(skip)]
+    pub schema_version: u32,
+}
+
+/// Message fields added after the original wire format
+///
+/// Transports send the extension after the encoded message, and only when
+/// one of its fields is set, so peers that predate it read the message as
+/// before and ignore what follows.
+///
+/// Fields are only ever appended. An extension from an older peer decodes
+/// with the newer fields left at their defaults, and one from a newer peer
+/// decodes with the fields this version does not know ignored.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct MessageExtension {
+    /// Custom headers, see [`MessageMetadata::headers`]
+    pub headers: HashMap<String, String>,
+    /// Event schema version, see [`MessageMetadata::schema_version`]
+    pub schema_version: u32,
+}
+
+impl MessageExtension {
+    /// Whether no field is set, in which case it need not be sent
+    pub fn is_empty(&self) -> bool {
+        self.headers.is_empty() && self.schema_version == 0
+    }
+
+    /// Encode the fields in order
+    pub fn to_bytes(&self) -> crate::Result<Vec<u8>> {
+        Ok(bincode::serialize(self)?)
+    }
+
+    /// Decode the fields in order, stopping at the end of `data`
+    pub fn from_bytes(mut data: &[u8]) -> crate::Result<Self> {
+        let mut extension = Self::default();
+        if !data.is_empty() {
+            extension.headers = bincode::deserialize_from(&mut data)?;
+        }
+        if !data.is_empty() {
+            extension.schema_version = bincode::deserialize_from(&mut data)?;
+        }
+        Ok(extension)
+    }
 }
 
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Default)]
@@ -106,6 +165,44 @@ impl Message {
         }
     }
 
+    /// Create an acknowledgement of an at-least-once event
+    pub fn event_ack(source: String, event: &Message) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            msg_type: MessageType::Response,
+            source,
+            target: Some(event.source.clone()),
+            topic: event.topic.clone(),
+            payload: Vec::new(),
+            correlation_id: Some(event.id),
+            metadata: MessageMetadata::default(),
+        }
+    }
+
+    /// Whether this is an event whose receivers should acknowledge it
+    pub fn wants_ack(&self) -> bool {
+        matches!(self.msg_type, MessageType::Event) && self.correlation_id == Some(self.id)
+    }
+
+    /// Value of a custom header
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.metadata.headers.get(name).map(String::as_str)
+    }
+
+    /// Fields of this message that travel in its extension
+    pub fn extension(&self) -> MessageExtension {
+        MessageExtension {
+            headers: self.metadata.headers.clone(),
+            schema_version: self.metadata.schema_version,
+        }
+    }
+
+    /// Restore the fields carried in an extension
+    pub fn apply_extension(&mut self, extension: MessageExtension) {
+        self.metadata.headers = extension.headers;
+        self.metadata.schema_version = extension.schema_version;
+    }
+
     /// Create a new event message
     pub fn event(source: String, topic: String, payload: Vec<u8>) -> Self {
         Self {
@@ -131,6 +228,8 @@ impl Default for MessageMetadata {
             priority: Priority::Normal,
             ttl: None,
             retain: false,
+            headers: HashMap::new(),
+            schema_version: 0,
         }
     }
 }
@@ -148,6 +247,82 @@ pub struct Response<T> {
     pub result: Result<T, String>,
 }
 
+/// Decode the parameters struct of a multi-parameter `#[rpc]` method
+///
+/// Generated services decode parameters through this rather than
+/// `bincode::deserialize`. Fields are read one after another while payload is
+/// left, and fields past the end are reported missing, so trailing parameters
+/// marked `#[serde(default)]` take their default when an older caller leaves
+/// them out. Fields sent by newer callers past the last known one are ignored.
+pub fn decode_params<T: DeserializeOwned>(payload: &[u8]) -> crate::Result<T> {
+    Ok(T::deserialize(ParamsDeserializer { payload })?)
+}
+
+/// Hands a struct's visitor one bincode-encoded field at a time
+struct ParamsDeserializer<'a> {
+    payload: &'a [u8],
+}
+
+impl<'de> Deserializer<'de> for ParamsDeserializer<'_> {
+    type Error = bincode::Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, _visitor: V) -> bincode::Result<V::Value> {
+        Err(de::Error::custom("parameters must be decoded as a struct"))
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> bincode::Result<V::Value> {
+        visitor.visit_seq(ParamsSeq {
+            payload: self.payload,
+            remaining: fields.len(),
+        })
+    }
+
+    fn is_human_readable(&self) -> bool {
+        false
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map enum identifier ignored_any
+    }
+}
+
+struct ParamsSeq<'a> {
+    payload: &'a [u8],
+    remaining: usize,
+}
+
+impl<'de> SeqAccess<'de> for ParamsSeq<'_> {
+    type Error = bincode::Error;
+
+    fn next_element_seed<S: DeserializeSeed<'de>>(
+        &mut self,
+        seed: S,
+    ) -> bincode::Result<Option<S::Value>> {
+        if self.remaining == 0 || self.payload.is_empty() {
+            return Ok(None);
+        }
+        self.remaining -= 1;
+
+        // The same options as `bincode::serialize`, reading from the front of the payload
+        let options = bincode::options()
+            .with_fixint_encoding()
+            .allow_trailing_bytes();
+        let mut de = bincode::Deserializer::with_reader(&mut self.payload, options);
+        seed.deserialize(&mut de).map(Some)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.remaining)
+    }
+}
+
 /// Service registration information
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ServiceInfo {
@@ -214,6 +389,58 @@ impl Message {
         }
     }
 
+    /// Create a shared subscription join announcement
+    ///
+    /// Broadcast when `target` is `None`, or sent to a single process in reply
+    /// to its own announcement.
+    pub fn shared_join(
+        source: String,
+        target: Option<String>,
+        member: &crate::shared::SharedMember,
+    ) -> Self {
+        let payload = bincode::serialize(member).unwrap_or_default();
+        Self {
+            id: Uuid::new_v4(),
+            msg_type: MessageType::Subscribe,
+            source,
+            target,
+            topic: Some("share.join".to_string()),
+            payload,
+            correlation_id: None,
+            metadata: MessageMetadata::default(),
+        }
+    }
+
+    /// Create a shared subscription leave announcement
+    pub fn shared_leave(source: String, member: &crate::shared::SharedMember) -> Self {
+        let payload = bincode::serialize(member).unwrap_or_default();
+        Self {
+            id: Uuid::new_v4(),
+            msg_type: MessageType::Unsubscribe,
+            source,
+            target: None, // Broadcast to all processes
+            topic: Some("share.leave".to_string()),
+            payload,
+            correlation_id: None,
+            metadata: MessageMetadata::default(),
+        }
+    }
+
+    /// Create a heartbeat repeating this process's shared group memberships
+    pub fn shared_heartbeat(source: String, members: &[crate::shared::SharedMember]) -> Self {
+        let payload = bincode::serialize(members).unwrap_or_default();
+        Self {
+            id: Uuid::new_v4(),
+            msg_type: MessageType::Heartbeat,
+            source,
+            target: None, // Broadcast to all processes
+            topic: Some("share.heartbeat".to_string()),
+            payload,
+            correlation_id: None,
+            metadata: MessageMetadata::default(),
+        }
+    }
+
     /// Create a subscription request message
     pub fn subscription_request(
         source: String,
@@ -328,3 +555,96 @@ impl Message {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extension_fields_are_read_up_to_the_end() {
+        let mut msg = Message::event("source".to_string(), "a/b".to_string(), vec![1, 2, 3]);
+        assert!(msg.extension().is_empty());
+
+        msg.metadata
+            .headers
+            .insert("tenant".to_string(), "acme".to_string());
+        msg.metadata.schema_version = 2;
+        let bytes = msg.extension().to_bytes().unwrap();
+
+        // Fields appended by newer peers are ignored
+        let mut newer = bytes.clone();
+        newer.extend_from_slice(&bincode::serialize("future field").unwrap());
+        let extension = MessageExtension::from_bytes(&newer).unwrap();
+        assert_eq!(extension, msg.extension());
+
+        // Fields missing from older peers are left at their defaults
+        let headers_only = bincode::serialize(&msg.metadata.headers).unwrap();
+        let older = MessageExtension::from_bytes(&headers_only).unwrap();
+        assert_eq!(older.headers, msg.metadata.headers);
+        assert_eq!(older.schema_version, 0);
+        assert_eq!(
+            MessageExtension::from_bytes(&[]).unwrap(),
+            MessageExtension::default()
+        );
+
+        // A field cut short is an error
+        assert!(MessageExtension::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+
+        let mut decoded = Message::event("source".to_string(), "a/b".to_string(), Vec::new());
+        decoded.apply_extension(extension);
+        assert_eq!(decoded.header("tenant"), Some("acme"));
+        assert_eq!(decoded.metadata.schema_version, 2);
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct OldParams {
+        name: String,
+        sides: u8,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct NewParams {
+        name: String,
+        sides: u8,
+        #[serde(default)]
+        unit: Option<String>,
+        #[serde(default = "default_scale")]
+        scale: f64,
+    }
+
+    fn default_scale() -> f64 {
+        1.0
+    }
+
+    #[test]
+    fn test_params_missing_trailing_defaults() {
+        let new = NewParams {
+            name: "cube".to_string(),
+            sides: 6,
+            unit: Some("mm".to_string()),
+            scale: 2.0,
+        };
+        let payload = bincode::serialize(&new).unwrap();
+        assert_eq!(decode_params::<NewParams>(&payload).unwrap(), new);
+
+        // Older callers leave out the trailing parameters
+        let old = OldParams {
+            name: "cube".to_string(),
+            sides: 6,
+        };
+        let payload = bincode::serialize(&old).unwrap();
+        let decoded: NewParams = decode_params(&payload).unwrap();
+        assert_eq!(decoded.unit, None);
+        assert_eq!(decoded.scale, 1.0);
+
+        // Newer callers' extra parameters are ignored
+        let payload = bincode::serialize(&new).unwrap();
+        assert_eq!(decode_params::<OldParams>(&payload).unwrap(), old);
+
+        // Parameters without a default are still required, and a cut short one is an error
+        let payload = bincode::serialize("cube").unwrap();
+        assert!(decode_params::<NewParams>(&payload).is_err());
+        let payload = bincode::serialize(&old).unwrap();
+        assert!(decode_params::<NewParams>(&payload[..payload.len() - 1]).is_err());
+    }
+}
@@ -0,0 +1,236 @@
+//! Expectations for mocks generated by `#[rpc(mock)]`
+//!
+//! Each method of a generated `MockXxx` is a [`MockMethod`]. Calls are matched
+//! against its expectations in the order they were added; the first one whose
+//! matcher accepts the arguments and whose call limit isn't reached answers.
+//! A call nothing expects panics, like any unexpected call in a test.
+//!
+//! Mocks are for tests only. Called directly, or through a service registered
+//! on the calling hub, the panic fails the test at the call. A mock registered
+//! on a hub and called from another process panics in that hub's handler
+//! task instead, and the caller only sees its call time out.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+type Matcher<A> = Box<dyn Fn(&A) -> bool + Send>;
+type Responder<A, R> = Box<dyn FnMut(A) -> R + Send>;
+
+struct ExpectationState<A, R> {
+    matcher: Option<Matcher<A>>,
+    responder: Option<Responder<A, R>>,
+    times: Option<usize>,
+    calls: usize,
+}
+
+/// An expected call, configured by chaining
+///
+/// ```rust,ignore
+/// mock.expect_add()
+///     .with(|(a, b)| *a > 0 && *b > 0)
+///     .times(2)
+///     .returning(|(a, b)| Ok(a + b));
+/// ```
+pub struct Expectation<A, R> {
+    method: Arc<str>,
+    state: Arc<Mutex<ExpectationState<A, R>>>,
+}
+
+impl<A, R> Clone for Expectation<A, R> {
+    fn clone(&self) -> Self {
+        Self {
+            method: self.method.clone(),
+            state: self.state.clone(),
+        }
+    }
+}
+
+impl<A, R> Expectation<A, R> {
+    /// Only match calls whose arguments pass `matcher`
+    pub fn with(self, matcher: impl Fn(&A) -> bool + Send + 'static) -> Self {
+        self.state.lock().unwrap().matcher = Some(Box::new(matcher));
+        self
+    }
+
+    /// Match at most `times` calls, and require exactly that many at a checkpoint
+    pub fn times(self, times: usize) -> Self {
+        self.state.lock().unwrap().times = Some(times);
+        self
+    }
+
+    /// Answer matched calls with `responder`
+    pub fn returning(self, responder: impl FnMut(A) -> R + Send + 'static) -> Self {
+        self.state.lock().unwrap().responder = Some(Box::new(responder));
+        self
+    }
+
+    /// Answer matched calls with a clone of `value`
+    pub fn return_value(self, value: R) -> Self
+    where
+        R: Clone + Send + 'static,
+    {
+        self.returning(move |_| value.clone())
+    }
+
+    /// Number of calls this expectation answered
+    pub fn calls(&self) -> usize {
+        self.state.lock().unwrap().calls
+    }
+}
+
+impl<A, T, E> Expectation<A, std::result::Result<T, E>> {
+    /// Answer matched calls with `Ok` of a clone of `value`
+    ///
+    /// Unlike [`return_value`](Self::return_value), the error type needn't be `Clone`.
+    pub fn return_ok(self, value: T) -> Self
+    where
+        T: Clone + Send + 'static,
+    {
+        self.returning(move |_| Ok(value.clone()))
+    }
+}
+
+/// Expectations and call count of one mocked method
+pub struct MockMethod<A, R> {
+    name: Arc<str>,
+    expectations: Mutex<Vec<Expectation<A, R>>>,
+    calls: AtomicUsize,
+}
+
+impl<A, R> MockMethod<A, R> {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.into(),
+            expectations: Mutex::new(Vec::new()),
+            calls: AtomicUsize::new(0),
+        }
+    }
+
+    /// Add an expectation for this method
+    pub fn expect(&self) -> Expectation<A, R> {
+        let expectation = Expectation {
+            method: self.name.clone(),
+            state: Arc::new(Mutex::new(ExpectationState {
+                matcher: None,
+                responder: None,
+                times: None,
+                calls: 0,
+            })),
+        };
+        self.expectations.lock().unwrap().push(expectation.clone());
+        expectation
+    }
+
+    /// Answer a call from the first matching expectation
+    ///
+    /// # Panics
+    ///
+    /// If no expectation matches, or the matching one has nothing to return.
+    pub fn call(&self, args: A) -> R {
+        self.calls.fetch_add(1, Ordering::SeqCst);
+
+        let expectations = self.expectations.lock().unwrap().clone();
+        for expectation in expectations {
+            let mut state = expectation.state.lock().unwrap();
+            let exhausted = state.times.is_some_and(|times| state.calls >= times);
+            let accepted = state
+                .matcher
+                .as_ref()
+                .map_or(true, |matcher| matcher(&args));
+            if exhausted || !accepted {
+                continue;
+            }
+
+            state.calls += 1;
+            if let Some(responder) = state.responder.as_mut() {
+                return responder(args);
+            }
+
+            // Release the expectation before panicking, so it isn't poisoned
+            drop(state);
+            panic!(
+                "expectation for `{}` has no return value, use `returning` or `return_value`",
+                expectation.method
+            );
+        }
+
+        panic!("unexpected call to `{}`: no expectation matched", self.name)
+    }
+
+    /// Total number of calls to this method
+    pub fn calls(&self) -> usize {
+        self.calls.load(Ordering::SeqCst)
+    }
+
+    /// Check that every expectation with `times` got exactly that many calls,
+    /// then clear the expectations
+    ///
+    /// # Panics
+    ///
+    /// If an expectation got a different number of calls.
+    pub fn checkpoint(&self) {
+        let expectations = std::mem::take(&mut *self.expectations.lock().unwrap());
+        for expectation in expectations {
+            let state = expectation.state.lock().unwrap();
+            if let Some(times) = state.times {
+                assert_eq!(
+                    state.calls, times,
+                    "`{}` expected {times} call(s), got {}",
+                    self.name, state.calls
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expectations_match_in_order() {
+        let method: MockMethod<(i32,), i32> = MockMethod::new("double");
+        let small = method.expect().with(|(n,)| *n < 10).returning(|(n,)| n * 2);
+        method.expect().return_value(-1);
+
+        assert_eq!(method.call((3,)), 6);
+        assert_eq!(method.call((30,)), -1);
+        assert_eq!(small.calls(), 1);
+        assert_eq!(method.calls(), 2);
+    }
+
+    #[test]
+    fn test_times_limits_and_checkpoint() {
+        let method: MockMethod<(), u8> = MockMethod::new("next");
+        method.expect().times(1).return_value(1);
+        method.expect().return_value(2);
+
+        assert_eq!(method.call(()), 1);
+        assert_eq!(method.call(()), 2);
+        method.checkpoint();
+
+        method.expect().times(2).return_value(3);
+        method.call(());
+        let unmet = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| method.checkpoint()));
+        assert!(unmet.is_err());
+    }
+
+    #[test]
+    fn test_missing_return_value_leaves_expectation_usable() {
+        let method: MockMethod<(), u8> = MockMethod::new("pending");
+        let expectation = method.expect();
+        let missing = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| method.call(())));
+        assert!(missing.is_err());
+
+        // The panic didn't poison the expectation, so it can still be configured
+        expectation.return_value(4);
+        assert_eq!(method.call(()), 4);
+    }
+
+    #[test]
+    #[should_panic(expected = "unexpected call to `missing`")]
+    fn test_unexpected_call_panics() {
+        let method: MockMethod<(), ()> = MockMethod::new("missing");
+        method.call(());
+    }
+}
@@ -0,0 +1,180 @@
+//! Event delivery guarantees
+//!
+//! Events are published at most once by default. With
+//! [`Qos::AtLeastOnce`] the publisher keeps resending the same message until a
+//! receiver acknowledges it or the retries run out:
+//!
+//! - the event carries its own ID as correlation ID, asking for an ack;
+//! - a receiver acks once the event is queued for at least one of its
+//!   subscriptions;
+//! - the ack is a `Response` to the publisher, matched like a service reply.
+//!
+//! Confirmations are per hub, not per subscriber: a hub acks once the event
+//! is queued for any one of its subscriptions, and a confirmed event is
+//! queued for at least one subscriber somewhere, not for every subscriber.
+//! The publish resolves at the first confirmation: a subscription of its own
+//! taking the event, or an ack from any other process. In the first case the
+//! copy for other processes is still resent in the background until one of
+//! them acks or the retries run out. Once another process has acked, the
+//! remaining processes get only the copies sent until then.
+//!
+//! Receivers remember the ID of every at-least-once event for a while,
+//! whether or not they had a subscription for it. Resent copies are never
+//! delivered or stored twice; copies of an event a receiver acked are acked
+//! again.
+//!
+//! Receivers that predate acknowledgements ignore the correlation ID and
+//! simply never confirm.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+/// Delivery guarantee for a published event
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Qos {
+    /// Send once without confirmation
+    #[default]
+    AtMostOnce,
+    /// Resend until a hub, this one or another process, confirms one of its
+    /// subscribers has the event
+    AtLeastOnce,
+}
+
+/// Options for publishing an event
+#[derive(Debug, Clone)]
+pub struct PublishOptions {
+    /// Delivery guarantee
+    pub qos: Qos,
+    /// How long to wait for an ack before resending
+    pub ack_timeout: Duration,
+    /// Number of resends before giving up
+    pub max_retries: u32,
+    /// Custom headers sent with the event
+    pub headers: HashMap<String, String>,
+}
+
+impl Default for PublishOptions {
+    fn default() -> Self {
+        Self {
+            qos: Qos::AtMostOnce,
+            ack_timeout: Duration::from_secs(1),
+            max_retries: 5,
+            headers: HashMap::new(),
+        }
+    }
+}
+
+impl PublishOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the delivery guarantee
+    pub fn qos(mut self, qos: Qos) -> Self {
+        self.qos = qos;
+        self
+    }
+
+    /// Set how long to wait for an ack before resending
+    pub fn ack_timeout(mut self, ack_timeout: Duration) -> Self {
+        self.ack_timeout = ack_timeout;
+        self
+    }
+
+    /// Set the number of resends before giving up
+    pub fn max_retries(mut self, max_retries: u32) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    /// Add a custom header
+    pub fn header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.insert(name.into(), value.into());
+        self
+    }
+}
+
+/// Outcome of a publish
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PublishReceipt {
+    /// ID of the published message
+    pub message_id: Uuid,
+    /// Process that confirmed delivery first, `None` for at-most-once events
+    ///
+    /// Confirmation is per hub: one of its subscriptions has the event. Other
+    /// processes may or may not have received it.
+    pub confirmed_by: Option<String>,
+    /// Number of times the event was sent
+    pub attempts: u32,
+}
+
+/// Recently received at-least-once events, and whether each was queued
+pub(crate) struct DedupeWindow {
+    window: Duration,
+    seen: Mutex<(HashMap<Uuid, bool>, VecDeque<(Instant, Uuid)>)>,
+}
+
+impl DedupeWindow {
+    /// How long a received ID is remembered
+    pub const DEFAULT_WINDOW: Duration = Duration::from_secs(60);
+
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            seen: Mutex::new((HashMap::new(), VecDeque::new())),
+        }
+    }
+
+    /// Whether the event was queued for a subscription, if it was received
+    /// within the window
+    pub fn get(&self, id: &Uuid) -> Option<bool> {
+        let mut seen = self.seen.lock().unwrap();
+        self.expire(&mut seen);
+        seen.0.get(id).copied()
+    }
+
+    /// Remember a received event and whether it was queued
+    pub fn insert(&self, id: Uuid, queued: bool) {
+        let mut seen = self.seen.lock().unwrap();
+        self.expire(&mut seen);
+        if seen.0.insert(id, queued).is_none() {
+            seen.1.push_back((Instant::now(), id));
+        }
+    }
+
+    fn expire(&self, (ids, order): &mut (HashMap<Uuid, bool>, VecDeque<(Instant, Uuid)>)) {
+        while let Some((at, id)) = order.front() {
+            if at.elapsed() < self.window {
+                break;
+            }
+            ids.remove(id);
+            order.pop_front();
+        }
+    }
+}
+
+impl Default for DedupeWindow {
+    fn default() -> Self {
+        Self::new(Self::DEFAULT_WINDOW)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dedupe_window_expires() {
+        let window = DedupeWindow::new(Duration::from_millis(50));
+        let id = Uuid::new_v4();
+        assert_eq!(window.get(&id), None);
+
+        window.insert(id, false);
+        assert_eq!(window.get(&id), Some(false));
+
+        std::thread::sleep(Duration::from_millis(60));
+        assert_eq!(window.get(&id), None);
+    }
+}
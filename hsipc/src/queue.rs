@@ -0,0 +1,330 @@
+//! Bounded per-subscription delivery queues
+//!
+//! Every subscription owns a queue that is fed by the registry and drained by
+//! a dedicated task, so a slow subscriber only ever delays itself.
+//!
+//! Events received from other processes are pushed from the hub's message
+//! loop, which also carries RPC traffic, so they never wait for room: under
+//! the `Block` policy they are kept in a backlog behind the full queue instead.
+//! The backlog holds as many deliveries as the queue itself; past that, events
+//! from other processes are dropped and counted like any other overflow.
+
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Mutex;
+use tokio::sync::Notify;
+
+/// What to do when a subscription queue is full
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OverflowPolicy {
+    /// Wait for the subscriber to make room (backpressure on the publisher)
+    ///
+    /// Only publishers in this process are held back. Events from other
+    /// processes wait in a backlog as large as the queue until the subscriber
+    /// catches up, and are dropped once that is full too.
+    #[default]
+    Block,
+    /// Discard the oldest queued event to make room for the new one
+    DropOldest,
+    /// Discard the new event
+    DropNewest,
+    /// Close the subscription
+    Disconnect,
+}
+
+/// An event waiting to be handled by a subscriber
+#[derive(Debug, Clone)]
+pub(crate) struct Delivery {
+    /// Context handed to the subscriber along with the payload
+    pub context: crate::EventContext,
+    pub payload: Vec<u8>,
+}
+
+/// Result of pushing a delivery onto a queue
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum PushOutcome {
+    /// The delivery was queued
+    Queued,
+    /// The delivery was queued in place of the oldest one, which was discarded
+    Displaced,
+    /// The delivery was discarded
+    Dropped,
+    /// The queue was closed, either before or because of this push
+    Closed,
+}
+
+struct QueueState {
+    items: VecDeque<Delivery>,
+    /// Deliveries pushed without waiting while a `Block` queue was full, at
+    /// most `capacity` of them
+    backlog: VecDeque<Delivery>,
+    closed: bool,
+}
+
+/// Bounded single-consumer queue with a configurable overflow policy
+pub(crate) struct DeliveryQueue {
+    state: Mutex<QueueState>,
+    capacity: usize,
+    policy: OverflowPolicy,
+    item_ready: Notify,
+    space_ready: Notify,
+    dropped: AtomicU64,
+    disconnected: AtomicBool,
+}
+
+impl DeliveryQueue {
+    pub fn new(capacity: usize, policy: OverflowPolicy) -> Self {
+        Self {
+            state: Mutex::new(QueueState {
+                items: VecDeque::new(),
+                backlog: VecDeque::new(),
+                closed: false,
+            }),
+            capacity: capacity.max(1),
+            policy,
+            item_ready: Notify::new(),
+            space_ready: Notify::new(),
+            dropped: AtomicU64::new(0),
+            disconnected: AtomicBool::new(false),
+        }
+    }
+
+    /// Push a delivery, applying the overflow policy if the queue is full
+    pub async fn push(&self, delivery: Delivery) -> PushOutcome {
+        let mut delivery = Some(delivery);
+        loop {
+            if let Some(outcome) = self.offer(&mut delivery, false) {
+                return outcome;
+            }
+            // Block policy: wait for the consumer to make room
+            self.space_ready.notified().await;
+        }
+    }
+
+    /// Push a delivery without waiting
+    ///
+    /// Under the `Block` policy a full queue keeps the delivery in its
+    /// backlog, which refills the queue as the subscriber makes room. With the
+    /// backlog full as well, the delivery is dropped.
+    pub fn push_nowait(&self, delivery: Delivery) -> PushOutcome {
+        self.offer(&mut Some(delivery), true)
+            .expect("backlogged pushes never wait")
+    }
+
+    /// Take the delivery out of `delivery`, or leave it there and return
+    /// `None` if the `Block` policy has to wait for room
+    fn offer(&self, delivery: &mut Option<Delivery>, backlog: bool) -> Option<PushOutcome> {
+        let mut state = self.state.lock().unwrap();
+        if state.closed {
+            // Pass the wake-up on to any other blocked producer
+            self.space_ready.notify_one();
+            return Some(PushOutcome::Closed);
+        }
+
+        // The backlog is only used while the queue is full
+        if state.items.len() < self.capacity {
+            state.items.extend(delivery.take());
+            drop(state);
+            self.item_ready.notify_one();
+            return Some(PushOutcome::Queued);
+        }
+
+        match self.policy {
+            OverflowPolicy::Block if backlog && state.backlog.len() < self.capacity => {
+                state.backlog.extend(delivery.take());
+                Some(PushOutcome::Queued)
+            }
+            OverflowPolicy::Block if backlog => {
+                delivery.take();
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+                Some(PushOutcome::Dropped)
+            }
+            OverflowPolicy::Block => None,
+            OverflowPolicy::DropOldest => {
+                state.items.pop_front();
+                state.items.extend(delivery.take());
+                drop(state);
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+                self.item_ready.notify_one();
+                Some(PushOutcome::Displaced)
+            }
+            OverflowPolicy::DropNewest => {
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+                Some(PushOutcome::Dropped)
+            }
+            OverflowPolicy::Disconnect => {
+                state.closed = true;
+                state.items.clear();
+                drop(state);
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+                self.disconnected.store(true, Ordering::Relaxed);
+                self.wake_all();
+                Some(PushOutcome::Closed)
+            }
+        }
+    }
+
+    /// Pop the next delivery, waiting until one is available
+    ///
+    /// Returns `None` once the queue is closed.
+    pub async fn pop(&self) -> Option<Delivery> {
+        loop {
+            {
+                let mut state = self.state.lock().unwrap();
+                if state.closed {
+                    return None;
+                }
+                if let Some(delivery) = state.items.pop_front() {
+                    // Backlogged deliveries take the freed slot first
+                    match state.backlog.pop_front() {
+                        Some(backlogged) => state.items.push_back(backlogged),
+                        None => {
+                            drop(state);
+                            self.space_ready.notify_one();
+                        }
+                    }
+                    return Some(delivery);
+                }
+            }
+
+            self.item_ready.notified().await;
+        }
+    }
+
+    /// Close the queue, discarding anything still queued
+    pub fn close(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.closed = true;
+        state.items.clear();
+        state.backlog.clear();
+        drop(state);
+        self.wake_all();
+    }
+
+    /// Number of deliveries discarded by the overflow policy
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Whether the queue was closed by the `Disconnect` policy
+    pub fn is_disconnected(&self) -> bool {
+        self.disconnected.load(Ordering::Relaxed)
+    }
+
+    fn wake_all(&self) {
+        self.item_ready.notify_one();
+        self.space_ready.notify_waiters();
+        self.space_ready.notify_one();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    fn delivery(n: u8) -> Delivery {
+        Delivery {
+            context: crate::EventContext::new("queue/test"),
+            payload: vec![n],
+        }
+    }
+
+    async fn drain(queue: &DeliveryQueue, count: usize) -> Vec<u8> {
+        let mut out = Vec::new();
+        for _ in 0..count {
+            out.push(queue.pop().await.unwrap().payload[0]);
+        }
+        out
+    }
+
+    #[tokio::test]
+    async fn test_drop_oldest_keeps_newest() {
+        let queue = DeliveryQueue::new(2, OverflowPolicy::DropOldest);
+        for n in 1..=4 {
+            queue.push(delivery(n)).await;
+        }
+        assert_eq!(queue.push(delivery(5)).await, PushOutcome::Displaced);
+
+        assert_eq!(queue.dropped(), 3);
+        assert_eq!(drain(&queue, 2).await, vec![4, 5]);
+    }
+
+    #[tokio::test]
+    async fn test_drop_newest_keeps_oldest() {
+        let queue = DeliveryQueue::new(2, OverflowPolicy::DropNewest);
+        for n in 1..=4 {
+            queue.push(delivery(n)).await;
+        }
+
+        assert_eq!(queue.dropped(), 2);
+        assert_eq!(drain(&queue, 2).await, vec![1, 2]);
+    }
+
+    #[tokio::test]
+    async fn test_disconnect_closes_queue() {
+        let queue = DeliveryQueue::new(1, OverflowPolicy::Disconnect);
+        assert_eq!(queue.push(delivery(1)).await, PushOutcome::Queued);
+        assert_eq!(queue.push(delivery(2)).await, PushOutcome::Closed);
+
+        assert!(queue.is_disconnected());
+        assert!(queue.pop().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_block_waits_for_space() {
+        let queue = Arc::new(DeliveryQueue::new(1, OverflowPolicy::Block));
+        queue.push(delivery(1)).await;
+
+        let producer = {
+            let queue = queue.clone();
+            tokio::spawn(async move { queue.push(delivery(2)).await })
+        };
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!producer.is_finished());
+
+        assert_eq!(queue.pop().await.unwrap().payload, vec![1]);
+        assert_eq!(producer.await.unwrap(), PushOutcome::Queued);
+        assert_eq!(queue.pop().await.unwrap().payload, vec![2]);
+        assert_eq!(queue.dropped(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_block_backlogs_pushes_that_cannot_wait() {
+        let queue = Arc::new(DeliveryQueue::new(2, OverflowPolicy::Block));
+        for n in 1..=3 {
+            assert_eq!(queue.push_nowait(delivery(n)), PushOutcome::Queued);
+        }
+
+        // A waiting push stays behind the backlog
+        let producer = {
+            let queue = queue.clone();
+            tokio::spawn(async move { queue.push(delivery(4)).await })
+        };
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!producer.is_finished());
+
+        assert_eq!(drain(&queue, 3).await, vec![1, 2, 3]);
+        assert_eq!(producer.await.unwrap(), PushOutcome::Queued);
+        assert_eq!(drain(&queue, 1).await, vec![4]);
+        assert_eq!(queue.dropped(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_block_backlog_is_bounded() {
+        let queue = DeliveryQueue::new(2, OverflowPolicy::Block);
+        for n in 1..=4 {
+            assert_eq!(queue.push_nowait(delivery(n)), PushOutcome::Queued);
+        }
+
+        // The backlog holds no more than the queue itself
+        assert_eq!(queue.push_nowait(delivery(5)), PushOutcome::Dropped);
+        assert_eq!(queue.dropped(), 1);
+
+        // Room freed by the subscriber is backlogged into first
+        assert_eq!(drain(&queue, 1).await, vec![1]);
+        assert_eq!(queue.push_nowait(delivery(6)), PushOutcome::Queued);
+        assert_eq!(drain(&queue, 4).await, vec![2, 3, 4, 6]);
+    }
+}
@@ -0,0 +1,187 @@
+//! Shared subscription groups
+//!
+//! A shared subscription (`$share/{group}/{filter}`) makes every member of the
+//! group, across all processes on the bus, a candidate for each matching event.
+//! Each process keeps its own view of the group membership and picks the
+//! receiving member from the message ID. While the views agree, exactly one
+//! member handles each event.
+//!
+//! Delivery is best-effort while membership changes: views are built from
+//! announcements and heartbeats, so until every process has seen a member
+//! join, leave or time out, an event may be handled by two members or by
+//! none.
+//!
+//! Membership is announced with `Subscribe`/`Unsubscribe` messages when
+//! subscriptions are created and dropped, and when a hub shuts down. A process
+//! joining a group is told about existing members by their owners.
+//!
+//! A process that dies without leaving would keep receiving its share of the
+//! events, so every hub also repeats its memberships in a `Heartbeat` every
+//! [`HEARTBEAT_INTERVAL`]. Remote members not heard from for
+//! [`MEMBER_TIMEOUT`] are dropped, and their events go to the other members.
+
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+/// How often a hub repeats its shared group memberships
+pub const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How long a remote member is kept without being announced again
+pub const MEMBER_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// One member of a shared subscription group
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SharedMember {
+    /// Group name
+    pub group: String,
+    /// Topic filter shared by the group
+    pub filter: String,
+    /// Process owning the subscription
+    pub process: String,
+    /// Subscription ID within that process
+    pub subscription: Uuid,
+}
+
+/// Group membership as seen by this process
+///
+/// Members are kept with the time they were last announced.
+#[derive(Debug, Default)]
+pub(crate) struct SharedGroups {
+    groups: RwLock<HashMap<(String, String), BTreeMap<(String, Uuid), Instant>>>,
+}
+
+impl SharedGroups {
+    /// Add a member, or refresh it if already known, returning whether it was new
+    pub fn join(&self, member: &SharedMember) -> bool {
+        self.groups
+            .write()
+            .unwrap()
+            .entry((member.group.clone(), member.filter.clone()))
+            .or_default()
+            .insert(
+                (member.process.clone(), member.subscription),
+                Instant::now(),
+            )
+            .is_none()
+    }
+
+    /// Remove a member
+    pub fn leave(&self, member: &SharedMember) {
+        let mut groups = self.groups.write().unwrap();
+        let key = (member.group.clone(), member.filter.clone());
+        if let Some(members) = groups.get_mut(&key) {
+            members.remove(&(member.process.clone(), member.subscription));
+            if members.is_empty() {
+                groups.remove(&key);
+            }
+        }
+    }
+
+    /// Pick the member that should receive the event identified by `key`
+    ///
+    /// Every process with the same membership view picks the same member.
+    pub fn select(&self, group: &str, filter: &str, key: Uuid) -> Option<(String, Uuid)> {
+        let groups = self.groups.read().unwrap();
+        let members = groups.get(&(group.to_string(), filter.to_string()))?;
+        let index = (key.as_u128() % members.len() as u128) as usize;
+        members.keys().nth(index).cloned()
+    }
+
+    /// Drop members of other processes not announced within `timeout`
+    ///
+    /// Members of `local_process` are never dropped, since they leave
+    /// explicitly. Returns the number of members dropped.
+    pub fn expire(&self, local_process: &str, timeout: Duration) -> usize {
+        let mut expired = 0;
+        self.groups.write().unwrap().retain(|_, members| {
+            members.retain(|(process, _), seen| {
+                let keep = process == local_process || seen.elapsed() < timeout;
+                expired += usize::from(!keep);
+                keep
+            });
+            !members.is_empty()
+        });
+        expired
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn member(process: &str) -> SharedMember {
+        SharedMember {
+            group: "workers".to_string(),
+            filter: "jobs/#".to_string(),
+            process: process.to_string(),
+            subscription: Uuid::new_v4(),
+        }
+    }
+
+    #[test]
+    fn test_selection_is_deterministic_and_spread() {
+        let members: Vec<SharedMember> = ["a", "b", "c"].iter().map(|p| member(p)).collect();
+
+        // Two processes learning members in different orders agree on the pick
+        let first = SharedGroups::default();
+        let second = SharedGroups::default();
+        for m in &members {
+            first.join(m);
+        }
+        for m in members.iter().rev() {
+            second.join(m);
+        }
+
+        let mut picked = std::collections::BTreeSet::new();
+        for _ in 0..64 {
+            let key = Uuid::new_v4();
+            let choice = first.select("workers", "jobs/#", key).unwrap();
+            assert_eq!(second.select("workers", "jobs/#", key).unwrap(), choice);
+            picked.insert(choice.0);
+        }
+        assert_eq!(picked.len(), 3);
+    }
+
+    #[test]
+    fn test_leave_removes_member() {
+        let groups = SharedGroups::default();
+        let a = member("a");
+        let b = member("b");
+        assert!(groups.join(&a));
+        assert!(!groups.join(&a));
+        groups.join(&b);
+
+        groups.leave(&a);
+        for _ in 0..16 {
+            let (process, _) = groups.select("workers", "jobs/#", Uuid::new_v4()).unwrap();
+            assert_eq!(process, "b");
+        }
+
+        groups.leave(&b);
+        assert!(groups.select("workers", "jobs/#", Uuid::new_v4()).is_none());
+    }
+
+    #[test]
+    fn test_silent_remote_members_expire() {
+        let groups = SharedGroups::default();
+        let (local, remote) = (member("a"), member("b"));
+        groups.join(&local);
+        groups.join(&remote);
+        assert_eq!(groups.expire("a", Duration::from_secs(60)), 0);
+
+        // Announcing a member again keeps it
+        std::thread::sleep(Duration::from_millis(30));
+        assert!(!groups.join(&remote));
+        assert_eq!(groups.expire("a", Duration::from_millis(20)), 0);
+
+        std::thread::sleep(Duration::from_millis(30));
+        assert_eq!(groups.expire("a", Duration::from_millis(20)), 1);
+        for _ in 0..16 {
+            let (process, _) = groups.select("workers", "jobs/#", Uuid::new_v4()).unwrap();
+            assert_eq!(process, "a");
+        }
+    }
+}
@@ -0,0 +1,800 @@
+//! Durable on-disk event log
+//!
+//! An [`EventStore`] keeps an append-only log per topic prefix under one
+//! directory. Each log is split into segment files named after the offset of
+//! their first record, and a new segment is started once the current one
+//! grows past the configured size.
+//!
+//! Offsets are assigned from a single sequence shared by all logs of a store,
+//! so an offset identifies one stored event regardless of its topic.
+//!
+//! Records are length-prefixed bincode, with the fields added since the first
+//! release in a trailing [`MessageExtension`]. A partially written record at
+//! the end of a log, left by a crash, is truncated when the store is opened.
+//!
+//! Each log keeps a sparse in-memory index of record positions per segment,
+//! so reads seek close to the requested offset instead of scanning the log
+//! from its start. Sealed segments are indexed the first time they are read.
+//!
+//! Appends are written to the OS as they happen, so stored events survive a
+//! crash of the process. They survive a crash of the machine only with
+//! [`EventStoreConfig::sync`], which syncs every append to disk.
+//!
+//! A directory has a single writer. Other processes open it read-only to
+//! replay what the writer recorded, e.g. a subscriber catching up on events
+//! published by an archiving process while it was down.
+
+use crate::message::MessageExtension;
+use crate::topic::{topic_matches, validate_topic_filter};
+use crate::{Error, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufReader, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use uuid::Uuid;
+
+const SEGMENT_EXTENSION: &str = "log";
+
+/// Where a replaying subscription starts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReplayFrom {
+    /// Replay stored events from this offset on
+    Offset(u64),
+    /// Replay stored events published at or after this time, in milliseconds
+    /// since the Unix epoch
+    Timestamp(u64),
+}
+
+/// Event store configuration
+#[derive(Debug, Clone)]
+pub struct EventStoreConfig {
+    /// Directory holding the logs
+    pub dir: PathBuf,
+    /// Topic prefixes to store, each in its own log
+    ///
+    /// When empty, every event is stored in a log named after the first level
+    /// of its topic.
+    pub prefixes: Vec<String>,
+    /// Size in bytes after which a log starts a new segment
+    pub segment_bytes: u64,
+    /// Only read logs written by another process
+    pub read_only: bool,
+    /// Sync every append to disk before it is delivered
+    pub sync: bool,
+}
+
+impl EventStoreConfig {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            prefixes: Vec::new(),
+            segment_bytes: 64 * 1024 * 1024,
+            read_only: false,
+            sync: false,
+        }
+    }
+
+    /// Store events whose topic is `prefix` or lies below it
+    pub fn prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefixes.push(prefix.into());
+        self
+    }
+
+    /// Set the segment size
+    pub fn segment_bytes(mut self, segment_bytes: u64) -> Self {
+        self.segment_bytes = segment_bytes;
+        self
+    }
+
+    /// Open the store read-only, never appending to it
+    pub fn read_only(mut self, read_only: bool) -> Self {
+        self.read_only = read_only;
+        self
+    }
+
+    /// Sync every append to disk, so stored events survive a machine crash
+    pub fn sync(mut self, sync: bool) -> Self {
+        self.sync = sync;
+        self
+    }
+}
+
+/// An event as stored in the log
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StoredEvent {
+    /// Position of the event in the store
+    pub offset: u64,
+    /// Publish time in milliseconds since the Unix epoch
+    pub timestamp: u64,
+    /// Event topic
+    pub topic: String,
+    /// Process that published the event
+    pub source: String,
+    /// ID of the event message
+    pub id: Uuid,
+    /// Serialized event
+    pub payload: Vec<u8>,
+    /// Headers the event was published with
+    ///
+    /// Stored in the record's [`MessageExtension`], after the other fields.
+    #[serde(skip)]
+    pub headers: HashMap<String, String>,
+    /// Schema version the event was published with, stored like `headers`
+    #[serde(skip)]
+    pub schema_version: u32,
+}
+
+/// Records between two entries of a segment index
+const INDEX_INTERVAL: u64 = 64;
+
+/// Sparse index of the records of a segment, built as they are written or
+/// first read
+#[derive(Debug, Clone)]
+struct SegmentIndex {
+    /// Offset and file position of every `INDEX_INTERVAL`-th record
+    entries: Vec<(u64, u64)>,
+    /// Length of the indexed part of the segment
+    len: u64,
+    /// Number of records indexed
+    records: u64,
+    /// Offset following the last indexed record
+    next_offset: u64,
+}
+
+impl SegmentIndex {
+    fn new(base: u64) -> Self {
+        Self {
+            entries: Vec::new(),
+            len: 0,
+            records: 0,
+            next_offset: base,
+        }
+    }
+
+    fn push(&mut self, offset: u64, record_len: u64) {
+        if self.records % INDEX_INTERVAL == 0 {
+            self.entries.push((offset, self.len));
+        }
+        self.records += 1;
+        self.len += record_len;
+        self.next_offset = offset + 1;
+    }
+
+    /// File position to start reading at to find the record at `offset`
+    fn position(&self, offset: u64) -> u64 {
+        if offset >= self.next_offset {
+            return self.len;
+        }
+        match self.entries.partition_point(|(entry, _)| *entry <= offset) {
+            0 => 0,
+            i => self.entries[i - 1].1,
+        }
+    }
+}
+
+struct Segment {
+    /// Offset of the first record
+    base: u64,
+    index: SegmentIndex,
+}
+
+/// Log of one topic prefix
+struct Log {
+    dir: PathBuf,
+    segments: Vec<Segment>,
+    /// Open current segment
+    current: Option<File>,
+}
+
+struct StoreState {
+    logs: BTreeMap<String, Log>,
+    next_offset: u64,
+}
+
+/// Append-only event log per topic prefix
+pub struct EventStore {
+    config: EventStoreConfig,
+    state: Mutex<StoreState>,
+}
+
+impl EventStore {
+    /// Open the store, creating its directory if needed
+    pub fn open(config: EventStoreConfig) -> Result<Self> {
+        for prefix in &config.prefixes {
+            validate_topic_filter(prefix)?;
+            if prefix.contains(['+', '#']) {
+                return Err(Error::configuration(
+                    format!("event store prefix '{prefix}' must not contain wildcards"),
+                    Some("prefixes".to_string()),
+                ));
+            }
+        }
+        if !config.read_only {
+            fs::create_dir_all(&config.dir)?;
+        }
+
+        let mut logs = BTreeMap::new();
+        let mut next_offset = 0;
+        for entry in fs::read_dir(&config.dir)? {
+            let entry = entry?;
+            if let Some(prefix) = entry.file_name().to_str().and_then(decode_log_name) {
+                let mut log = Log::open(entry.path())?;
+                next_offset = next_offset.max(log.recover(!config.read_only)?);
+                logs.insert(prefix, log);
+            }
+        }
+
+        Ok(Self {
+            config,
+            state: Mutex::new(StoreState { logs, next_offset }),
+        })
+    }
+
+    /// Offset the next stored event will get
+    ///
+    /// A read-only store reports the writer's current offset.
+    pub fn next_offset(&self) -> Result<u64> {
+        let mut state = self.state.lock().unwrap();
+        if self.config.read_only {
+            self.refresh(&mut state)?;
+        }
+        Ok(state.next_offset)
+    }
+
+    /// Pick up the logs, segments and records another process wrote since
+    /// the last refresh
+    fn refresh(&self, state: &mut StoreState) -> Result<()> {
+        for entry in fs::read_dir(&self.config.dir)? {
+            let entry = entry?;
+            let Some(prefix) = entry.file_name().to_str().and_then(decode_log_name) else {
+                continue;
+            };
+            match state.logs.get_mut(&prefix) {
+                Some(log) => log.refresh()?,
+                None => {
+                    state.logs.insert(prefix, Log::open(entry.path())?);
+                }
+            }
+        }
+
+        let mut next_offset = state.next_offset;
+        for log in state.logs.values_mut() {
+            next_offset = next_offset.max(log.recover(false)?);
+        }
+        state.next_offset = next_offset;
+        Ok(())
+    }
+
+    /// Prefix of the log a topic is stored in, if it is stored at all
+    fn log_prefix(&self, topic: &str) -> Option<String> {
+        if self.config.prefixes.is_empty() {
+            return topic.split('/').next().map(str::to_string);
+        }
+        self.config
+            .prefixes
+            .iter()
+            .find(|prefix| {
+                topic == prefix.as_str()
+                    || topic
+                        .strip_prefix(prefix.as_str())
+                        .is_some_and(|rest| rest.starts_with('/'))
+            })
+            .cloned()
+    }
+
+    /// Append an event, returning its offset
+    ///
+    /// `extension` carries the message fields stored after the others, such
+    /// as headers and schema version. Returns `None` when the topic is not
+    /// under any configured prefix, or when the store is read-only. This does
+    /// blocking file I/O; the hub calls it on the blocking thread pool.
+    pub fn append(
+        &self,
+        topic: &str,
+        source: &str,
+        id: Uuid,
+        timestamp: u64,
+        payload: &[u8],
+        extension: &MessageExtension,
+    ) -> Result<Option<u64>> {
+        if self.config.read_only {
+            return Ok(None);
+        }
+        let Some(prefix) = self.log_prefix(topic) else {
+            return Ok(None);
+        };
+
+        let mut state = self.state.lock().unwrap();
+        let offset = state.next_offset;
+        let mut record = bincode::serialize(&StoredEvent {
+            offset,
+            timestamp,
+            topic: topic.to_string(),
+            source: source.to_string(),
+            id,
+            payload: payload.to_vec(),
+            headers: HashMap::new(),
+            schema_version: 0,
+        })?;
+        if !extension.is_empty() {
+            record.extend(extension.to_bytes()?);
+        }
+
+        let log = match state.logs.entry(prefix) {
+            std::collections::btree_map::Entry::Occupied(entry) => entry.into_mut(),
+            std::collections::btree_map::Entry::Vacant(entry) => {
+                let dir = self.config.dir.join(encode_log_name(entry.key()));
+                fs::create_dir_all(&dir)?;
+                entry.insert(Log::open(dir)?)
+            }
+        };
+        log.append(offset, &record, &self.config)?;
+        state.next_offset = offset + 1;
+        Ok(Some(offset))
+    }
+
+    /// Read up to `limit` stored events matching `filter`, in offset order
+    ///
+    /// Only events with an offset in `from..end` are returned, and with
+    /// `ReplayFrom::Timestamp` only those published at or after that time.
+    pub fn read(
+        &self,
+        filter: &str,
+        from: ReplayFrom,
+        end: u64,
+        limit: usize,
+    ) -> Result<Vec<StoredEvent>> {
+        let (start, min_timestamp) = match from {
+            ReplayFrom::Offset(offset) => (offset, 0),
+            ReplayFrom::Timestamp(timestamp) => (0, timestamp),
+        };
+        self.read_range(filter, start..end, min_timestamp, limit)
+    }
+
+    /// Read up to `limit` events matching `filter` with an offset in `offsets`
+    /// that were published at or after `min_timestamp`
+    ///
+    /// Each log is read from the indexed position nearest to the start offset
+    /// on, without holding the store lock, so appends aren't held up.
+    pub(crate) fn read_range(
+        &self,
+        filter: &str,
+        offsets: std::ops::Range<u64>,
+        min_timestamp: u64,
+        limit: usize,
+    ) -> Result<Vec<StoredEvent>> {
+        let std::ops::Range { start, end } = offsets;
+
+        // Pick the segments to read, with a copy of their index
+        let mut plan = Vec::new();
+        {
+            let mut state = self.state.lock().unwrap();
+            if self.config.read_only && end > state.next_offset {
+                self.refresh(&mut state)?;
+            }
+            for (prefix, log) in &state.logs {
+                let segments: Vec<(u64, SegmentIndex)> = log
+                    .segments
+                    .iter()
+                    .enumerate()
+                    // Skip segments that end before the start offset
+                    .filter(|(i, _)| {
+                        log.segments
+                            .get(i + 1)
+                            .map_or(true, |next| next.base > start)
+                    })
+                    .take_while(|(_, segment)| segment.base < end)
+                    .map(|(_, segment)| (segment.base, segment.index.clone()))
+                    .collect();
+                plan.push((prefix.clone(), log.dir.clone(), segments));
+            }
+        }
+
+        let mut events = Vec::new();
+        for (prefix, dir, segments) in plan {
+            let mut found = 0;
+            for (base, mut index) in segments {
+                if found >= limit {
+                    break;
+                }
+                let indexed = index.len;
+                read_segment(&segment_path(&dir, base), &mut index, start..end, |event| {
+                    if event.timestamp >= min_timestamp && topic_matches(&event.topic, filter) {
+                        events.push(event);
+                        found += 1;
+                    }
+                    found < limit
+                })?;
+
+                // Keep what this read added to the index
+                if index.len > indexed {
+                    let mut state = self.state.lock().unwrap();
+                    let segment = state.logs.get_mut(&prefix).and_then(|log| {
+                        log.segments.iter_mut().find(|segment| segment.base == base)
+                    });
+                    if let Some(segment) = segment {
+                        if index.len > segment.index.len {
+                            segment.index = index;
+                        }
+                    }
+                }
+            }
+        }
+
+        events.sort_by_key(|event| event.offset);
+        events.truncate(limit);
+        Ok(events)
+    }
+}
+
+impl Log {
+    fn open(dir: PathBuf) -> Result<Self> {
+        let segments = list_segments(&dir)?
+            .into_iter()
+            .map(|base| Segment {
+                base,
+                index: SegmentIndex::new(base),
+            })
+            .collect();
+
+        Ok(Self {
+            dir,
+            segments,
+            current: None,
+        })
+    }
+
+    /// Add segments another process started since the log was opened
+    fn refresh(&mut self) -> Result<()> {
+        let last = self.segments.last().map(|segment| segment.base);
+        for base in list_segments(&self.dir)? {
+            if last.map_or(true, |last| base > last) {
+                self.segments.push(Segment {
+                    base,
+                    index: SegmentIndex::new(base),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Index the rest of the last segment and return the offset following
+    /// the last stored event, truncating a torn record at the end of the
+    /// segment if `truncate` is set
+    fn recover(&mut self, truncate: bool) -> Result<u64> {
+        let Some(segment) = self.segments.last_mut() else {
+            return Ok(0);
+        };
+        let path = segment_path(&self.dir, segment.base);
+        read_segment(&path, &mut segment.index, u64::MAX..u64::MAX, |_| true)?;
+
+        if truncate {
+            let file = OpenOptions::new().write(true).open(&path)?;
+            if file.metadata()?.len() > segment.index.len {
+                tracing::warn!("Truncating torn record at the end of {}", path.display());
+                file.set_len(segment.index.len)?;
+            }
+        }
+        Ok(segment.index.next_offset)
+    }
+
+    fn append(&mut self, offset: u64, record: &[u8], config: &EventStoreConfig) -> Result<()> {
+        let full = self
+            .segments
+            .last()
+            .is_some_and(|segment| segment.index.len >= config.segment_bytes);
+        if self.segments.is_empty() || full {
+            self.segments.push(Segment {
+                base: offset,
+                index: SegmentIndex::new(offset),
+            });
+            self.current = None;
+        }
+
+        let segment = self.segments.last_mut().unwrap();
+        let file = match &mut self.current {
+            Some(file) => file,
+            None => {
+                let path = segment_path(&self.dir, segment.base);
+                let file = OpenOptions::new().create(true).append(true).open(path)?;
+                self.current.insert(file)
+            }
+        };
+
+        let mut buf = Vec::with_capacity(4 + record.len());
+        buf.extend_from_slice(&(record.len() as u32).to_le_bytes());
+        buf.extend_from_slice(record);
+        file.write_all(&buf)?;
+        if config.sync {
+            file.sync_data()?;
+        }
+        segment.index.push(offset, buf.len() as u64);
+        Ok(())
+    }
+}
+
+/// Base offsets of the segments in a log directory, in order
+fn list_segments(dir: &Path) -> Result<Vec<u64>> {
+    let mut segments = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().and_then(|ext| ext.to_str()) != Some(SEGMENT_EXTENSION) {
+            continue;
+        }
+        if let Some(base) = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .and_then(|stem| stem.parse::<u64>().ok())
+        {
+            segments.push(base);
+        }
+    }
+    segments.sort_unstable();
+    Ok(segments)
+}
+
+fn segment_path(dir: &Path, base: u64) -> PathBuf {
+    dir.join(format!("{base:020}.{SEGMENT_EXTENSION}"))
+}
+
+/// Pass the records of a segment with an offset in `offsets` to `visit` until
+/// it returns false, indexing any records read past the indexed part
+///
+/// Reading starts at the indexed position nearest to `offsets.start`, and
+/// stops at the end of the segment, at a torn record or at `offsets.end`.
+fn read_segment(
+    path: &Path,
+    index: &mut SegmentIndex,
+    offsets: std::ops::Range<u64>,
+    mut visit: impl FnMut(StoredEvent) -> bool,
+) -> Result<()> {
+    let mut position = index.position(offsets.start);
+    let mut file = File::open(path)?;
+    file.seek(SeekFrom::Start(position))?;
+    let mut reader = BufReader::new(file);
+    while let Some((event, len)) = read_record(&mut reader)? {
+        if position == index.len {
+            index.push(event.offset, len);
+        }
+        position += len;
+        if event.offset >= offsets.end {
+            break;
+        }
+        if event.offset >= offsets.start && !visit(event) {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Read one record and its length on disk, or `None` at the end of the
+/// segment or at a torn record
+fn read_record(reader: &mut impl Read) -> Result<Option<(StoredEvent, u64)>> {
+    let mut len = [0; 4];
+    match reader.read_exact(&mut len) {
+        Ok(()) => {}
+        Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e.into()),
+    }
+    let len = u32::from_le_bytes(len) as usize;
+
+    let mut record = vec![0; len];
+    match reader.read_exact(&mut record) {
+        Ok(()) => {}
+        Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e.into()),
+    }
+    let mut rest = record.as_slice();
+    let Ok(mut event) = bincode::deserialize_from::<_, StoredEvent>(&mut rest) else {
+        return Ok(None);
+    };
+    let Ok(extension) = MessageExtension::from_bytes(rest) else {
+        return Ok(None);
+    };
+    event.headers = extension.headers;
+    event.schema_version = extension.schema_version;
+    Ok(Some((event, 4 + len as u64)))
+}
+
+/// Directory name of the log for a prefix
+///
+/// Bytes other than ASCII letters, digits, `-` and `_` are percent-encoded so
+/// any topic prefix maps to a single, reversible directory name.
+fn encode_log_name(prefix: &str) -> String {
+    let mut name = String::from("log-");
+    for byte in prefix.bytes() {
+        if byte.is_ascii_alphanumeric() || byte == b'-' || byte == b'_' {
+            name.push(byte as char);
+        } else {
+            name.push_str(&format!("%{byte:02X}"));
+        }
+    }
+    name
+}
+
+fn decode_log_name(name: &str) -> Option<String> {
+    let encoded = name.strip_prefix("log-")?;
+    let mut bytes = Vec::with_capacity(encoded.len());
+    let mut chars = encoded.bytes();
+    while let Some(byte) = chars.next() {
+        if byte == b'%' {
+            let hex = [chars.next()?, chars.next()?];
+            bytes.push(u8::from_str_radix(std::str::from_utf8(&hex).ok()?, 16).ok()?);
+        } else {
+            bytes.push(byte);
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new() -> Self {
+            Self(std::env::temp_dir().join(format!("hsipc-store-{}", Uuid::new_v4())))
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn append(store: &EventStore, topic: &str, timestamp: u64) -> Option<u64> {
+        store
+            .append(
+                topic,
+                "test",
+                Uuid::new_v4(),
+                timestamp,
+                topic.as_bytes(),
+                &MessageExtension::default(),
+            )
+            .unwrap()
+    }
+
+    fn topics(events: &[StoredEvent]) -> Vec<&str> {
+        events.iter().map(|event| event.topic.as_str()).collect()
+    }
+
+    #[test]
+    fn test_append_and_read_by_prefix() {
+        let dir = TempDir::new();
+        let store = EventStore::open(
+            EventStoreConfig::new(&dir.0)
+                .prefix("sensor")
+                .prefix("orders/eu"),
+        )
+        .unwrap();
+
+        assert_eq!(append(&store, "sensor/temp", 1), Some(0));
+        assert_eq!(append(&store, "orders/eu/1", 2), Some(1));
+        assert_eq!(append(&store, "orders/us/1", 3), None);
+        assert_eq!(append(&store, "sensorx/temp", 4), None);
+        assert_eq!(append(&store, "sensor/humidity", 5), Some(2));
+
+        let all = store.read("#", ReplayFrom::Offset(0), 10, 100).unwrap();
+        assert_eq!(
+            topics(&all),
+            vec!["sensor/temp", "orders/eu/1", "sensor/humidity"]
+        );
+
+        let sensors = store
+            .read("sensor/+", ReplayFrom::Offset(1), 10, 100)
+            .unwrap();
+        assert_eq!(topics(&sensors), vec!["sensor/humidity"]);
+
+        let recent = store.read("#", ReplayFrom::Timestamp(2), 10, 100).unwrap();
+        assert_eq!(topics(&recent), vec!["orders/eu/1", "sensor/humidity"]);
+
+        let bounded = store.read("#", ReplayFrom::Offset(0), 2, 1).unwrap();
+        assert_eq!(topics(&bounded), vec!["sensor/temp"]);
+    }
+
+    #[test]
+    fn test_segments_roll_and_survive_reopen() {
+        let dir = TempDir::new();
+        let config = EventStoreConfig::new(&dir.0).segment_bytes(64);
+        {
+            let store = EventStore::open(config.clone()).unwrap();
+            for _ in 0..10 {
+                append(&store, "metrics/cpu", 0);
+            }
+        }
+
+        let log_dir = dir.0.join(encode_log_name("metrics"));
+        assert!(fs::read_dir(&log_dir).unwrap().count() > 1);
+
+        // Simulate a crash in the middle of writing a record
+        let last = Log::open(log_dir).unwrap();
+        let path = segment_path(&last.dir, last.segments.last().unwrap().base);
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(&[200, 0, 0, 0, 1, 2]).unwrap();
+
+        let store = EventStore::open(config.clone()).unwrap();
+        assert_eq!(store.next_offset().unwrap(), 10);
+        assert_eq!(append(&store, "metrics/cpu", 0), Some(10));
+
+        let events = store
+            .read("metrics/#", ReplayFrom::Offset(8), 11, 100)
+            .unwrap();
+        let offsets: Vec<u64> = events.iter().map(|event| event.offset).collect();
+        assert_eq!(offsets, vec![8, 9, 10]);
+
+        // A reader sees what the writer appends after the reader was opened
+        let reader = EventStore::open(config.read_only(true)).unwrap();
+        append(&store, "metrics/mem", 0);
+        assert_eq!(reader.next_offset().unwrap(), 12);
+        assert_eq!(
+            reader
+                .append(
+                    "metrics/cpu",
+                    "test",
+                    Uuid::new_v4(),
+                    0,
+                    &[],
+                    &MessageExtension::default()
+                )
+                .unwrap(),
+            None
+        );
+        let latest = reader
+            .read("metrics/mem", ReplayFrom::Offset(0), 12, 100)
+            .unwrap();
+        assert_eq!(topics(&latest), vec!["metrics/mem"]);
+    }
+
+    #[test]
+    fn test_reads_seek_through_the_index() {
+        let dir = TempDir::new();
+        let config = EventStoreConfig::new(&dir.0).segment_bytes(16 * 1024);
+        let store = EventStore::open(config.clone()).unwrap();
+        for _ in 0..1000 {
+            append(&store, "metrics/cpu", 0);
+        }
+
+        let offsets = |events: Vec<StoredEvent>| -> Vec<u64> {
+            events.iter().map(|event| event.offset).collect()
+        };
+        let events = store.read("#", ReplayFrom::Offset(700), 1000, 3).unwrap();
+        assert_eq!(offsets(events), vec![700, 701, 702]);
+
+        // The writer indexed its current segment, a reader indexes the
+        // segments it reads
+        let reader = EventStore::open(config.read_only(true)).unwrap();
+        let events = reader.read("#", ReplayFrom::Offset(130), 1000, 2).unwrap();
+        assert_eq!(offsets(events), vec![130, 131]);
+        {
+            let state = reader.state.lock().unwrap();
+            let log = &state.logs["metrics"];
+            assert!(log.segments.len() > 2);
+            let first = &log.segments[0].index;
+            assert!(first.records > 0 && first.records < 1000);
+            assert_eq!(first.entries[0], (0, 0));
+        }
+        let events = reader.read("#", ReplayFrom::Offset(999), 1000, 10).unwrap();
+        assert_eq!(offsets(events), vec![999]);
+
+        // Records appended after the reader indexed the segment are found too
+        append(&store, "metrics/cpu", 0);
+        assert_eq!(reader.next_offset().unwrap(), 1001);
+        let events = reader.read("#", ReplayFrom::Offset(998), 1001, 10).unwrap();
+        assert_eq!(offsets(events), vec![998, 999, 1000]);
+    }
+
+    #[test]
+    fn test_log_names_round_trip() {
+        for prefix in ["sensor", "orders/eu", "$SYS", "", "a%b"] {
+            let name = encode_log_name(prefix);
+            assert!(!name.contains('/'));
+            assert_eq!(decode_log_name(&name).as_deref(), Some(prefix));
+        }
+    }
+}
@@ -58,10 +58,15 @@ impl Service for Calculator {
     }
 
     fn methods(&self) -> Vec<&'static str> {
-        vec!["add", "multiply"]
+        vec!["add", "multiply", "whoami", "tenant", "sleep"]
     }
 
-    async fn handle(&self, method: &str, payload: Vec<u8>) -> Result<Vec<u8>> {
+    async fn handle(
+        &self,
+        ctx: &RequestContext,
+        method: &str,
+        payload: Vec<u8>,
+    ) -> Result<Vec<u8>> {
         match method {
             "add" => {
                 let (a, b): (i32, i32) = bincode::deserialize(&payload)?;
@@ -73,6 +78,19 @@ impl Service for Calculator {
                 let result = a * b;
                 Ok(bincode::serialize(&result)?)
             }
+            "tenant" => {
+                let tenant = ctx.header("tenant").map(str::to_string);
+                Ok(bincode::serialize(&(tenant, ctx.priority))?)
+            }
+            "sleep" => {
+                let millis: u64 = bincode::deserialize(&payload)?;
+                tokio::time::sleep(Duration::from_millis(millis)).await;
+                Ok(bincode::serialize(&millis)?)
+            }
+            "whoami" => {
+                let remaining = ctx.remaining().map(|remaining| remaining.as_secs());
+                Ok(bincode::serialize(&(ctx.caller.clone(), remaining))?)
+            }
             _ => Err(Error::method_not_found("TestService", method)),
         }
     }
@@ -182,4 +200,1234 @@ mod integration_tests {
         // Cleanup
         hub.shutdown().await.unwrap();
     }
+
+    #[tokio::test]
+    async fn test_subscribe_typed_stream() {
+        use futures::StreamExt;
+
+        let hub = ProcessHub::new("typed_stream_hub").await.unwrap();
+        let mut stream = hub
+            .subscribe_typed::<TestEvent>("typed/stream/+")
+            .await
+            .unwrap();
+
+        hub.publish(
+            "typed/stream/one",
+            TestEvent {
+                message: "typed".to_string(),
+                value: 7,
+            },
+        )
+        .await
+        .unwrap();
+
+        let (topic, event) = timeout(Duration::from_secs(5), stream.next())
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        assert_eq!(topic, "typed/stream/one");
+        assert_eq!(event.value, 7);
+
+        hub.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_dropping_event_stream_unsubscribes() {
+        let registry = event::SubscriptionRegistry::new();
+        let stream = registry
+            .subscribe_typed::<TestEvent>("typed/drop")
+            .await
+            .unwrap();
+        assert_eq!(registry.subscription_count(), 1);
+
+        drop(stream);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(registry.subscription_count(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_closure_subscribers() {
+        let hub = ProcessHub::new("closure_hub").await.unwrap();
+
+        let async_received = Arc::new(Mutex::new(Vec::new()));
+        let sink = async_received.clone();
+        let _async_sub = hub
+            .on("closure/async", move |topic, event: TestEvent| {
+                let sink = sink.clone();
+                async move {
+                    sink.lock().await.push((topic, event.value));
+                    Ok(())
+                }
+            })
+            .await
+            .unwrap();
+
+        let sync_received = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let sink = sync_received.clone();
+        let _sync_sub = hub
+            .on_sync("closure/+", move |topic, event: TestEvent| {
+                sink.lock().unwrap().push((topic.to_string(), event.value));
+                Ok(())
+            })
+            .await
+            .unwrap();
+
+        hub.publish(
+            "closure/async",
+            TestEvent {
+                message: "closure".to_string(),
+                value: 3,
+            },
+        )
+        .await
+        .unwrap();
+
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        assert_eq!(
+            *async_received.lock().await,
+            vec![("closure/async".to_string(), 3)]
+        );
+        assert_eq!(
+            *sync_received.lock().unwrap(),
+            vec![("closure/async".to_string(), 3)]
+        );
+
+        hub.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_slow_subscriber_does_not_stall_others() {
+        let hub = ProcessHub::new("queue_hub").await.unwrap();
+
+        let slow_sub = hub
+            .on_with(
+                "queue/slow",
+                SubscribeOptions::new()
+                    .capacity(2)
+                    .overflow(OverflowPolicy::DropOldest),
+                |_topic, _event: TestEvent| async move {
+                    tokio::time::sleep(Duration::from_secs(60)).await;
+                    Ok(())
+                },
+            )
+            .await
+            .unwrap();
+
+        let fast_received = Arc::new(Mutex::new(0));
+        let counter = fast_received.clone();
+        let _fast_sub = hub
+            .on("queue/slow", move |_topic, _event: TestEvent| {
+                let counter = counter.clone();
+                async move {
+                    *counter.lock().await += 1;
+                    Ok(())
+                }
+            })
+            .await
+            .unwrap();
+
+        for value in 0..10 {
+            hub.publish(
+                "queue/slow",
+                TestEvent {
+                    message: "queued".to_string(),
+                    value,
+                },
+            )
+            .await
+            .unwrap();
+        }
+
+        tokio::time::sleep(Duration::from_millis(300)).await;
+
+        assert_eq!(*fast_received.lock().await, 10);
+        // Two events stay queued (plus possibly one in flight), the rest were dropped
+        let dropped = slow_sub.dropped_count();
+        assert!((7..=8).contains(&dropped), "dropped {dropped}");
+
+        hub.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_full_block_queue_does_not_stall_message_loop() {
+        let server = ProcessHub::new("block_server_hub").await.unwrap();
+        let client = ProcessHub::new("block_client_hub").await.unwrap();
+        server.register_service(Calculator).await.unwrap();
+
+        let (release, released) = tokio::sync::watch::channel(false);
+        let handled = Arc::new(Mutex::new(Vec::new()));
+        let sink = handled.clone();
+        let _sub = server
+            .on_with(
+                "queue/block",
+                SubscribeOptions::new().capacity(3),
+                move |_topic, event: TestEvent| {
+                    let (mut released, sink) = (released.clone(), sink.clone());
+                    async move {
+                        let _ = released.wait_for(|released| *released).await;
+                        sink.lock().await.push(event.value);
+                        Ok(())
+                    }
+                },
+            )
+            .await
+            .unwrap();
+
+        for value in 0..5 {
+            client
+                .publish(
+                    "queue/block",
+                    TestEvent {
+                        message: "backlogged".to_string(),
+                        value,
+                    },
+                )
+                .await
+                .unwrap();
+        }
+
+        // The server's message loop still answers calls behind the full queue
+        let result: i32 = timeout(
+            Duration::from_secs(5),
+            client.call("CalculatorService.add", (2, 3)),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        assert_eq!(result, 5);
+
+        // The queue and its backlog hold every event, so nothing was dropped
+        // and the backlog is delivered in order
+        release.send(true).unwrap();
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert_eq!(*handled.lock().await, vec![0, 1, 2, 3, 4]);
+
+        client.shutdown().await.unwrap();
+        server.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_disconnect_policy_removes_subscription() {
+        let registry = event::SubscriptionRegistry::new();
+        let stream = registry
+            .subscribe_typed_with::<TestEvent>(
+                "queue/disconnect",
+                SubscribeOptions::new()
+                    .capacity(1)
+                    .overflow(OverflowPolicy::Disconnect),
+            )
+            .await
+            .unwrap();
+
+        let payload = bincode::serialize(&TestEvent {
+            message: "overflow".to_string(),
+            value: 1,
+        })
+        .unwrap();
+        for _ in 0..4 {
+            registry
+                .publish("queue/disconnect", payload.clone())
+                .await
+                .unwrap();
+        }
+
+        assert!(stream.subscription().is_disconnected());
+        assert_eq!(registry.subscription_count(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_invalid_topics_are_rejected() {
+        let hub = ProcessHub::new("topic_validation_hub").await.unwrap();
+
+        let result = hub.subscribe_typed::<TestEvent>("topics/#/invalid").await;
+        assert!(matches!(result, Err(Error::InvalidTopicPattern { .. })));
+
+        let result = hub.on_sync("topics/foo+", |_, _: TestEvent| Ok(())).await;
+        assert!(matches!(result, Err(Error::InvalidTopicPattern { .. })));
+
+        let event = TestEvent {
+            message: "wildcard".to_string(),
+            value: 0,
+        };
+        let result = hub.publish("topics/+", event).await;
+        assert!(matches!(result, Err(Error::InvalidTopicPattern { .. })));
+
+        hub.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_shared_subscription_delivers_once_per_group() {
+        let worker_a = ProcessHub::new("shared_worker_a").await.unwrap();
+        let worker_b = ProcessHub::new("shared_worker_b").await.unwrap();
+
+        let counter = |count: Arc<Mutex<usize>>| {
+            move |_topic: String, _event: TestEvent| {
+                let count = count.clone();
+                async move {
+                    *count.lock().await += 1;
+                    Ok(())
+                }
+            }
+        };
+
+        let received_a = Arc::new(Mutex::new(0));
+        let received_b = Arc::new(Mutex::new(0));
+        let sub_a = worker_a
+            .on("$share/shared_test/jobs/#", counter(received_a.clone()))
+            .await
+            .unwrap();
+        let _sub_b = worker_b
+            .on("$share/shared_test/jobs/#", counter(received_b.clone()))
+            .await
+            .unwrap();
+
+        // Let the membership announcements settle
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        for value in 0..20 {
+            let event = TestEvent {
+                message: "job".to_string(),
+                value,
+            };
+            worker_a.publish("jobs/build", event).await.unwrap();
+        }
+        tokio::time::sleep(Duration::from_millis(300)).await;
+
+        let (a, b) = (*received_a.lock().await, *received_b.lock().await);
+        assert_eq!(a + b, 20, "each job handled exactly once");
+        assert!(a > 0 && b > 0, "jobs spread across workers: {a}/{b}");
+
+        // Once worker A leaves, worker B takes every job
+        sub_a.unsubscribe().await.unwrap();
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        for value in 0..10 {
+            let event = TestEvent {
+                message: "job".to_string(),
+                value,
+            };
+            worker_a.publish("jobs/build", event).await.unwrap();
+        }
+        tokio::time::sleep(Duration::from_millis(300)).await;
+
+        assert_eq!(*received_a.lock().await, a);
+        assert_eq!(*received_b.lock().await, b + 10);
+
+        worker_a.shutdown().await.unwrap();
+        worker_b.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_crashed_shared_members_expire() {
+        use crate::transport::Transport;
+
+        let worker = ProcessHub::new("shared_survivor").await.unwrap();
+        let received = Arc::new(Mutex::new(0));
+        let count = received.clone();
+        let _sub = worker
+            .on(
+                "$share/crash_test/crash/#",
+                move |_topic, _event: TestEvent| {
+                    let count = count.clone();
+                    async move {
+                        *count.lock().await += 1;
+                        Ok(())
+                    }
+                },
+            )
+            .await
+            .unwrap();
+
+        // A member that joins and then dies without leaving or heartbeating
+        let crashed = transport::IpmbTransport::new("shared_crashed")
+            .await
+            .unwrap();
+        let member = shared::SharedMember {
+            group: "crash_test".to_string(),
+            filter: "crash/#".to_string(),
+            process: "shared_crashed".to_string(),
+            subscription: uuid::Uuid::new_v4(),
+        };
+        crashed
+            .send(Message::shared_join(
+                "shared_crashed".to_string(),
+                None,
+                &member,
+            ))
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        let publish_jobs = || async {
+            for value in 0..20 {
+                let event = TestEvent {
+                    message: "job".to_string(),
+                    value,
+                };
+                worker.publish("crash/job", event).await.unwrap();
+            }
+            tokio::time::sleep(Duration::from_millis(200)).await;
+        };
+
+        // Jobs picked for the crashed member are lost until it expires
+        publish_jobs().await;
+        let before = *received.lock().await;
+        assert!(before < 20, "crashed member still picked: {before}");
+
+        tokio::time::sleep(shared::MEMBER_TIMEOUT + shared::HEARTBEAT_INTERVAL * 2).await;
+        publish_jobs().await;
+        assert_eq!(*received.lock().await, before + 20);
+
+        worker.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_local_publish_delivered_once() {
+        let hub = ProcessHub::new("fast_path_hub").await.unwrap();
+
+        let received = Arc::new(Mutex::new(0));
+        let counter = received.clone();
+        let _sub = hub
+            .on("fast/path", move |_topic, _event: TestEvent| {
+                let counter = counter.clone();
+                async move {
+                    *counter.lock().await += 1;
+                    Ok(())
+                }
+            })
+            .await
+            .unwrap();
+
+        let event = TestEvent {
+            message: "local".to_string(),
+            value: 1,
+        };
+        hub.publish("fast/path", event).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        // Delivered directly, and not again when it comes back from the bus
+        assert_eq!(*received.lock().await, 1);
+
+        hub.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_no_local_ignores_own_publications() {
+        let hub = ProcessHub::new("no_local_hub").await.unwrap();
+        let other = ProcessHub::new("no_local_other").await.unwrap();
+
+        let received = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let sink = received.clone();
+        let _sub = hub
+            .on_sync_with(
+                "no_local/#",
+                SubscribeOptions::new().no_local(true),
+                move |_topic, event: TestEvent| {
+                    sink.lock().unwrap().push(event.message);
+                    Ok(())
+                },
+            )
+            .await
+            .unwrap();
+
+        for (publisher, message) in [(&hub, "own"), (&other, "remote")] {
+            let event = TestEvent {
+                message: message.to_string(),
+                value: 0,
+            };
+            publisher.publish("no_local/test", event).await.unwrap();
+        }
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        assert_eq!(*received.lock().unwrap(), vec!["remote".to_string()]);
+
+        hub.shutdown().await.unwrap();
+        other.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_subscriber_failures_are_reported_and_dead_lettered() {
+        use futures::StreamExt;
+
+        let hub = ProcessHub::new("failure_hub").await.unwrap();
+        let mut failures = hub.failures();
+        hub.set_dead_letter_topic(Some("failure_dlq")).unwrap();
+
+        let failing = hub
+            .on_sync("failure/jobs/+", |_topic, event: TestEvent| {
+                if event.value < 0 {
+                    return Err(Error::runtime_msg("negative value"));
+                }
+                Ok(())
+            })
+            .await
+            .unwrap();
+
+        let mut dead_letters = hub
+            .subscribe_typed::<TestEvent>("failure_dlq/#")
+            .await
+            .unwrap()
+            .with_context();
+
+        let event = TestEvent {
+            message: "bad".to_string(),
+            value: -1,
+        };
+        hub.publish_with(
+            "failure/jobs/1",
+            event,
+            PublishOptions::new().header("trace", "t-1"),
+        )
+        .await
+        .unwrap();
+
+        let failure = tokio::time::timeout(Duration::from_secs(1), failures.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(failure.topic.as_deref(), Some("failure/jobs/1"));
+        assert_eq!(failure.subscription, Some(failing.id));
+        assert_eq!(failure.source, "failure_hub");
+        assert!(failure.error.to_string().contains("negative value"));
+
+        assert_eq!(failure.headers.get("trace").map(String::as_str), Some("t-1"));
+
+        // The failed event is republished unchanged, headers included, under
+        // the dead-letter prefix
+        let (ctx, event) = tokio::time::timeout(Duration::from_secs(1), dead_letters.next())
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        assert_eq!(ctx.topic, "failure_dlq/failure/jobs/1");
+        assert_eq!(ctx.header("trace"), Some("t-1"));
+        assert_eq!(event.value, -1);
+
+        hub.shutdown().await.unwrap();
+    }
+
+    struct RecordingSyncSubscriber {
+        received: Arc<std::sync::Mutex<Vec<i32>>>,
+    }
+
+    impl SyncSubscriber for RecordingSyncSubscriber {
+        fn topic_pattern(&self) -> &str {
+            "sync_sub/+"
+        }
+
+        fn handle_sync(&mut self, _topic: &str, payload: Vec<u8>) -> Result<()> {
+            let event: TestEvent = bincode::deserialize(&payload)?;
+            // Blocking here must neither stall the hub nor reorder events
+            std::thread::sleep(Duration::from_millis(10));
+            self.received.lock().unwrap().push(event.value);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_sync_subscriber_receives_events_in_order() {
+        let hub = SyncProcessHub::new("sync_subscriber_hub").unwrap();
+
+        let received = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let subscription = hub
+            .subscribe_sync(RecordingSyncSubscriber {
+                received: received.clone(),
+            })
+            .unwrap();
+
+        for value in 0..5 {
+            let event = TestEvent {
+                message: "sync".to_string(),
+                value,
+            };
+            hub.publish("sync_sub/events", event).unwrap();
+        }
+        std::thread::sleep(Duration::from_millis(300));
+
+        assert_eq!(*received.lock().unwrap(), vec![0, 1, 2, 3, 4]);
+
+        // Dropping outside the runtime must not panic
+        drop(subscription);
+        hub.shutdown().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_blocking_sync_closure_does_not_stall_runtime() {
+        let hub = ProcessHub::new("sync_closure_hub").await.unwrap();
+
+        // Blocks its thread until released; inline, it would freeze this runtime
+        let (release, released) = std::sync::mpsc::channel::<()>();
+        let released = std::sync::Mutex::new(released);
+        let _blocking = hub
+            .on_sync("sync_closure/blocking", move |_topic, _event: TestEvent| {
+                let _ = released.lock().unwrap().recv();
+                Ok(())
+            })
+            .await
+            .unwrap();
+
+        let received = Arc::new(Mutex::new(0));
+        let count = received.clone();
+        let _async_sub = hub
+            .on("sync_closure/async", move |_topic, _event: TestEvent| {
+                let count = count.clone();
+                async move {
+                    *count.lock().await += 1;
+                    Ok(())
+                }
+            })
+            .await
+            .unwrap();
+
+        for topic in ["sync_closure/blocking", "sync_closure/async"] {
+            let event = TestEvent {
+                message: "sync".to_string(),
+                value: 0,
+            };
+            hub.publish(topic, event).await.unwrap();
+        }
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert_eq!(*received.lock().await, 1);
+
+        release.send(()).unwrap();
+        hub.shutdown().await.unwrap();
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    struct ReadingV1 {
+        celsius: i32,
+    }
+
+    impl Event for ReadingV1 {
+        const VERSION: u32 = 1;
+
+        fn topic(&self) -> String {
+            "versioned/reading".to_string()
+        }
+    }
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    struct Reading {
+        value: f64,
+        unit: String,
+    }
+
+    impl Event for Reading {
+        const VERSION: u32 = 2;
+
+        fn topic(&self) -> String {
+            "versioned/reading".to_string()
+        }
+    }
+
+    /// Raw subscriber decoding readings through the event context
+    struct RawReadingSubscriber {
+        received: Arc<std::sync::Mutex<Vec<(u32, Reading)>>>,
+    }
+
+    #[async_trait::async_trait]
+    impl Subscriber for RawReadingSubscriber {
+        fn topic_pattern(&self) -> &str {
+            "versioned/reading"
+        }
+
+        async fn handle(&mut self, _topic: &str, _payload: Vec<u8>) -> Result<()> {
+            unreachable!("events are delivered with their context")
+        }
+
+        async fn handle_event(&mut self, ctx: &EventContext, payload: Vec<u8>) -> Result<()> {
+            let event: Reading = ctx.decode(&payload)?;
+            self.received
+                .lock()
+                .unwrap()
+                .push((ctx.schema_version, event));
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_old_event_versions_are_upcast() {
+        let publisher = ProcessHub::new("versioned_publisher").await.unwrap();
+        let subscriber = ProcessHub::new("versioned_subscriber").await.unwrap();
+        let dir = std::env::temp_dir().join(format!("hsipc-versioned-{}", uuid::Uuid::new_v4()));
+        subscriber.set_event_store(Some(
+            EventStore::open(EventStoreConfig::new(&dir).prefix("versioned")).unwrap(),
+        ));
+        subscriber
+            .register_upcaster(1, |old: ReadingV1| Reading {
+                value: old.celsius as f64,
+                unit: "C".to_string(),
+            })
+            .unwrap();
+        let raw = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let _raw_sub = subscriber
+            .subscribe(RawReadingSubscriber {
+                received: raw.clone(),
+            })
+            .await
+            .unwrap();
+
+        let received = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let sink = received.clone();
+        let _sub = subscriber
+            .on_sync("versioned/reading", move |_topic, event: Reading| {
+                sink.lock().unwrap().push(event);
+                Ok(())
+            })
+            .await
+            .unwrap();
+
+        // One process still publishes version 1, another already publishes version 2
+        publisher
+            .publish_event(ReadingV1 { celsius: 20 })
+            .await
+            .unwrap();
+        publisher
+            .publish_event(Reading {
+                value: 68.0,
+                unit: "F".to_string(),
+            })
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        let received = received.lock().unwrap().clone();
+        assert_eq!(
+            received,
+            vec![
+                Reading {
+                    value: 20.0,
+                    unit: "C".to_string(),
+                },
+                Reading {
+                    value: 68.0,
+                    unit: "F".to_string(),
+                },
+            ]
+        );
+
+        // Raw subscribers see the schema version and upcast through the context
+        let expected: Vec<_> = [1, 2].into_iter().zip(received.iter().cloned()).collect();
+        assert_eq!(*raw.lock().unwrap(), expected);
+
+        // Stored events keep their schema version for replay
+        let replayed = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let sink = replayed.clone();
+        let _replaying = subscriber
+            .on_sync_with(
+                "versioned/reading",
+                SubscribeOptions::new().replay(ReplayFrom::Offset(0)),
+                move |_topic, event: Reading| {
+                    sink.lock().unwrap().push(event);
+                    Ok(())
+                },
+            )
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert_eq!(*replayed.lock().unwrap(), received);
+
+        publisher.shutdown().await.unwrap();
+        subscriber.shutdown().await.unwrap();
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn test_replay_stored_events_before_live_delivery() {
+        let dir = std::env::temp_dir().join(format!("hsipc-replay-{}", uuid::Uuid::new_v4()));
+        let hub = ProcessHub::new("replay_hub").await.unwrap();
+        hub.set_event_store(Some(
+            EventStore::open(EventStoreConfig::new(&dir).prefix("replay")).unwrap(),
+        ));
+
+        for value in 0..3 {
+            let event = TestEvent {
+                message: "stored".to_string(),
+                value,
+            };
+            hub.publish("replay/readings", event).await.unwrap();
+        }
+
+        let received = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let sink = received.clone();
+        let _sub = hub
+            .on_sync_with(
+                "replay/#",
+                SubscribeOptions::new().replay(ReplayFrom::Offset(1)),
+                move |_topic, event: TestEvent| {
+                    sink.lock().unwrap().push(event.value);
+                    Ok(())
+                },
+            )
+            .await
+            .unwrap();
+
+        let event = TestEvent {
+            message: "live".to_string(),
+            value: 3,
+        };
+        hub.publish("replay/readings", event).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        // Offsets 1 and 2 are replayed, then the live event follows exactly once
+        assert_eq!(*received.lock().unwrap(), vec![1, 2, 3]);
+
+        // Replay needs a store
+        hub.set_event_store(None);
+        let result = hub
+            .on_sync_with(
+                "replay/#",
+                SubscribeOptions::new().replay(ReplayFrom::Timestamp(0)),
+                |_topic, _event: TestEvent| Ok(()),
+            )
+            .await;
+        assert!(result.is_err());
+
+        hub.shutdown().await.unwrap();
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn test_content_filter_skips_non_matching_events() {
+        let hub = ProcessHub::new("content_filter_hub").await.unwrap();
+
+        let received = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let sink = received.clone();
+        let _sub = hub
+            .on_sync_with(
+                "filtered/#",
+                SubscribeOptions::new().filter(r#"value > 80 && message == "C""#),
+                move |_topic, event: TestEvent| {
+                    sink.lock().unwrap().push(event.value);
+                    Ok(())
+                },
+            )
+            .await
+            .unwrap();
+
+        for (value, unit) in [(75, "C"), (85, "C"), (90, "F"), (95, "C")] {
+            let event = TestEvent {
+                message: unit.to_string(),
+                value,
+            };
+            hub.publish("filtered/temperature", event).await.unwrap();
+        }
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        assert_eq!(*received.lock().unwrap(), vec![85, 95]);
+
+        // Invalid expressions and untyped subscribers are rejected
+        let invalid = hub
+            .on_sync_with(
+                "filtered/#",
+                SubscribeOptions::new().filter("value >"),
+                |_topic, _event: TestEvent| Ok(()),
+            )
+            .await;
+        assert!(invalid.is_err());
+
+        let untyped = hub
+            .subscribe_with(
+                TestSubscriber::new(),
+                SubscribeOptions::new().filter("value > 80"),
+            )
+            .await;
+        assert!(untyped.is_err());
+
+        hub.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_at_least_once_events_are_confirmed() {
+        let publisher = ProcessHub::new("qos_publisher").await.unwrap();
+        let receiver = ProcessHub::new("qos_receiver").await.unwrap();
+        let bystander = ProcessHub::new("qos_bystander").await.unwrap();
+        let dir = std::env::temp_dir().join(format!("hsipc-qos-{}", uuid::Uuid::new_v4()));
+        bystander.set_event_store(Some(
+            EventStore::open(EventStoreConfig::new(&dir).prefix("qos")).unwrap(),
+        ));
+
+        let received = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let sink = received.clone();
+        let _sub = receiver
+            .on_sync("qos/confirmed", move |_topic, event: TestEvent| {
+                sink.lock().unwrap().push(event.value);
+                Ok(())
+            })
+            .await
+            .unwrap();
+
+        let options = PublishOptions::new().qos(Qos::AtLeastOnce);
+        let event = TestEvent {
+            message: "confirmed".to_string(),
+            value: 7,
+        };
+        let receipt = publisher
+            .publish_with("qos/confirmed", event, options)
+            .await
+            .unwrap();
+        assert_eq!(receipt.confirmed_by.as_deref(), Some("qos_receiver"));
+        assert_eq!(receipt.attempts, 1);
+
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert_eq!(*received.lock().unwrap(), vec![7]);
+
+        // Nobody is listening here, so every attempt goes unconfirmed
+        let options = PublishOptions::new()
+            .qos(Qos::AtLeastOnce)
+            .ack_timeout(Duration::from_millis(50))
+            .max_retries(1);
+        let event = TestEvent {
+            message: "unheard".to_string(),
+            value: 8,
+        };
+        let result = publisher.publish_with("qos/unheard", event, options).await;
+        assert!(result.is_err());
+
+        // Processes without a subscription keep only the first copy
+        let stored = EventStore::open(EventStoreConfig::new(&dir).read_only(true))
+            .unwrap()
+            .read("qos/unheard", ReplayFrom::Offset(0), u64::MAX, 10)
+            .unwrap();
+        assert_eq!(stored.len(), 1);
+
+        // A subscription of the publisher confirms the event on its own
+        let local = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let sink = local.clone();
+        let _local_sub = publisher
+            .on_sync("qos/confirmed", move |_topic, event: TestEvent| {
+                sink.lock().unwrap().push(event.value);
+                Ok(())
+            })
+            .await
+            .unwrap();
+        let event = TestEvent {
+            message: "confirmed locally".to_string(),
+            value: 9,
+        };
+        let options = PublishOptions::new().qos(Qos::AtLeastOnce);
+        let receipt = publisher
+            .publish_with("qos/confirmed", event, options)
+            .await
+            .unwrap();
+        assert_eq!(receipt.confirmed_by.as_deref(), Some("qos_publisher"));
+        assert_eq!(receipt.attempts, 1);
+
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert_eq!(*local.lock().unwrap(), vec![9]);
+        assert_eq!(*received.lock().unwrap(), vec![7, 9]);
+
+        // The copy for other processes is still resent until one of them acks,
+        // so a process subscribing after the first copies went out gets it too
+        let _local_late = publisher
+            .on_sync("qos/late", |_topic, _event: TestEvent| Ok(()))
+            .await
+            .unwrap();
+        let options = PublishOptions::new()
+            .qos(Qos::AtLeastOnce)
+            .ack_timeout(Duration::from_millis(100))
+            .max_retries(10);
+        let event = TestEvent {
+            message: "late".to_string(),
+            value: 10,
+        };
+        let receipt = publisher
+            .publish_with("qos/late", event, options)
+            .await
+            .unwrap();
+        assert_eq!(receipt.confirmed_by.as_deref(), Some("qos_publisher"));
+        tokio::time::sleep(Duration::from_millis(250)).await;
+
+        let late_hub = ProcessHub::new("qos_late").await.unwrap();
+        let late = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let sink = late.clone();
+        let _late_sub = late_hub
+            .on_sync("qos/late", move |_topic, event: TestEvent| {
+                sink.lock().unwrap().push(event.value);
+                Ok(())
+            })
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        assert_eq!(*late.lock().unwrap(), vec![10]);
+
+        late_hub.shutdown().await.unwrap();
+        publisher.shutdown().await.unwrap();
+        receiver.shutdown().await.unwrap();
+        bystander.shutdown().await.unwrap();
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn test_services_receive_request_context() {
+        let server = ProcessHub::new("ctx_server_hub").await.unwrap();
+        let client = ProcessHub::new("ctx_client_hub").await.unwrap();
+        server.register_service(Calculator).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        // Remote calls carry the caller and the call's deadline
+        let (caller, remaining): (String, Option<u64>) =
+            client.call("CalculatorService.whoami", ()).await.unwrap();
+        assert_eq!(caller, "ctx_client_hub");
+        assert!(remaining.is_some_and(|secs| secs > 20 && secs <= 30));
+
+        // Local calls see the hub itself as the caller
+        let (caller, _): (String, Option<u64>) =
+            server.call("CalculatorService.whoami", ()).await.unwrap();
+        assert_eq!(caller, "ctx_server_hub");
+
+        client.shutdown().await.unwrap();
+        server.shutdown().await.unwrap();
+    }
+
+    /// Subscriber recording the tenant header of each event
+    struct TenantSubscriber {
+        tenants: Arc<Mutex<Vec<Option<String>>>>,
+    }
+
+    #[async_trait::async_trait]
+    impl Subscriber for TenantSubscriber {
+        fn topic_pattern(&self) -> &str {
+            "headers/#"
+        }
+
+        async fn handle(&mut self, _topic: &str, _payload: Vec<u8>) -> Result<()> {
+            unreachable!("events are delivered with their context")
+        }
+
+        async fn handle_event(&mut self, ctx: &EventContext, _payload: Vec<u8>) -> Result<()> {
+            self.tenants
+                .lock()
+                .await
+                .push(ctx.header("tenant").map(str::to_string));
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_headers_reach_handlers_and_subscribers() {
+        use futures::StreamExt;
+
+        let server = ProcessHub::new("headers_server_hub").await.unwrap();
+        let client = ProcessHub::new("headers_client_hub").await.unwrap();
+        let dir = std::env::temp_dir().join(format!("hsipc-headers-{}", uuid::Uuid::new_v4()));
+        server.set_event_store(Some(
+            EventStore::open(EventStoreConfig::new(&dir).prefix("headers")).unwrap(),
+        ));
+        server.register_service(Calculator).await.unwrap();
+        let tenants = Arc::new(Mutex::new(Vec::new()));
+        let _subscription = server
+            .subscribe(TenantSubscriber {
+                tenants: tenants.clone(),
+            })
+            .await
+            .unwrap();
+
+        // Closures and streams read headers from the event context
+        let closure_tenants = Arc::new(Mutex::new(Vec::new()));
+        let recorded = closure_tenants.clone();
+        let _closure = server
+            .on_event("headers/#", move |ctx, _: TestEvent| {
+                let recorded = recorded.clone();
+                async move {
+                    recorded
+                        .lock()
+                        .await
+                        .push(ctx.header("tenant").map(str::to_string));
+                    Ok(())
+                }
+            })
+            .await
+            .unwrap();
+        let sync_tenants = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let recorded = sync_tenants.clone();
+        let _sync_closure = server
+            .on_sync_event("headers/#", move |ctx, _: TestEvent| {
+                recorded
+                    .lock()
+                    .unwrap()
+                    .push(ctx.header("tenant").map(str::to_string));
+                Ok(())
+            })
+            .await
+            .unwrap();
+        let mut stream = server
+            .subscribe_typed::<TestEvent>("headers/#")
+            .await
+            .unwrap()
+            .with_context();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        // Call options carry headers and priority to the service
+        let options = CallOptions::new()
+            .header("tenant", "acme")
+            .priority(Priority::High);
+        let (tenant, priority): (Option<String>, Priority) = client
+            .call_with("CalculatorService.tenant", (), options)
+            .await
+            .unwrap();
+        assert_eq!(tenant.as_deref(), Some("acme"));
+        assert_eq!(priority, Priority::High);
+
+        let (tenant, _): (Option<String>, Priority) =
+            client.call("CalculatorService.tenant", ()).await.unwrap();
+        assert_eq!(tenant, None);
+
+        // Publish options carry headers to remote and local subscribers
+        client
+            .publish_with(
+                "headers/a",
+                TestEvent {
+                    message: "remote".to_string(),
+                    value: 1,
+                },
+                PublishOptions::new().header("tenant", "remote"),
+            )
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        server
+            .publish_with(
+                "headers/b",
+                TestEvent {
+                    message: "local".to_string(),
+                    value: 2,
+                },
+                PublishOptions::new().header("tenant", "local"),
+            )
+            .await
+            .unwrap();
+        let event = TestEvent {
+            message: "none".to_string(),
+            value: 3,
+        };
+        server.publish("headers/c", event).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let expected = vec![Some("remote".to_string()), Some("local".to_string()), None];
+        assert_eq!(*tenants.lock().await, expected);
+        assert_eq!(*closure_tenants.lock().await, expected);
+        assert_eq!(*sync_tenants.lock().unwrap(), expected);
+        for (tenant, value) in expected.iter().zip(1..) {
+            let (ctx, event) = stream.next().await.unwrap().unwrap();
+            assert_eq!(ctx.header("tenant"), tenant.as_deref());
+            assert_eq!(
+                ctx.source,
+                if value == 1 {
+                    client.name()
+                } else {
+                    server.name()
+                }
+            );
+            assert_eq!(event.value, value);
+        }
+
+        // Stored events are replayed with their headers
+        let replayed = Arc::new(Mutex::new(Vec::new()));
+        let _replaying = server
+            .subscribe_with(
+                TenantSubscriber {
+                    tenants: replayed.clone(),
+                },
+                SubscribeOptions::new().replay(ReplayFrom::Offset(0)),
+            )
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(*replayed.lock().await, *tenants.lock().await);
+
+        client.shutdown().await.unwrap();
+        server.shutdown().await.unwrap();
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    /// Service layer rejecting requests without a tenant header
+    struct RequireTenant;
+
+    #[async_trait::async_trait]
+    impl ServiceLayer for RequireTenant {
+        async fn handle(
+            &self,
+            ctx: &RequestContext,
+            method: &str,
+            payload: Vec<u8>,
+            next: layer::ServiceNext<'_>,
+        ) -> Result<Vec<u8>> {
+            if ctx.header("tenant").is_none() {
+                return Err(Error::invalid_request("missing tenant", None));
+            }
+            next.run(ctx, method, payload).await
+        }
+    }
+
+    /// Call layer adding a tenant header to every call
+    struct AddTenant(&'static str);
+
+    #[async_trait::async_trait]
+    impl CallLayer for AddTenant {
+        async fn call(&self, mut call: layer::Call, next: layer::CallNext<'_>) -> Result<Vec<u8>> {
+            call.options
+                .headers
+                .insert("tenant".to_string(), self.0.to_string());
+            next.run(call).await
+        }
+    }
+
+    #[tokio::test]
+    async fn test_layers_wrap_services_and_calls() {
+        let server = ProcessHub::new("layers_server_hub").await.unwrap();
+        let client = ProcessHub::new("layers_client_hub").await.unwrap();
+        let server_timing = TimingLayer::new();
+        server.add_service_layer(LoggingLayer);
+        server.add_service_layer(server_timing.clone());
+        server.add_service_layer(RequireTenant);
+        server.register_service(Calculator).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        // The service layer rejects calls before they reach the service
+        let err = client
+            .call::<_, (Option<String>, Priority)>("CalculatorService.tenant", ())
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("missing tenant"), "{err}");
+
+        // Call layers run on the sending side, outermost first
+        let client_timing = TimingLayer::new();
+        client.add_call_layer(client_timing.clone());
+        client.add_call_layer(AddTenant("acme"));
+        let (tenant, _): (Option<String>, Priority) =
+            client.call("CalculatorService.tenant", ()).await.unwrap();
+        assert_eq!(tenant.as_deref(), Some("acme"));
+
+        let timing = server_timing.timings()["CalculatorService.tenant"];
+        assert_eq!((timing.calls, timing.errors), (2, 1));
+        let timing = client_timing.timings()["CalculatorService.tenant"];
+        assert_eq!((timing.calls, timing.errors), (1, 0));
+
+        client.shutdown().await.unwrap();
+        server.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_local_calls_time_out() {
+        let hub = ProcessHub::new("test_local_timeout").await.unwrap();
+        hub.register_service(Calculator).await.unwrap();
+
+        // A local service is called in-process, but the caller still gives up on time
+        let options = CallOptions::new().timeout(Duration::from_millis(100));
+        let started = std::time::Instant::now();
+        let err = hub
+            .call_with::<_, u64>("CalculatorService.sleep", 5_000u64, options)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::Timeout { .. }), "{err}");
+        assert!(started.elapsed() < Duration::from_secs(2));
+
+        let slept: u64 = hub.call("CalculatorService.sleep", 10u64).await.unwrap();
+        assert_eq!(slept, 10);
+
+        hub.shutdown().await.unwrap();
+    }
 }
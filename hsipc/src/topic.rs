@@ -0,0 +1,462 @@
+//! Topic pattern matching
+//!
+//! Topics are `/`-separated levels and follow MQTT rules. Patterns may use `+`
+//! to match exactly one level and `#` as the last level to match the parent
+//! and all remaining levels. Topics starting with `$` are system topics and
+//! are not matched by patterns that start with a wildcard.
+//!
+//! Shared subscriptions use `$share/{group}/{filter}`: each matching event is
+//! delivered to only one member of the group.
+
+use crate::{Error, Result};
+use std::collections::HashMap;
+
+/// Validate a topic name used for publishing
+///
+/// Topic names must be non-empty and must not contain wildcards or NUL.
+pub fn validate_topic_name(topic: &str) -> Result<()> {
+    if topic.is_empty() || topic.contains(['+', '#', '\0']) {
+        return Err(Error::invalid_topic_pattern(topic));
+    }
+    Ok(())
+}
+
+/// Prefix of shared subscription patterns
+pub const SHARED_PREFIX: &str = "$share/";
+
+/// Split a `$share/{group}/{filter}` pattern into its group and filter
+///
+/// Returns `None` for patterns that are not shared subscriptions.
+pub fn parse_shared_pattern(pattern: &str) -> Option<(&str, &str)> {
+    pattern.strip_prefix(SHARED_PREFIX)?.split_once('/')
+}
+
+/// Validate a topic pattern used for subscribing
+///
+/// Wildcards must occupy a whole level, and `#` may only be the last level.
+pub fn validate_topic_filter(pattern: &str) -> Result<()> {
+    if pattern.starts_with(SHARED_PREFIX) {
+        return match parse_shared_pattern(pattern) {
+            Some((group, filter)) if !group.is_empty() && !group.contains(['+', '#']) => {
+                validate_plain_filter(filter).map_err(|_| Error::invalid_topic_pattern(pattern))
+            }
+            _ => Err(Error::invalid_topic_pattern(pattern)),
+        };
+    }
+
+    validate_plain_filter(pattern)
+}
+
+fn validate_plain_filter(pattern: &str) -> Result<()> {
+    if pattern.is_empty() || pattern.contains('\0') {
+        return Err(Error::invalid_topic_pattern(pattern));
+    }
+
+    let mut levels = pattern.split('/').peekable();
+    while let Some(level) = levels.next() {
+        let valid = match level {
+            "+" => true,
+            "#" => levels.peek().is_none(),
+            _ => !level.contains(['+', '#']),
+        };
+        if !valid {
+            return Err(Error::invalid_topic_pattern(pattern));
+        }
+    }
+    Ok(())
+}
+
+/// Whether a topic is a `$`-prefixed system topic
+fn is_system_topic(topic: &str) -> bool {
+    topic.starts_with('$')
+}
+
+/// Check if a topic matches a pattern
+/// Supports wildcards: + (single level), # (multi level)
+pub fn topic_matches(topic: &str, pattern: &str) -> bool {
+    // Root wildcards never match system topics
+    if is_system_topic(topic) && pattern.starts_with(['+', '#']) {
+        return false;
+    }
+
+    let mut topic_parts = topic.split('/');
+    for part in pattern.split('/') {
+        match part {
+            // Multi-level wildcard matches the parent and everything below it
+            "#" => return true,
+            // Single-level wildcard matches exactly one part
+            "+" => {
+                if topic_parts.next().is_none() {
+                    return false;
+                }
+            }
+            part => {
+                if topic_parts.next() != Some(part) {
+                    return false;
+                }
+            }
+        }
+    }
+
+    // The topic must be exhausted as well
+    topic_parts.next().is_none()
+}
+
+/// Index of topic patterns, organised as a trie over topic levels
+///
+/// Looking up the values whose pattern matches a topic costs time proportional
+/// to the topic depth (times the number of wildcard branches taken), rather
+/// than to the number of registered patterns.
+#[derive(Debug)]
+pub struct TopicTrie<T> {
+    root: TrieNode<T>,
+    len: usize,
+}
+
+#[derive(Debug)]
+struct TrieNode<T> {
+    /// Literal child levels
+    children: HashMap<String, TrieNode<T>>,
+    /// Child for the `+` wildcard
+    single: Option<Box<TrieNode<T>>>,
+    /// Values whose pattern ends in `#` at this level
+    multi: Vec<T>,
+    /// Values whose pattern ends exactly at this level
+    values: Vec<T>,
+}
+
+impl<T> Default for TrieNode<T> {
+    fn default() -> Self {
+        Self {
+            children: HashMap::new(),
+            single: None,
+            multi: Vec::new(),
+            values: Vec::new(),
+        }
+    }
+}
+
+impl<T> TrieNode<T> {
+    fn is_empty(&self) -> bool {
+        self.children.is_empty()
+            && self.single.is_none()
+            && self.multi.is_empty()
+            && self.values.is_empty()
+    }
+}
+
+impl<T> Default for TopicTrie<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> TopicTrie<T> {
+    pub fn new() -> Self {
+        Self {
+            root: TrieNode::default(),
+            len: 0,
+        }
+    }
+
+    /// Number of stored values
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Whether the trie holds no values
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Add a value under a topic pattern
+    pub fn insert(&mut self, pattern: &str, value: T) {
+        let mut node = &mut self.root;
+        for level in pattern.split('/') {
+            match level {
+                "#" => {
+                    node.multi.push(value);
+                    self.len += 1;
+                    return;
+                }
+                "+" => node = node.single.get_or_insert_with(Default::default),
+                _ => node = node.children.entry(level.to_string()).or_default(),
+            }
+        }
+        node.values.push(value);
+        self.len += 1;
+    }
+}
+
+impl<T: PartialEq> TopicTrie<T> {
+    /// Remove a value from a topic pattern, returning whether it was present
+    pub fn remove(&mut self, pattern: &str, value: &T) -> bool {
+        let levels: Vec<&str> = pattern.split('/').collect();
+        let removed = Self::remove_at(&mut self.root, &levels, value);
+        if removed {
+            self.len -= 1;
+        }
+        removed
+    }
+
+    fn remove_at(node: &mut TrieNode<T>, levels: &[&str], value: &T) -> bool {
+        let Some((level, rest)) = levels.split_first() else {
+            return Self::remove_value(&mut node.values, value);
+        };
+
+        match *level {
+            "#" => Self::remove_value(&mut node.multi, value),
+            "+" => {
+                let Some(child) = node.single.as_deref_mut() else {
+                    return false;
+                };
+                let removed = Self::remove_at(child, rest, value);
+                if child.is_empty() {
+                    node.single = None;
+                }
+                removed
+            }
+            _ => {
+                let Some(child) = node.children.get_mut(*level) else {
+                    return false;
+                };
+                let removed = Self::remove_at(child, rest, value);
+                if child.is_empty() {
+                    node.children.remove(*level);
+                }
+                removed
+            }
+        }
+    }
+
+    fn remove_value(values: &mut Vec<T>, value: &T) -> bool {
+        match values.iter().position(|v| v == value) {
+            Some(pos) => {
+                values.swap_remove(pos);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+impl<T: Clone> TopicTrie<T> {
+    /// Collect the values of every pattern matching a topic
+    pub fn matches(&self, topic: &str) -> Vec<T> {
+        let levels: Vec<&str> = topic.split('/').collect();
+        let mut out = Vec::new();
+
+        if is_system_topic(topic) {
+            // Root wildcards never match system topics
+            if let Some(child) = self.root.children.get(levels[0]) {
+                Self::collect(child, &levels[1..], &mut out);
+            }
+        } else {
+            Self::collect(&self.root, &levels, &mut out);
+        }
+        out
+    }
+
+    fn collect(node: &TrieNode<T>, levels: &[&str], out: &mut Vec<T>) {
+        // `#` also matches the parent level
+        out.extend(node.multi.iter().cloned());
+
+        let Some((level, rest)) = levels.split_first() else {
+            out.extend(node.values.iter().cloned());
+            return;
+        };
+
+        if let Some(child) = node.children.get(*level) {
+            Self::collect(child, rest, out);
+        }
+        if let Some(child) = node.single.as_deref() {
+            Self::collect(child, rest, out);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PATTERNS: &[&str] = &[
+        "sensor/temperature",
+        "sensor/+",
+        "sensor/#",
+        "+/temperature",
+        "+/+/status",
+        "device/+/status",
+        "#",
+    ];
+
+    const TOPICS: &[&str] = &[
+        "sensor/temperature",
+        "sensor/humidity",
+        "sensor/temperature/raw",
+        "device/42/status",
+        "device/42/config",
+        "sensor",
+        "other",
+    ];
+
+    #[test]
+    fn test_trie_agrees_with_scan() {
+        let mut trie = TopicTrie::new();
+        for (i, pattern) in PATTERNS.iter().enumerate() {
+            trie.insert(pattern, i);
+        }
+
+        for topic in TOPICS {
+            let mut from_trie = trie.matches(topic);
+            from_trie.sort_unstable();
+
+            let from_scan: Vec<usize> = PATTERNS
+                .iter()
+                .enumerate()
+                .filter(|(_, pattern)| topic_matches(topic, pattern))
+                .map(|(i, _)| i)
+                .collect();
+
+            assert_eq!(from_trie, from_scan, "topic {topic}");
+        }
+    }
+
+    /// (pattern, topic, expected match)
+    const CONFORMANCE: &[(&str, &str, bool)] = &[
+        // Exact matches
+        ("sport/tennis", "sport/tennis", true),
+        ("sport/tennis", "sport/tennis/player1", false),
+        ("sport/tennis", "sport", false),
+        // `#` matches the parent and any number of child levels
+        ("sport/#", "sport", true),
+        ("sport/#", "sport/tennis", true),
+        ("sport/#", "sport/tennis/player1", true),
+        ("sport/#", "sports", false),
+        ("sport/tennis/#", "sport/tennis", true),
+        ("#", "sport/tennis", true),
+        ("#", "/", true),
+        // `+` matches exactly one level, including an empty one
+        ("sport/+", "sport/tennis", true),
+        ("sport/+", "sport", false),
+        ("sport/+", "sport/", true),
+        ("sport/+", "sport/tennis/player1", false),
+        ("sport/+/player1", "sport/tennis/player1", true),
+        ("+/+", "/finance", true),
+        ("/+", "/finance", true),
+        ("+", "/finance", false),
+        ("+/tennis/#", "sport/tennis", true),
+        // Levels are case sensitive and may be empty
+        ("Sport", "sport", false),
+        ("a//b", "a//b", true),
+        ("a/+/b", "a//b", true),
+        // `$` topics are not matched by root wildcards
+        ("#", "$SYS/uptime", false),
+        ("+/uptime", "$SYS/uptime", false),
+        ("$SYS/#", "$SYS/uptime", true),
+        ("$SYS/+", "$SYS/uptime", true),
+        ("$SYS/uptime", "$SYS/uptime", true),
+    ];
+
+    #[test]
+    fn test_mqtt_matching_conformance() {
+        for &(pattern, topic, expected) in CONFORMANCE {
+            assert_eq!(
+                topic_matches(topic, pattern),
+                expected,
+                "scan: pattern {pattern:?} topic {topic:?}"
+            );
+
+            let mut trie = TopicTrie::new();
+            trie.insert(pattern, ());
+            assert_eq!(
+                trie.matches(topic).len() == 1,
+                expected,
+                "trie: pattern {pattern:?} topic {topic:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_topic_filter_validation() {
+        let cases: &[(&str, bool)] = &[
+            ("sport/tennis", true),
+            ("sport/#", true),
+            ("#", true),
+            ("+", true),
+            ("+/tennis/#", true),
+            ("sport/+/player1", true),
+            ("/", true),
+            ("$SYS/#", true),
+            ("", false),
+            ("sport/tennis#", false),
+            ("sport/#/ranking", false),
+            ("#/sport", false),
+            ("sport+", false),
+            ("foo+", false),
+            ("sport/+tennis", false),
+            ("a/\0", false),
+            ("$share/workers/jobs/#", true),
+            ("$share/workers/+", true),
+            ("$share/workers", false),
+            ("$share//jobs", false),
+            ("$share/work+/jobs", false),
+            ("$share/workers/jobs/#/x", false),
+        ];
+
+        for &(pattern, valid) in cases {
+            assert_eq!(
+                validate_topic_filter(pattern).is_ok(),
+                valid,
+                "pattern {pattern:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_shared_pattern() {
+        assert_eq!(
+            parse_shared_pattern("$share/workers/jobs/#"),
+            Some(("workers", "jobs/#"))
+        );
+        assert_eq!(parse_shared_pattern("jobs/#"), None);
+        assert_eq!(parse_shared_pattern("$share/workers"), None);
+    }
+
+    #[test]
+    fn test_topic_name_validation() {
+        let cases: &[(&str, bool)] = &[
+            ("sport/tennis", true),
+            ("/", true),
+            ("$SYS/uptime", true),
+            ("", false),
+            ("sport/+", false),
+            ("sport/#", false),
+            ("a\0b", false),
+        ];
+
+        for &(topic, valid) in cases {
+            assert_eq!(validate_topic_name(topic).is_ok(), valid, "topic {topic:?}");
+        }
+
+        let err = validate_topic_name("sport/#").unwrap_err();
+        assert_eq!(err.category(), "topic_validation");
+    }
+
+    #[test]
+    fn test_trie_remove_prunes_nodes() {
+        let mut trie = TopicTrie::new();
+        trie.insert("device/+/status", 1);
+        trie.insert("device/+/status", 2);
+        trie.insert("device/#", 3);
+        assert_eq!(trie.len(), 3);
+
+        assert!(trie.remove("device/+/status", &1));
+        assert!(!trie.remove("device/+/status", &1));
+        assert_eq!(trie.matches("device/7/status"), vec![3, 2]);
+
+        assert!(trie.remove("device/+/status", &2));
+        assert!(trie.remove("device/#", &3));
+        assert!(trie.is_empty());
+        assert!(trie.root.is_empty());
+    }
+}
@@ -1,20 +1,84 @@
 //! Real IPMB-based transport for cross-process communication
 
+use crate::message::MessageExtension;
 use crate::transport::Transport;
 use crate::{Error, Message, Result};
 use async_trait::async_trait;
-use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 use tokio::sync::Mutex;
 use type_uuid::TypeUuid;
 
+/// Format of the extension trailer
+///
+/// A message without a trailer ends with its `retain` flag, encoded as 0 or 1,
+/// so a larger last byte marks a trailer.
+const EXTENSION_FORMAT: u8 = 2;
+
 /// IPMB message wrapper for our Message type
-#[derive(Debug, Serialize, Deserialize, TypeUuid)]
+///
+/// Encoded as the message, in the layout every peer shares, followed by a
+/// trailer when the message has a [`MessageExtension`]:
+///
+/// `message | extension | extension length (u32 LE) | EXTENSION_FORMAT`
+///
+/// Peers that predate extensions stop reading after the message.
+#[derive(Debug, TypeUuid)]
 #[uuid = "7b07473e-9659-4d47-a502-8245d71c0078"]
 struct IpmbMessage {
     inner: Message,
 }
 
+impl ipmb::MessageBox for IpmbMessage {
+    fn decode(uuid: type_uuid::Bytes, data: &[u8]) -> std::result::Result<Self, ipmb::Error> {
+        if uuid != Self::UUID {
+            return Err(ipmb::Error::TypeUuidNotFound);
+        }
+        let (message, extension) = split_extension(data)?;
+        let mut inner: Message = ipmb::decode(message)?;
+        if let Some(extension) = extension {
+            inner.apply_extension(MessageExtension::from_bytes(extension).map_err(invalid_data)?);
+        }
+        Ok(Self { inner })
+    }
+
+    fn encode(&self) -> std::result::Result<Vec<u8>, ipmb::Error> {
+        let mut data = ipmb::encode(&self.inner)?;
+        let extension = self.inner.extension();
+        if !extension.is_empty() {
+            let extension = extension.to_bytes().map_err(invalid_data)?;
+            data.extend_from_slice(&extension);
+            data.extend_from_slice(&(extension.len() as u32).to_le_bytes());
+            data.push(EXTENSION_FORMAT);
+        }
+        Ok(data)
+    }
+
+    fn uuid(&self) -> type_uuid::Bytes {
+        Self::UUID
+    }
+}
+
+/// Split an encoded message from its extension, if it has a trailer
+fn split_extension(data: &[u8]) -> std::result::Result<(&[u8], Option<&[u8]>), ipmb::Error> {
+    match data.last() {
+        Some(&format) if format >= EXTENSION_FORMAT => {
+            let truncated = || invalid_data("truncated message extension");
+            let end = data.len().checked_sub(5).ok_or_else(truncated)?;
+            let len = u32::from_le_bytes(data[end..end + 4].try_into().unwrap()) as usize;
+            let start = end.checked_sub(len).ok_or_else(truncated)?;
+            Ok((&data[..start], Some(&data[start..end])))
+        }
+        _ => Ok((data, None)),
+    }
+}
+
+fn invalid_data(error: impl ToString) -> ipmb::Error {
+    ipmb::Error::IoError(std::io::Error::new(
+        std::io::ErrorKind::InvalidData,
+        error.to_string(),
+    ))
+}
+
 /// IPMB-based transport implementation
 pub struct IpmbTransport {
     sender: ipmb::EndpointSender<IpmbMessage>,
@@ -99,3 +163,106 @@ impl Transport for IpmbTransport {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::{MessageMetadata, MessageType, Priority};
+    use ipmb::MessageBox;
+    use serde::{Deserialize, Serialize};
+    use uuid::Uuid;
+
+    /// Messages as encoded before extensions existed
+    #[derive(Serialize, Deserialize)]
+    struct OldMessage {
+        id: Uuid,
+        msg_type: MessageType,
+        source: String,
+        target: Option<String>,
+        topic: Option<String>,
+        payload: Vec<u8>,
+        correlation_id: Option<Uuid>,
+        metadata: OldMetadata,
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct OldMetadata {
+        timestamp: u64,
+        priority: Priority,
+        ttl: Option<u64>,
+        retain: bool,
+    }
+
+    fn message(retain: bool) -> Message {
+        let mut msg = Message::event("source".to_string(), "a/b".to_string(), vec![1, 2, 3]);
+        msg.metadata = MessageMetadata {
+            retain,
+            ..MessageMetadata::default()
+        };
+        msg
+    }
+
+    fn round_trip(msg: Message) -> Message {
+        let data = IpmbMessage { inner: msg }.encode().unwrap();
+        IpmbMessage::decode(IpmbMessage::UUID, &data).unwrap().inner
+    }
+
+    #[test]
+    fn test_messages_without_extension_keep_the_old_encoding() {
+        for retain in [false, true] {
+            let msg = message(retain);
+            let data = IpmbMessage { inner: msg.clone() }.encode().unwrap();
+            let old: OldMessage = ipmb::decode(&data).unwrap();
+            assert_eq!(ipmb::encode(&old).unwrap(), data);
+
+            let decoded = IpmbMessage::decode(IpmbMessage::UUID, &data).unwrap().inner;
+            assert_eq!(decoded.metadata.retain, retain);
+            assert!(decoded.metadata.headers.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_headers_and_schema_version_travel_in_the_extension() {
+        for retain in [false, true] {
+            let mut msg = message(retain);
+            msg.metadata
+                .headers
+                .insert("tenant".to_string(), "acme".to_string());
+            msg.metadata.schema_version = 2;
+            let data = IpmbMessage { inner: msg.clone() }.encode().unwrap();
+
+            // Older peers read the message and ignore the trailer
+            let old: OldMessage = ipmb::decode(&data).unwrap();
+            assert_eq!(old.payload, vec![1, 2, 3]);
+            assert_eq!(old.metadata.retain, retain);
+
+            let decoded = round_trip(msg);
+            assert_eq!(decoded.header("tenant"), Some("acme"));
+            assert_eq!(decoded.metadata.schema_version, 2);
+            assert_eq!(decoded.metadata.retain, retain);
+            assert_eq!(decoded.payload, vec![1, 2, 3]);
+        }
+    }
+
+    #[test]
+    fn test_truncated_extension_is_an_error() {
+        let mut msg = message(false);
+        msg.metadata
+            .headers
+            .insert("tenant".to_string(), "acme".to_string());
+        let data = IpmbMessage { inner: msg }.encode().unwrap();
+
+        // Length pointing before the start of the message
+        let mut corrupted = data.clone();
+        let len = corrupted.len();
+        corrupted[len - 5..len - 1].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(IpmbMessage::decode(IpmbMessage::UUID, &corrupted).is_err());
+
+        // Extension cut short
+        let mut corrupted = data;
+        let len = corrupted.len();
+        corrupted[len - 5..len - 1].copy_from_slice(&1u32.to_le_bytes());
+        assert!(IpmbMessage::decode(IpmbMessage::UUID, &corrupted).is_err());
+        assert!(IpmbMessage::decode(IpmbMessage::UUID, &[EXTENSION_FORMAT]).is_err());
+    }
+}
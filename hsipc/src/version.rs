@@ -0,0 +1,166 @@
+//! Event schema versions and upcasting
+//!
+//! Event payloads are always plain bincode. An event type with a non-zero
+//! [`Event::VERSION`] is published with that version in
+//! [`MessageMetadata::schema_version`](crate::message::MessageMetadata::schema_version),
+//! which travels in the message extension next to the headers and is handed
+//! to subscribers in their [`EventContext`](crate::EventContext).
+//!
+//! Subscribers decode payloads of their own version directly. Payloads of any
+//! other version go through an upcaster registered for that version, which
+//! decodes the old layout and converts it into the current type.
+
+use crate::{Error, Event, Result};
+use serde::de::DeserializeOwned;
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+type UpcastFn<E> = Arc<dyn Fn(&[u8]) -> Result<E> + Send + Sync>;
+
+/// Upcasters from older event versions, keyed by event type and version
+#[derive(Default)]
+pub(crate) struct Upcasters {
+    upcasters: RwLock<HashMap<(TypeId, u32), Box<dyn Any + Send + Sync>>>,
+}
+
+impl Upcasters {
+    /// Register a conversion from payloads of `from_version`, laid out as `Old`
+    pub fn register<E, Old, F>(&self, from_version: u32, upcast: F) -> Result<()>
+    where
+        E: Event,
+        Old: DeserializeOwned + 'static,
+        F: Fn(Old) -> E + Send + Sync + 'static,
+    {
+        if from_version == E::VERSION {
+            return Err(Error::configuration(
+                format!(
+                    "cannot upcast {} from its current version {from_version}",
+                    std::any::type_name::<E>()
+                ),
+                Some("from_version".to_string()),
+            ));
+        }
+
+        let upcast: UpcastFn<E> =
+            Arc::new(move |body| Ok(upcast(bincode::deserialize::<Old>(body)?)));
+        self.upcasters
+            .write()
+            .unwrap()
+            .insert((TypeId::of::<E>(), from_version), Box::new(upcast));
+        Ok(())
+    }
+
+    fn get<E: Event>(&self, version: u32) -> Option<UpcastFn<E>> {
+        self.upcasters
+            .read()
+            .unwrap()
+            .get(&(TypeId::of::<E>(), version))
+            .and_then(|upcast| upcast.downcast_ref::<UpcastFn<E>>())
+            .cloned()
+    }
+
+    /// Decode a payload published with schema version `version`
+    ///
+    /// Version 0 payloads, published without a version, are decoded directly
+    /// unless a version 0 upcaster is registered.
+    pub fn decode<E: Event>(&self, version: u32, payload: &[u8]) -> Result<E> {
+        if version == E::VERSION {
+            return Ok(bincode::deserialize(payload)?);
+        }
+
+        match self.get::<E>(version) {
+            Some(upcast) => upcast(payload),
+            None if version == 0 => Ok(bincode::deserialize(payload)?),
+            None => Err(Error::serialization_msg(format!(
+                "no upcaster from version {version} to {} version {}",
+                std::any::type_name::<E>(),
+                E::VERSION
+            ))),
+        }
+    }
+}
+
+impl std::fmt::Debug for Upcasters {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Upcasters")
+            .field("count", &self.upcasters.read().unwrap().len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct ReadingV1 {
+        celsius: i32,
+    }
+
+    impl Event for ReadingV1 {
+        fn topic(&self) -> String {
+            "version/reading".to_string()
+        }
+    }
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct Reading {
+        value: f64,
+        unit: String,
+    }
+
+    impl Event for Reading {
+        const VERSION: u32 = 2;
+
+        fn topic(&self) -> String {
+            "version/reading".to_string()
+        }
+    }
+
+    fn reading() -> Reading {
+        Reading {
+            value: 21.5,
+            unit: "C".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_current_version_round_trips() {
+        let payload = bincode::serialize(&reading()).unwrap();
+        assert_eq!(
+            Upcasters::default().decode::<Reading>(2, &payload).unwrap(),
+            reading()
+        );
+    }
+
+    #[test]
+    fn test_old_versions_are_upcast() {
+        let upcasters = Upcasters::default();
+        upcasters
+            .register(0, |old: ReadingV1| Reading {
+                value: old.celsius as f64,
+                unit: "C".to_string(),
+            })
+            .unwrap();
+
+        let old = bincode::serialize(&ReadingV1 { celsius: 20 }).unwrap();
+        assert_eq!(
+            upcasters.decode::<Reading>(0, &old).unwrap(),
+            Reading {
+                value: 20.0,
+                unit: "C".to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_unknown_version_is_rejected() {
+        let payload = bincode::serialize(&reading()).unwrap();
+
+        let upcasters = Upcasters::default();
+        assert!(upcasters.decode::<Reading>(3, &payload).is_err());
+        assert!(upcasters.register(2, |old: Reading| old).is_err());
+    }
+}